@@ -0,0 +1,114 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compares the per-call cost of the SMBus methods, one `I2C_SMBUS` ioctl
+//! each, with the same accesses done as a write-read `transfer`, one
+//! `I2C_RDWR` ioctl.
+//!
+//! Only the public API is used.  By default the accesses go to `/dev/null`
+//! through a `LinuxI2CBus`, where the first ioctl fails right away, so the
+//! numbers only show the overhead of one call and one syscall: the SMBus
+//! methods of the bus select the address first, which is the ioctl failing
+//! there, so their marshaling is not timed.  Set `I2CDEV_BENCH_BUS` to a bus
+//! with a device at 0x48 to compare real accesses through a
+//! `LinuxI2CDevice` instead.
+
+extern crate i2cdev;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod bench {
+    use i2cdev::core::{I2CDevice, I2CMessage, I2CSmbus, I2CTransfer, SMBUS_BLOCK_MAX};
+    use i2cdev::linux::{LinuxI2CBus, LinuxI2CDevice, LinuxI2CMessage};
+    use std::time::Instant;
+
+    pub const ADDR: u16 = 0x48;
+    const REGISTER: u8 = 0x00;
+    const ITERATIONS: u32 = 100_000;
+
+    fn bench<F: FnMut()>(name: &str, mut f: F) {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            f();
+        }
+        println!("{:<28} {:?} per call", name, start.elapsed() / ITERATIONS);
+    }
+
+    pub fn device(dev: &mut LinuxI2CDevice) {
+        println!("{} iterations", ITERATIONS);
+        bench("smbus_read_word_data", || {
+            let _ = dev.smbus_read_word_data(REGISTER);
+        });
+        bench("transfer write-read word", || {
+            let mut data = [0; 2];
+            let _ = dev.transfer(&mut [
+                LinuxI2CMessage::write(&[REGISTER]),
+                LinuxI2CMessage::read(&mut data),
+            ]);
+        });
+        bench("smbus_read_block_data_into", || {
+            let mut buf = [0; SMBUS_BLOCK_MAX];
+            let _ = dev.smbus_read_block_data_into(REGISTER, &mut buf);
+        });
+        bench("transfer RECV_LEN block", || {
+            let mut data = [0; SMBUS_BLOCK_MAX + 1];
+            let _ = dev.transfer(&mut [
+                LinuxI2CMessage::write(&[REGISTER]),
+                LinuxI2CMessage::read_with_recv_len(&mut data).unwrap(),
+            ]);
+        });
+    }
+
+    pub fn bus(bus: &mut LinuxI2CBus) {
+        println!("{} iterations", ITERATIONS);
+        bench("smbus_read_word_data", || {
+            let _ = bus.smbus_read_word_data(ADDR, REGISTER);
+        });
+        bench("transfer write-read word", || {
+            let mut data = [0; 2];
+            let _ = bus.transfer(&mut [
+                LinuxI2CMessage::write(&[REGISTER]).with_address(ADDR),
+                LinuxI2CMessage::read(&mut data).with_address(ADDR),
+            ]);
+        });
+        bench("smbus_read_block_data_into", || {
+            let mut buf = [0; SMBUS_BLOCK_MAX];
+            let _ = bus.smbus_read_block_data_into(ADDR, REGISTER, &mut buf);
+        });
+        bench("transfer RECV_LEN block", || {
+            let mut data = [0; SMBUS_BLOCK_MAX + 1];
+            let _ = bus.transfer(&mut [
+                LinuxI2CMessage::write(&[REGISTER]).with_address(ADDR),
+                LinuxI2CMessage::read_with_recv_len(&mut data)
+                    .unwrap()
+                    .with_address(ADDR),
+            ]);
+        });
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn main() {
+    use i2cdev::linux::{LinuxI2CBus, LinuxI2CDevice};
+    use std::env;
+
+    match env::var("I2CDEV_BENCH_BUS") {
+        Ok(path) => {
+            print!("{}: ", path);
+            let mut dev = LinuxI2CDevice::new(&path, bench::ADDR).unwrap();
+            bench::device(&mut dev);
+        }
+        Err(_) => {
+            print!("/dev/null: ");
+            let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+            bench::bus(&mut bus);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn main() {}
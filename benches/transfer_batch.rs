@@ -0,0 +1,58 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compares the per-call cost of building a fresh message array for every
+//! transfer with issuing a prebuilt `TransferBatch`.
+//!
+//! By default the transfers go to `/dev/null`, where the ioctl fails right
+//! away, so the numbers are dominated by the setup work and one syscall.
+//! Set `I2CDEV_BENCH_BUS` to a bus with a device at 0x48 to measure real
+//! transfers instead.
+
+extern crate i2cdev;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn main() {
+    use i2cdev::core::{I2CMessage, I2CTransfer};
+    use i2cdev::linux::{LinuxI2CBus, LinuxI2CMessage, TransferBatch};
+    use std::env;
+    use std::time::Instant;
+
+    const ADDR: u16 = 0x48;
+    const ITERATIONS: u32 = 100_000;
+
+    let path = env::var("I2CDEV_BENCH_BUS").unwrap_or_else(|_| "/dev/null".to_string());
+    let mut bus = LinuxI2CBus::new(&path).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut data = [0; 6];
+        let mut msgs = [
+            LinuxI2CMessage::write(&[0x28]).with_address(ADDR),
+            LinuxI2CMessage::read(&mut data).with_address(ADDR),
+        ];
+        let _ = bus.transfer(&mut msgs);
+    }
+    let messages = start.elapsed() / ITERATIONS;
+
+    let mut batch = TransferBatch::new();
+    batch.push_write(ADDR, &[0x28]);
+    batch.push_read(ADDR, 6);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let _ = batch.execute(&mut bus);
+    }
+    let batched = start.elapsed() / ITERATIONS;
+
+    println!("{}: {} iterations", path, ITERATIONS);
+    println!("messages built per call: {:?} per transfer", messages);
+    println!("TransferBatch:           {:?} per transfer", batched);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn main() {}
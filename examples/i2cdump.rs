@@ -0,0 +1,150 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate docopt;
+extern crate i2cdev;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::core::I2CDevice;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::linux::LinuxI2CDevice;
+
+use docopt::Docopt;
+use std::convert::TryFrom;
+use std::env::args;
+use std::fmt::Write;
+
+const USAGE: &str = "
+Dumps the byte registers of a device, like i2cdump.
+
+Registers are read one at a time with SMBus read byte data commands.
+Registers which cannot be read are shown as --.
+
+Usage:
+  i2cdump <device> <address> [<start> <end>]
+  i2cdump (-h | --help)
+  i2cdump --version
+
+Options:
+  -h --help    Show this help text.
+  --version    Show version.
+";
+
+/// Format the registers `start` to `end` as a grid of 16 registers per
+/// line, with their printable characters at the end of the line
+///
+/// `read` returns the value of a register, or `None` if it cannot be read.
+fn format_dump<F>(start: u8, end: u8, mut read: F) -> String
+where
+    F: FnMut(u8) -> Option<u8>,
+{
+    let mut out = String::from("   ");
+    for column in 0..16 {
+        write!(out, "  {:x}", column).unwrap();
+    }
+    out.push_str("    0123456789abcdef\n");
+
+    for row in (start >> 4)..=(end >> 4) {
+        let mut hex = String::new();
+        let mut chars = String::new();
+        for column in 0..16 {
+            let register = row << 4 | column;
+            if register < start || register > end {
+                hex.push_str("   ");
+                chars.push(' ');
+                continue;
+            }
+            match read(register) {
+                Some(value) => {
+                    write!(hex, " {:02x}", value).unwrap();
+                    chars.push(if value.is_ascii_graphic() || value == b' ' {
+                        value as char
+                    } else {
+                        '.'
+                    });
+                }
+                None => {
+                    hex.push_str(" --");
+                    chars.push('-');
+                }
+            }
+        }
+        writeln!(out, "{:02x}:{}    {}", row << 4, hex, chars.trim_end()).unwrap();
+    }
+    out
+}
+
+fn parse_hex(arg: &str) -> u16 {
+    u16::from_str_radix(arg.trim_start_matches("0x"), 16)
+        .unwrap_or_else(|_| panic!("invalid hexadecimal number {}", arg))
+}
+
+fn parse_register(arg: &str) -> u8 {
+    u8::try_from(parse_hex(arg)).unwrap_or_else(|_| panic!("invalid register {}", arg))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn main() {}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn main() {
+    let args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(args()).parse())
+        .unwrap_or_else(|e| e.exit());
+    let path = args.get_str("<device>");
+    let address = parse_hex(args.get_str("<address>"));
+    let (start, end) = if args.get_str("<start>").is_empty() {
+        (0x00, 0xFF)
+    } else {
+        (
+            parse_register(args.get_str("<start>")),
+            parse_register(args.get_str("<end>")),
+        )
+    };
+    if start > end {
+        eprintln!("start register 0x{:02x} is after end 0x{:02x}", start, end);
+        std::process::exit(1);
+    }
+
+    let mut dev = LinuxI2CDevice::new(path, address).unwrap();
+    let dump = format_dump(start, end, |register| {
+        dev.smbus_read_byte_data(register).ok()
+    });
+    print!("{}", dump);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_dump() {
+        let dump = format_dump(0x0E, 0x11, |register| match register {
+            0x0F => None,
+            register => Some(register + 0x30),
+        });
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f    0123456789abcdef",
+                "00:                                           3e --                  >-",
+                "10: 40 41                                              @A",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_full_range() {
+        let dump = format_dump(0x00, 0xFF, Some);
+        assert_eq!(dump.lines().count(), 17);
+        assert!(dump.ends_with(
+            "f0: f0 f1 f2 f3 f4 f5 f6 f7 f8 f9 fa fb fc fd fe ff    ................\n"
+        ));
+    }
+}
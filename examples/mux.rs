@@ -0,0 +1,74 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate docopt;
+extern crate i2cdev;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::core::{I2CMessage, I2CTransfer};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::linux::{LinuxI2CBus, LinuxI2CMessage};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::mux::{Tca9548a, TCA9548A_DEFAULT_ADDR};
+
+use docopt::Docopt;
+use std::env::args;
+
+const USAGE: &str = "
+Reads a register of a device behind a TCA9548A I2C multiplexer.
+
+Assumes the multiplexer is using address 0x70.
+
+Usage:
+  mux <device> <channel> <address> <register>
+  mux (-h | --help)
+  mux --version
+
+Options:
+  -h --help    Show this help text.
+  --version    Show version.
+";
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn main() {}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn main() {
+    let args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(args()).parse())
+        .unwrap_or_else(|e| e.exit());
+    let path = args.get_str("<device>");
+    let channel: u8 = args.get_str("<channel>").parse().unwrap();
+    let address =
+        u16::from_str_radix(args.get_str("<address>").trim_start_matches("0x"), 16).unwrap();
+    let register =
+        u8::from_str_radix(args.get_str("<register>").trim_start_matches("0x"), 16).unwrap();
+
+    let bus = LinuxI2CBus::new(path).unwrap();
+    let mut mux = Tca9548a::new(bus, TCA9548A_DEFAULT_ADDR);
+
+    // route the bus to the downstream device
+    mux.select_channel(channel).unwrap();
+
+    // write the register address, then read the register with a repeated
+    // start
+    let register = [register];
+    let mut data = [0; 1];
+    let mut msgs = [
+        LinuxI2CMessage::write(&register).with_address(address),
+        LinuxI2CMessage::read(&mut data).with_address(address),
+    ];
+    mux.bus().transfer(&mut msgs).unwrap();
+    println!(
+        "Channel {} device 0x{:02x} register 0x{:02x}: 0x{:02x}",
+        channel, address, register[0], data[0]
+    );
+
+    // leave the downstream buses isolated for other users of the bus
+    mux.disable_all().unwrap();
+}
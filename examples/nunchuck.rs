@@ -10,8 +10,9 @@
 //
 // NOTE: This code is provided as an example.  Driver developers are encouraged
 // to use the embedded-hal traits if possible rather than coupling directly
-// to this library.  An implementation of the embedded-hal I2C traits based
-// on this library may be found in the embedded-hal-linux project.
+// to this library.  With the `embedded-hal` feature enabled, `LinuxI2CDevice`
+// implements the embedded-hal 1.0 `I2c` trait and can be handed straight to
+// such drivers.
 
 extern crate docopt;
 extern crate i2cdev;
@@ -57,6 +58,8 @@ mod nunchuck {
     // TODO: Move Nunchuck code out to be an actual sensor and add tests
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[allow(dead_code)] // fields are only read through Debug
     pub struct NunchuckReading {
         pub joystick_x: u8,
         pub joystick_y: u8,
@@ -149,8 +152,8 @@ mod nunchuck {
 
             // these values must be written out for things to work
             let mut dev = Nunchuck::new(i2cdev).unwrap();
-            assert_eq!(dev.get_i2cdev().smbus_read_byte_data(0xF0).unwrap(), 0x55);
-            assert_eq!(dev.get_i2cdev().smbus_read_byte_data(0xFB).unwrap(), 0x00);
+            assert_eq!(dev.get_i2cdev().register(0xF0), 0x55);
+            assert_eq!(dev.get_i2cdev().register(0xFB), 0x00);
         }
 
         #[test]
@@ -184,7 +187,7 @@ mod nunchuck {
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use nunchuck::*;
+use crate::nunchuck::*;
 
 use docopt::Docopt;
 use std::env::args;
@@ -10,28 +10,95 @@
 // - Analog Devices ADXL345 Digital Accelerometer
 // - Freescale MPL115A2 Digital Barometer/Thermometer
 // - Bosch BNO055 9-Axis O Absolute Orientation Sensor
+//
+// Drivers for the ST L3G family of gyroscopes and the Bosch BMP280/BME280
+// barometers are included as well.
+//
+// The drivers only rely on the `I2CDevice` trait, so they and their tests
+// build on any host against `MockI2CDevice`; only `main` needs Linux.
 
 #![allow(dead_code)] // register map
 
-extern crate byteorder;
 extern crate docopt;
 extern crate i2cdev;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::sensors::adxl345_accelerometer::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::sensors::mpl115a2_barometer::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::sensors::{Accelerometer, Barometer, Thermometer};
+#[cfg(any(target_os = "linux", target_os = "android"))]
 use docopt::Docopt;
-use sensors::adxl345_accelerometer::*;
-use sensors::mpl115a2_barometer::*;
-use sensors::{Accelerometer, Barometer, Thermometer};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use i2cdev::linux::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
 use std::env::args;
+#[cfg(any(target_os = "linux", target_os = "android"))]
 use std::thread;
-use std::time::Duration;
-
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use i2cdev::linux::*;
+use std::time::Duration;
 
 mod sensors {
+    use i2cdev::core::{u16_from_be, u16_from_le};
     use std::error::Error;
+    use std::fmt;
+
+    /// Errors of the sensor drivers which can fail in other ways than the
+    /// underlying device
+    #[derive(Debug)]
+    pub enum SensorError<E> {
+        /// Error from the underlying device
+        Bus(E),
+        /// The checksum sent with a measurement does not match its data
+        CrcMismatch,
+        /// The data read from the sensor is not valid
+        Parse,
+        /// The sensor cannot make the requested measurement, such as
+        /// humidity on a BMP280
+        Unsupported,
+        /// The chip ID is not that of a chip the driver supports
+        UnknownChip(u8),
+        /// The measurement saturated, so the sensor has to be set up for a
+        /// larger range
+        Saturated,
+        /// The sensor did not finish the measurement in time
+        Timeout,
+    }
+
+    impl<E: Error> fmt::Display for SensorError<E> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                SensorError::Bus(ref e) => write!(f, "Sensor bus error: {}", e),
+                SensorError::CrcMismatch => write!(f, "Measurement CRC mismatch"),
+                SensorError::Parse => write!(f, "Could not parse sensor data"),
+                SensorError::Unsupported => {
+                    write!(f, "Measurement not supported by this sensor")
+                }
+                SensorError::UnknownChip(id) => write!(f, "Unknown chip ID 0x{:02X}", id),
+                SensorError::Saturated => write!(f, "Sensor saturated"),
+                SensorError::Timeout => write!(f, "Sensor measurement timed out"),
+            }
+        }
+    }
+
+    impl<E: Error> Error for SensorError<E> {
+        fn cause(&self) -> Option<&dyn Error> {
+            match *self {
+                SensorError::Bus(ref e) => Some(e),
+                _ => None,
+            }
+        }
+    }
+
+    impl<E> From<E> for SensorError<E> {
+        fn from(e: E) -> SensorError<E> {
+            SensorError::Bus(e)
+        }
+    }
 
     #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct AccelerometerSample {
         /// x-axis G's
         pub x: f32,
@@ -49,6 +116,25 @@ mod sensors {
         fn accelerometer_sample(&mut self) -> Result<AccelerometerSample, Self::Error>;
     }
 
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct GyroscopeSample {
+        /// x-axis rate, in degrees per second
+        pub x: f32,
+        /// y-axis rate, in degrees per second
+        pub y: f32,
+        /// z-axis rate, in degrees per second
+        pub z: f32,
+    }
+
+    /// Trait for sensors that provide access to gyroscope readings (3-axis)
+    pub trait Gyroscope {
+        type Error: Error;
+
+        /// Grab a gyroscope sample from the device
+        fn gyroscope_sample(&mut self) -> Result<GyroscopeSample, Self::Error>;
+    }
+
     /// Trait for sensors that provide access to temperature readings
     pub trait Thermometer {
         type Error: Error;
@@ -71,6 +157,28 @@ mod sensors {
         fn pressure_kpa(&mut self) -> Result<f32, Self::Error>;
     }
 
+    /// Trait for sensors that provide access to relative humidity readings
+    pub trait Hygrometer {
+        type Error: Error;
+
+        /// Get a relative humidity reading from the sensor in percent
+        ///
+        /// Returns `Ok(humidity)` if available, otherwise returns
+        /// `Err(Self::Error)`
+        fn relative_humidity(&mut self) -> Result<f32, Self::Error>;
+    }
+
+    /// Trait for sensors that provide access to ambient light readings
+    pub trait Photometer {
+        type Error: Error;
+
+        /// Get an illuminance reading from the sensor in lux
+        ///
+        /// Returns `Ok(lux)` if available, otherwise returns
+        /// `Err(Self::Error)`
+        fn lux(&mut self) -> Result<f32, Self::Error>;
+    }
+
     /// Trait for sensors that provide access to altitude readings
     pub trait Altimeter {
         type Error: Error;
@@ -99,9 +207,50 @@ mod sensors {
         }
     }
 
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io;
+
+        #[test]
+        fn test_sensor_error_display() {
+            let e: SensorError<io::Error> = io::Error::new(io::ErrorKind::TimedOut, "NAK").into();
+            assert_eq!(e.to_string(), "Sensor bus error: NAK");
+            #[allow(deprecated)]
+            let cause = e.cause().map(|e| e.to_string());
+            assert_eq!(cause.as_deref(), Some("NAK"));
+
+            let e = SensorError::<io::Error>::UnknownChip(0x55);
+            assert_eq!(e.to_string(), "Unknown chip ID 0x55");
+            #[allow(deprecated)]
+            let cause = e.cause();
+            assert!(cause.is_none());
+        }
+    }
+
+    #[cfg(all(test, feature = "serde"))]
+    mod serde_tests {
+        use super::*;
+
+        #[test]
+        fn test_sample_json_round_trip() {
+            let sample = AccelerometerSample {
+                x: 0.5,
+                y: -1.25,
+                z: 9.75,
+            };
+            let json = serde_json::to_string(&sample).unwrap();
+            assert_eq!(json, r#"{"x":0.5,"y":-1.25,"z":9.75}"#);
+            let parsed: AccelerometerSample = serde_json::from_str(&json).unwrap();
+            assert_eq!((parsed.x, parsed.y, parsed.z), (0.5, -1.25, 9.75));
+
+            let parsed: GyroscopeSample = serde_json::from_str(&json).unwrap();
+            assert_eq!((parsed.x, parsed.y, parsed.z), (0.5, -1.25, 9.75));
+        }
+    }
+
     pub mod adxl345_accelerometer {
         use super::*;
-        use byteorder::{ByteOrder, LittleEndian};
         use i2cdev::core::I2CDevice;
 
         // TODO: read/write data format (for now, assumed 0x00)
@@ -142,7 +291,8 @@ mod sensors {
 
         #[repr(u8)]
         #[allow(non_camel_case_types)]
-        enum ADXL345DataRate {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ADXL345DataRate {
             RATE_3200HZ = 0x0F,
             RATE_1600HZ = 0x0E,
             RATE_800HZ = 0x0D,
@@ -155,6 +305,49 @@ mod sensors {
             RATE_6HZ25 = 0x06,
         }
 
+        /// Measurement range, in G's
+        #[repr(u8)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ADXL345Range {
+            G2 = 0x00,
+            G4 = 0x01,
+            G8 = 0x02,
+            G16 = 0x03,
+        }
+
+        /// Settings applied when creating an `ADXL345Accelerometer`
+        ///
+        /// The default configuration is the one that used to be hard-coded:
+        /// a 1600Hz data rate, the +-2G range and offsets of -3, 3 and -2
+        /// on the x, y and z axes.  The device is always used in full
+        /// resolution mode, which keeps the scale factor independent of the
+        /// range.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct ADXL345Config {
+            pub data_rate: ADXL345DataRate,
+            pub range: ADXL345Range,
+            /// x-axis offset, in steps of 15.6 mG
+            pub offset_x: i8,
+            /// y-axis offset, in steps of 15.6 mG
+            pub offset_y: i8,
+            /// z-axis offset, in steps of 15.6 mG
+            pub offset_z: i8,
+        }
+
+        impl Default for ADXL345Config {
+            fn default() -> Self {
+                ADXL345Config {
+                    data_rate: ADXL345DataRate::RATE_1600HZ,
+                    range: ADXL345Range::G2,
+                    offset_x: -3,
+                    offset_y: 3,
+                    offset_z: -2,
+                }
+            }
+        }
+
+        const DATA_FORMAT_FULL_RES: u8 = 0x08;
+
         pub struct ADXL345Accelerometer<T: I2CDevice + Sized> {
             i2cdev: T,
         }
@@ -168,17 +361,21 @@ mod sensors {
             /// The `SLAVE_ADDR_*` constants from this module should be
             /// used to select either the primary or alternative slave
             /// address (dependent on `ALT ADDRESS` pin)
-            pub fn new(mut i2cdev: T) -> Result<ADXL345Accelerometer<T>, T::Error> {
+            pub fn new(
+                mut i2cdev: T,
+                config: ADXL345Config,
+            ) -> Result<ADXL345Accelerometer<T>, T::Error> {
                 // setup standy mode to configure
                 i2cdev.smbus_write_byte_data(REGISTER_POWER_CTL, 0x00)?;
 
-                // configure some defaults
-                i2cdev
-                    .smbus_write_byte_data(REGISTER_BW_RATE, ADXL345DataRate::RATE_1600HZ as u8)?;
-                i2cdev.smbus_write_byte_data(REGISTER_DATA_FORMAT, 0x08)?;
-                i2cdev.smbus_write_byte_data(REGISTER_OFSX, 0xFD)?;
-                i2cdev.smbus_write_byte_data(REGISTER_OFSY, 0x03)?;
-                i2cdev.smbus_write_byte_data(REGISTER_OFSZ, 0xFE)?;
+                i2cdev.smbus_write_byte_data(REGISTER_BW_RATE, config.data_rate as u8)?;
+                i2cdev.smbus_write_byte_data(
+                    REGISTER_DATA_FORMAT,
+                    DATA_FORMAT_FULL_RES | config.range as u8,
+                )?;
+                i2cdev.smbus_write_byte_data(REGISTER_OFSX, config.offset_x as u8)?;
+                i2cdev.smbus_write_byte_data(REGISTER_OFSY, config.offset_y as u8)?;
+                i2cdev.smbus_write_byte_data(REGISTER_OFSZ, config.offset_z as u8)?;
 
                 // put device in measurement mode
                 i2cdev.smbus_write_byte_data(REGISTER_POWER_CTL, 0x08)?;
@@ -198,6 +395,7 @@ mod sensors {
         impl<T> Accelerometer for ADXL345Accelerometer<T>
         where
             T: I2CDevice + Sized,
+            T::Error: Error,
         {
             type Error = T::Error;
 
@@ -208,9 +406,9 @@ mod sensors {
                 self.i2cdev.write(&[REGISTER_X0])?;
                 self.i2cdev.read(&mut buf)?;
 
-                let x: i16 = LittleEndian::read_i16(&[buf[0], buf[1]]);
-                let y: i16 = LittleEndian::read_i16(&[buf[2], buf[3]]);
-                let z: i16 = LittleEndian::read_i16(&[buf[4], buf[5]]);
+                let x = u16_from_le(buf[0], buf[1]) as i16;
+                let y = u16_from_le(buf[2], buf[3]) as i16;
+                let z = u16_from_le(buf[4], buf[5]) as i16;
                 Ok(AccelerometerSample {
                     x: (f32::from(x) / 1023.0) * (ACCEL_RANGE * 2.0),
                     y: (f32::from(y) / 1023.0) * (ACCEL_RANGE * 2.0),
@@ -218,11 +416,141 @@ mod sensors {
                 })
             }
         }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::MockI2CDevice;
+
+            #[test]
+            fn test_default_config_writes() {
+                let dev = ADXL345Accelerometer::new(MockI2CDevice::new(), Default::default());
+                let mut i2cdev = dev.unwrap().i2cdev;
+                let mut read = |reg| i2cdev.smbus_read_byte_data(reg).unwrap();
+                assert_eq!(read(REGISTER_BW_RATE), 0x0E);
+                assert_eq!(read(REGISTER_DATA_FORMAT), 0x08);
+                assert_eq!(read(REGISTER_OFSX), 0xFD);
+                assert_eq!(read(REGISTER_OFSY), 0x03);
+                assert_eq!(read(REGISTER_OFSZ), 0xFE);
+                assert_eq!(read(REGISTER_POWER_CTL), 0x08);
+            }
+
+            #[test]
+            fn test_config_range() {
+                let config = ADXL345Config {
+                    range: ADXL345Range::G8,
+                    ..Default::default()
+                };
+                let dev = ADXL345Accelerometer::new(MockI2CDevice::new(), config);
+                let mut i2cdev = dev.unwrap().i2cdev;
+                assert_eq!(
+                    i2cdev.smbus_read_byte_data(REGISTER_DATA_FORMAT).unwrap(),
+                    0x0A
+                );
+            }
+        }
+    }
+
+    pub mod l3g_gyro {
+        use super::*;
+        use i2cdev::core::{I2CDevice, I2CDeviceExt};
+
+        pub const SLAVE_ADDR_PRIMARY: u16 = 0x6B;
+        pub const SLAVE_ADDR_ALT: u16 = 0x6A;
+
+        const REGISTER_WHO_AM_I: u8 = 0x0F;
+        const REGISTER_CTRL_REG1: u8 = 0x20;
+        const REGISTER_CTRL_REG4: u8 = 0x23;
+        const REGISTER_OUT_X_L: u8 = 0x28;
+
+        // setting the top bit of the register address makes the device
+        // advance through the registers in a multi-byte read
+        const AUTO_INCREMENT: u8 = 0x80;
+
+        // normal mode with the x, y and z axes enabled
+        const CTRL_REG1_ENABLE: u8 = 0x0F;
+
+        const GYRO_SENSITIVITY: f32 = 0.00875; // degrees/s per digit at +-250 degrees/s
+
+        /// Provides access to the gyroscope of the L3G4200D, L3GD20 and
+        /// L3GD20H in the +-250 degrees/s range
+        pub struct L3GGyroscope<T: I2CDevice + Sized> {
+            i2cdev: T,
+        }
+
+        impl<T> L3GGyroscope<T>
+        where
+            T: I2CDevice + Sized,
+        {
+            /// Power up the gyroscope
+            ///
+            /// The `SLAVE_ADDR_*` constants from this module should be
+            /// used to select either the primary or alternative slave
+            /// address (dependent on the `SDO`/`SA0` pin)
+            pub fn new(mut i2cdev: T) -> Result<L3GGyroscope<T>, T::Error> {
+                i2cdev.smbus_write_byte_data(REGISTER_CTRL_REG4, 0x00)?;
+                i2cdev.smbus_write_byte_data(REGISTER_CTRL_REG1, CTRL_REG1_ENABLE)?;
+                Ok(L3GGyroscope { i2cdev })
+            }
+
+            /// Get the device id
+            pub fn device_id(&mut self) -> Result<u8, T::Error> {
+                self.i2cdev.smbus_read_byte_data(REGISTER_WHO_AM_I)
+            }
+        }
+
+        impl<T> Gyroscope for L3GGyroscope<T>
+        where
+            T: I2CDeviceExt + Sized,
+            T::Error: Error,
+        {
+            type Error = T::Error;
+
+            fn gyroscope_sample(&mut self) -> Result<GyroscopeSample, T::Error> {
+                let buf = self
+                    .i2cdev
+                    .smbus_read_i2c_block_data(AUTO_INCREMENT | REGISTER_OUT_X_L, 6)?;
+
+                let x = u16_from_le(buf[0], buf[1]) as i16;
+                let y = u16_from_le(buf[2], buf[3]) as i16;
+                let z = u16_from_le(buf[4], buf[5]) as i16;
+                Ok(GyroscopeSample {
+                    x: f32::from(x) * GYRO_SENSITIVITY,
+                    y: f32::from(y) * GYRO_SENSITIVITY,
+                    z: f32::from(z) * GYRO_SENSITIVITY,
+                })
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::MockI2CDevice;
+
+            #[test]
+            fn test_gyroscope_sample() {
+                let mut i2cdev = MockI2CDevice::new().with_auto_increment_bit(true);
+                i2cdev
+                    .regmap
+                    .write_regs(0x28, &[0xE8, 0x03, 0x18, 0xFC, 0x00, 0x00]);
+
+                let mut gyro = L3GGyroscope::new(i2cdev).unwrap();
+                let sample = gyro.gyroscope_sample().unwrap();
+                assert!((sample.x - 8.75).abs() < 0.0001);
+                assert!((sample.y + 8.75).abs() < 0.0001);
+                assert_eq!(sample.z, 0.0);
+                assert_eq!(
+                    gyro.i2cdev
+                        .smbus_read_byte_data(REGISTER_CTRL_REG1)
+                        .unwrap(),
+                    0x0F
+                );
+            }
+        }
     }
 
     pub mod mpl115a2_barometer {
         use super::*;
-        use byteorder::{BigEndian, ByteOrder};
         use i2cdev::core::I2CDevice;
         use std::error::Error;
         use std::thread;
@@ -255,6 +583,7 @@ mod sensors {
         /// One use case for use of this struct directly would be for
         /// getting both temperature and pressure in a single call.
         #[derive(Debug)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub struct MPL115A2RawReading {
             padc: u16, // 10-bit pressure ADC output value
             tadc: u16, // 10-bit pressure ADC output value
@@ -284,7 +613,7 @@ mod sensors {
         ) -> f32 {
             // If values are less than 16 bytes, need to adjust
             let extrabits = 16 - integer_bits - fractional_bits - 1;
-            let rawval: i16 = BigEndian::read_i16(&[msb, lsb]);
+            let rawval = u16_from_be(msb, lsb) as i16;
             (f32::from(rawval) / 2_f32.powi(fractional_bits + extrabits))
                 / 10_f32.powi(dec_pt_zero_pad)
         }
@@ -326,8 +655,8 @@ mod sensors {
                 let mut buf = [0_u8; 4];
                 i2cdev.write(&[REGISTER_ADDR_PADC])?;
                 i2cdev.read(&mut buf)?;
-                let padc: u16 = BigEndian::read_u16(&buf) >> 6;
-                let tadc: u16 = BigEndian::read_u16(&buf[2..]) >> 6;
+                let padc = u16_from_be(buf[0], buf[1]) >> 6;
+                let tadc = u16_from_be(buf[2], buf[3]) >> 6;
                 Ok(MPL115A2RawReading { padc, tadc })
             }
 
@@ -353,6 +682,7 @@ mod sensors {
         impl<T> MPL115A2BarometerThermometer<T>
         where
             T: I2CDevice + Sized,
+            T::Error: Error,
         {
             /// Create sensor accessor for MPL115A2 on the provided i2c bus path
             pub fn new(mut i2cdev: T) -> Result<MPL115A2BarometerThermometer<T>, T::Error> {
@@ -364,6 +694,7 @@ mod sensors {
         impl<T> Barometer for MPL115A2BarometerThermometer<T>
         where
             T: I2CDevice + Sized,
+            T::Error: Error,
         {
             type Error = T::Error;
 
@@ -376,6 +707,7 @@ mod sensors {
         impl<T> Thermometer for MPL115A2BarometerThermometer<T>
         where
             T: I2CDevice + Sized,
+            T::Error: Error,
         {
             type Error = T::Error;
 
@@ -389,8 +721,7 @@ mod sensors {
         mod tests {
             use super::calc_coefficient;
             use super::*;
-            use mock::MockI2CDevice;
-            use sensors::*;
+            use i2cdev::mock::MockI2CDevice;
 
             macro_rules! assert_almost_eq {
                 ($left:expr, $right:expr) => {{
@@ -405,7 +736,7 @@ mod sensors {
             }
 
             fn make_dev(mut i2cdev: MockI2CDevice) -> MPL115A2BarometerThermometer<MockI2CDevice> {
-                (&mut i2cdev.regmap).write_regs(
+                i2cdev.regmap.write_regs(
                     0x04,
                     &[
                         74, 98, /* A0 */
@@ -432,7 +763,7 @@ mod sensors {
             #[test]
             fn test_basic_pressure_read() {
                 let mut i2cdev = MockI2CDevice::new();
-                (&mut i2cdev.regmap).write_regs(0x00, &[0x6e, 0xc0, 0x81, 0x40]);
+                i2cdev.regmap.write_regs(0x00, &[0x6e, 0xc0, 0x81, 0x40]);
 
                 let mut dev = make_dev(i2cdev);
                 assert_almost_eq!(dev.pressure_kpa().unwrap(), 83.93877);
@@ -441,13 +772,1014 @@ mod sensors {
             #[test]
             fn test_basic_temp_read() {
                 let mut i2cdev = MockI2CDevice::new();
-                (&mut i2cdev.regmap).write_regs(0, &[0x6e, 0xc0, 0x81, 0x40]);
+                i2cdev.regmap.write_regs(0, &[0x6e, 0xc0, 0x81, 0x40]);
 
                 let mut dev = make_dev(i2cdev);
                 assert_almost_eq!(dev.temperature_celsius().unwrap(), 21.448599);
             }
         }
     }
+
+    pub mod bme280 {
+        use super::*;
+        use i2cdev::core::I2CDevice;
+        use std::error::Error;
+
+        pub const SLAVE_ADDR_PRIMARY: u16 = 0x76;
+        pub const SLAVE_ADDR_ALT: u16 = 0x77;
+
+        const REGISTER_CALIB_00: u8 = 0x88; // 24 bytes of temperature and pressure trimming
+        const REGISTER_CALIB_H1: u8 = 0xA1;
+        const REGISTER_CHIP_ID: u8 = 0xD0;
+        const REGISTER_CALIB_H2: u8 = 0xE1; // 7 bytes of humidity trimming
+        const REGISTER_CTRL_HUM: u8 = 0xF2;
+        const REGISTER_CTRL_MEAS: u8 = 0xF4;
+        const REGISTER_PRESS_MSB: u8 = 0xF7; // pressure, temperature, humidity follow
+
+        const CHIP_ID_BME280: u8 = 0x60;
+        const CHIP_IDS_BMP280: [u8; 3] = [0x56, 0x57, 0x58];
+
+        // oversampling x1 for each measurement, normal mode
+        const CTRL_HUM_DEFAULT: u8 = 0b001;
+        const CTRL_MEAS_DEFAULT: u8 = (0b001 << 5) | (0b001 << 2) | 0b11;
+
+        /// The trimming parameters programmed into each chip, which are
+        /// needed to compensate the raw readings
+        ///
+        /// The humidity parameters are only present on a BME280.
+        #[derive(Debug, Default)]
+        pub struct BME280Calibration {
+            t1: f64,
+            t2: f64,
+            t3: f64,
+            p: [f64; 9],
+            h: Option<[f64; 6]>,
+        }
+
+        impl BME280Calibration {
+            /// Read the trimming parameters from the device
+            pub fn new<E: Error>(
+                i2cdev: &mut dyn I2CDevice<Error = E>,
+                humidity: bool,
+            ) -> Result<BME280Calibration, E> {
+                let mut buf = [0_u8; 24];
+                i2cdev.write(&[REGISTER_CALIB_00])?;
+                i2cdev.read(&mut buf)?;
+                let mut p = [0.; 9];
+                p[0] = f64::from(u16_from_le(buf[6], buf[7]));
+                for (i, value) in p.iter_mut().enumerate().skip(1) {
+                    *value = f64::from(u16_from_le(buf[6 + 2 * i], buf[7 + 2 * i]) as i16);
+                }
+                let h = if humidity {
+                    let h1 = i2cdev.smbus_read_byte_data(REGISTER_CALIB_H1)?;
+                    let mut e = [0_u8; 7];
+                    i2cdev.write(&[REGISTER_CALIB_H2])?;
+                    i2cdev.read(&mut e)?;
+                    // H4 and H5 are 12-bit signed values sharing a nibble
+                    let h4 = (i16::from(e[3] as i8) << 4) | i16::from(e[4] & 0x0F);
+                    let h5 = (i16::from(e[5] as i8) << 4) | i16::from(e[4] >> 4);
+                    Some([
+                        f64::from(h1),
+                        f64::from(u16_from_le(e[0], e[1]) as i16),
+                        f64::from(e[2]),
+                        f64::from(h4),
+                        f64::from(h5),
+                        f64::from(e[6] as i8),
+                    ])
+                } else {
+                    None
+                };
+                Ok(BME280Calibration {
+                    t1: f64::from(u16_from_le(buf[0], buf[1])),
+                    t2: f64::from(u16_from_le(buf[2], buf[3]) as i16),
+                    t3: f64::from(u16_from_le(buf[4], buf[5]) as i16),
+                    p,
+                    h,
+                })
+            }
+        }
+
+        /// Uncompensated ADC values of one measurement
+        #[derive(Debug)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct BME280RawReading {
+            adc_p: i32, // 20 bits
+            adc_t: i32, // 20 bits
+            adc_h: i32, // 16 bits, BME280 only
+        }
+
+        impl BME280RawReading {
+            /// Read the most recent measurement from the device
+            pub fn new<E: Error>(
+                i2cdev: &mut dyn I2CDevice<Error = E>,
+                humidity: bool,
+            ) -> Result<BME280RawReading, E> {
+                let mut buf = [0_u8; 8];
+                let len = if humidity { 8 } else { 6 };
+                i2cdev.write(&[REGISTER_PRESS_MSB])?;
+                i2cdev.read(&mut buf[..len])?;
+                let adc20 = |b: &[u8]| {
+                    (i32::from(b[0]) << 12) | (i32::from(b[1]) << 4) | (i32::from(b[2]) >> 4)
+                };
+                Ok(BME280RawReading {
+                    adc_p: adc20(&buf[0..3]),
+                    adc_t: adc20(&buf[3..6]),
+                    adc_h: i32::from(u16_from_be(buf[6], buf[7])),
+                })
+            }
+
+            /// The fine temperature used by the pressure and humidity
+            /// compensation
+            fn t_fine(&self, cal: &BME280Calibration) -> f64 {
+                let adc_t = f64::from(self.adc_t);
+                let var1 = (adc_t / 16384.0 - cal.t1 / 1024.0) * cal.t2;
+                let var2 = (adc_t / 131072.0 - cal.t1 / 8192.0).powi(2) * cal.t3;
+                var1 + var2
+            }
+
+            /// Calculate the temperature in degrees celsius for this reading
+            pub fn temperature_celsius(&self, cal: &BME280Calibration) -> f32 {
+                (self.t_fine(cal) / 5120.0) as f32
+            }
+
+            /// Calculate the pressure in kPa for this reading
+            pub fn pressure_kpa(&self, cal: &BME280Calibration) -> f32 {
+                let p = &cal.p;
+                let mut var1 = self.t_fine(cal) / 2.0 - 64000.0;
+                let mut var2 = var1 * var1 * p[5] / 32768.0;
+                var2 += var1 * p[4] * 2.0;
+                var2 = var2 / 4.0 + p[3] * 65536.0;
+                var1 = (p[2] * var1 * var1 / 524288.0 + p[1] * var1) / 524288.0;
+                var1 = (1.0 + var1 / 32768.0) * p[0];
+                if var1 == 0.0 {
+                    // avoid dividing by zero on a chip without calibration
+                    return 0.0;
+                }
+                let mut pa = 1048576.0 - f64::from(self.adc_p);
+                pa = (pa - var2 / 4096.0) * 6250.0 / var1;
+                var1 = p[8] * pa * pa / 2147483648.0;
+                var2 = pa * p[7] / 32768.0;
+                pa += (var1 + var2 + p[6]) / 16.0;
+                (pa / 1000.0) as f32
+            }
+
+            /// Calculate the relative humidity in percent for this reading,
+            /// if the calibration has humidity parameters
+            pub fn relative_humidity(&self, cal: &BME280Calibration) -> Option<f32> {
+                let h = cal.h.as_ref()?;
+                let mut hum = self.t_fine(cal) - 76800.0;
+                hum = (f64::from(self.adc_h) - (h[3] * 64.0 + h[4] / 16384.0 * hum))
+                    * (h[1] / 65536.0
+                        * (1.0 + h[5] / 67108864.0 * hum * (1.0 + h[2] / 67108864.0 * hum)));
+                hum *= 1.0 - h[0] * hum / 524288.0;
+                Some(hum.clamp(0.0, 100.0) as f32)
+            }
+        }
+
+        /// Provides access to the Bosch BMP280 pressure and temperature
+        /// sensor, and to the BME280 which adds a humidity sensor
+        ///
+        /// https://www.bosch-sensortec.com/media/boschsensortec/downloads/datasheets/bst-bme280-ds002.pdf
+        pub struct BME280<T: I2CDevice + Sized> {
+            pub i2cdev: T,
+            pub calibration: BME280Calibration,
+            chip_id: u8,
+        }
+
+        impl<T> BME280<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            /// Identify the chip, read its calibration and start continuous
+            /// measurements
+            pub fn new(mut i2cdev: T) -> Result<BME280<T>, SensorError<T::Error>> {
+                let chip_id = i2cdev.smbus_read_byte_data(REGISTER_CHIP_ID)?;
+                let humidity = match chip_id {
+                    CHIP_ID_BME280 => true,
+                    id if CHIP_IDS_BMP280.contains(&id) => false,
+                    id => return Err(SensorError::UnknownChip(id)),
+                };
+                let calibration = BME280Calibration::new(&mut i2cdev, humidity)?;
+                if humidity {
+                    // only takes effect with the following write of ctrl_meas
+                    i2cdev.smbus_write_byte_data(REGISTER_CTRL_HUM, CTRL_HUM_DEFAULT)?;
+                }
+                i2cdev.smbus_write_byte_data(REGISTER_CTRL_MEAS, CTRL_MEAS_DEFAULT)?;
+                Ok(BME280 {
+                    i2cdev,
+                    calibration,
+                    chip_id,
+                })
+            }
+
+            /// The chip ID, 0x60 for a BME280
+            pub fn chip_id(&self) -> u8 {
+                self.chip_id
+            }
+
+            /// Whether the chip is a BME280, with a humidity sensor
+            pub fn has_humidity(&self) -> bool {
+                self.calibration.h.is_some()
+            }
+
+            fn reading(&mut self) -> Result<BME280RawReading, T::Error> {
+                let humidity = self.has_humidity();
+                BME280RawReading::new(&mut self.i2cdev, humidity)
+            }
+        }
+
+        impl<T> Barometer for BME280<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn pressure_kpa(&mut self) -> Result<f32, Self::Error> {
+                let reading = self.reading()?;
+                Ok(reading.pressure_kpa(&self.calibration))
+            }
+        }
+
+        impl<T> Thermometer for BME280<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn temperature_celsius(&mut self) -> Result<f32, Self::Error> {
+                let reading = self.reading()?;
+                Ok(reading.temperature_celsius(&self.calibration))
+            }
+        }
+
+        impl<T> Hygrometer for BME280<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn relative_humidity(&mut self) -> Result<f32, Self::Error> {
+                if !self.has_humidity() {
+                    return Err(SensorError::Unsupported);
+                }
+                let reading = self.reading()?;
+                reading
+                    .relative_humidity(&self.calibration)
+                    .ok_or(SensorError::Unsupported)
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::MockI2CDevice;
+
+            macro_rules! assert_almost_eq {
+                ($left:expr, $right:expr) => {{
+                    match (&($left), &($right)) {
+                        (left_val, right_val) => {
+                            if (*left_val - *right_val).abs() > 0.001 {
+                                panic!("assertion failed: ({:?} != {:?})", *left_val, *right_val);
+                            }
+                        }
+                    }
+                }};
+            }
+
+            /// A device with the calibration and readings of the example in
+            /// the BMP280 datasheet, plus humidity trimming for a BME280
+            fn make_dev(chip_id: u8) -> MockI2CDevice {
+                let mut i2cdev = MockI2CDevice::new();
+                i2cdev.regmap.write_regs(
+                    REGISTER_CALIB_00 as usize,
+                    &[
+                        112, 107, /* T1 */
+                        67, 103, /* T2 */
+                        24, 252, /* T3 */
+                        125, 142, /* P1 */
+                        67, 214, 208, 11, 39, 11, 140, 0, 249, 255, 140, 60, 248, 198, 112,
+                        23, /* P2-P9 */
+                    ],
+                );
+                i2cdev.regmap.write_regs(REGISTER_CALIB_H1 as usize, &[75]);
+                i2cdev
+                    .regmap
+                    .write_regs(REGISTER_CHIP_ID as usize, &[chip_id]);
+                i2cdev
+                    .regmap
+                    .write_regs(REGISTER_CALIB_H2 as usize, &[106, 1, 0, 19, 41, 3, 30]);
+                i2cdev.regmap.write_regs(
+                    REGISTER_PRESS_MSB as usize,
+                    &[
+                        101, 90, 192, /* pressure */
+                        126, 237, 0, /* temperature */
+                        117, 48, /* humidity */
+                    ],
+                );
+                i2cdev
+            }
+
+            #[test]
+            fn test_bme280() {
+                let mut dev = BME280::new(make_dev(CHIP_ID_BME280)).unwrap();
+                assert!(dev.has_humidity());
+                assert_eq!(
+                    dev.i2cdev.smbus_read_byte_data(REGISTER_CTRL_HUM).unwrap(),
+                    CTRL_HUM_DEFAULT
+                );
+                assert_eq!(
+                    dev.i2cdev.smbus_read_byte_data(REGISTER_CTRL_MEAS).unwrap(),
+                    CTRL_MEAS_DEFAULT
+                );
+                assert_almost_eq!(dev.temperature_celsius().unwrap(), 25.08248);
+                assert_almost_eq!(dev.pressure_kpa().unwrap(), 100.65327);
+                assert_almost_eq!(dev.relative_humidity().unwrap(), 55.00071);
+            }
+
+            #[test]
+            fn test_bmp280() {
+                let mut dev = BME280::new(make_dev(0x58)).unwrap();
+                assert!(!dev.has_humidity());
+                assert_eq!(
+                    dev.i2cdev.smbus_read_byte_data(REGISTER_CTRL_HUM).unwrap(),
+                    0
+                );
+                assert_almost_eq!(dev.temperature_celsius().unwrap(), 25.08248);
+                assert_almost_eq!(dev.pressure_kpa().unwrap(), 100.65327);
+                match dev.relative_humidity() {
+                    Err(SensorError::Unsupported) => {}
+                    other => panic!("unexpected result {:?}", other),
+                }
+            }
+
+            #[test]
+            fn test_unknown_chip() {
+                match BME280::new(make_dev(0x55)) {
+                    Err(SensorError::UnknownChip(0x55)) => {}
+                    Err(e) => panic!("unexpected error {:?}", e),
+                    Ok(_) => panic!("unexpected success"),
+                }
+            }
+        }
+    }
+
+    pub mod htu21d {
+        use super::*;
+        use i2cdev::core::I2CDevice;
+        use std::error::Error;
+        use std::thread;
+        use std::time::Duration;
+
+        pub const SLAVE_ADDR: u16 = 0x40;
+
+        // "no hold master" measurements: the sensor NACKs reads until done
+        const CMD_TRIGGER_TEMP_NO_HOLD: u8 = 0xF3;
+        const CMD_TRIGGER_HUMIDITY_NO_HOLD: u8 = 0xF5;
+
+        // maximum conversion times at the default 14-bit temperature and
+        // 12-bit humidity resolution
+        const TEMP_CONVERSION_MS: u64 = 50;
+        const HUMIDITY_CONVERSION_MS: u64 = 16;
+
+        /// CRC-8 of a measurement, with polynomial x^8 + x^5 + x^4 + 1 (0x31)
+        /// and an initial value of zero
+        ///
+        /// This is not the SMBus PEC, which uses polynomial 0x07.
+        fn crc8_31(data: &[u8]) -> u8 {
+            data.iter().fold(0, |crc, &byte| {
+                (0..8).fold(crc ^ byte, |crc, _| {
+                    if crc & 0x80 != 0 {
+                        (crc << 1) ^ 0x31
+                    } else {
+                        crc << 1
+                    }
+                })
+            })
+        }
+
+        /// Provides access to the HTU21D humidity and temperature sensor, and
+        /// compatibles such as the SHT21 and Si7021
+        ///
+        /// https://www.te.com/commerce/DocumentDelivery/DDEController?Action=showdoc&DocId=Data+Sheet%7FHPC199_6%7FA6%7Fpdf%7FEnglish%7FENG_DS_HPC199_6_A6.pdf
+        pub struct HTU21D<T: I2CDevice + Sized> {
+            pub i2cdev: T,
+        }
+
+        impl<T> HTU21D<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            /// Create a sensor on the provided device; the sensor needs no
+            /// configuration
+            pub fn new(i2cdev: T) -> HTU21D<T> {
+                HTU21D { i2cdev }
+            }
+
+            /// Trigger a measurement, wait for it and read back the 16-bit
+            /// sample with the status bits cleared
+            fn measure(
+                &mut self,
+                command: u8,
+                delay_ms: u64,
+            ) -> Result<u16, SensorError<T::Error>> {
+                self.i2cdev.write(&[command])?;
+                thread::sleep(Duration::from_millis(delay_ms));
+                let mut buf = [0_u8; 3];
+                self.i2cdev.read(&mut buf)?;
+                if crc8_31(&buf[..2]) != buf[2] {
+                    return Err(SensorError::CrcMismatch);
+                }
+                // the two least significant bits carry status
+                Ok(u16_from_be(buf[0], buf[1]) & !0x0003)
+            }
+        }
+
+        impl<T> Thermometer for HTU21D<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn temperature_celsius(&mut self) -> Result<f32, Self::Error> {
+                let sample = self.measure(CMD_TRIGGER_TEMP_NO_HOLD, TEMP_CONVERSION_MS)?;
+                Ok(-46.85 + 175.72 * f32::from(sample) / 65536.0)
+            }
+        }
+
+        impl<T> Hygrometer for HTU21D<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn relative_humidity(&mut self) -> Result<f32, Self::Error> {
+                let sample = self.measure(CMD_TRIGGER_HUMIDITY_NO_HOLD, HUMIDITY_CONVERSION_MS)?;
+                // the conversion can slightly overshoot near 0% and 100%
+                Ok((-6.0 + 125.0 * f32::from(sample) / 65536.0).clamp(0.0, 100.0))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::{MockI2CDevice, MockTransaction};
+
+            #[test]
+            fn test_crc8_31() {
+                // the examples of the datasheet
+                assert_eq!(crc8_31(&[0xDC]), 0x79);
+                assert_eq!(crc8_31(&[0x68, 0x3A]), 0x7C);
+                assert_eq!(crc8_31(&[0x4E, 0x85]), 0x6B);
+            }
+
+            #[test]
+            fn test_htu21d() {
+                let mut i2cdev = MockI2CDevice::new();
+                i2cdev.expect(vec![
+                    MockTransaction::Write(vec![CMD_TRIGGER_TEMP_NO_HOLD]),
+                    MockTransaction::Read(vec![0x68, 0x3A, 0x7C]),
+                    MockTransaction::Write(vec![CMD_TRIGGER_HUMIDITY_NO_HOLD]),
+                    MockTransaction::Read(vec![0x4E, 0x85, 0x6B]),
+                ]);
+                let mut dev = HTU21D::new(i2cdev);
+                // 0x6838 and 0x4E84 once the status bits are cleared
+                let temperature = dev.temperature_celsius().unwrap();
+                assert!((temperature - 24.6864).abs() < 0.001, "{}", temperature);
+                let humidity = dev.relative_humidity().unwrap();
+                assert!((humidity - 32.3380).abs() < 0.001, "{}", humidity);
+                dev.i2cdev.done();
+            }
+
+            #[test]
+            fn test_crc_mismatch() {
+                let mut i2cdev = MockI2CDevice::new();
+                i2cdev.expect(vec![
+                    MockTransaction::Write(vec![CMD_TRIGGER_HUMIDITY_NO_HOLD]),
+                    MockTransaction::Read(vec![0x4E, 0x85, 0x6C]),
+                ]);
+                let mut dev = HTU21D::new(i2cdev);
+                match dev.relative_humidity() {
+                    Err(SensorError::CrcMismatch) => {}
+                    other => panic!("unexpected result {:?}", other),
+                }
+            }
+        }
+    }
+    pub mod tsl2561 {
+        use super::*;
+        use i2cdev::core::I2CDevice;
+        use std::error::Error;
+        use std::thread;
+        use std::time::Duration;
+
+        pub const SLAVE_ADDR_FLOAT: u16 = 0x39;
+        pub const SLAVE_ADDR_GND: u16 = 0x29;
+        pub const SLAVE_ADDR_VDD: u16 = 0x49;
+
+        // every register access starts with the command bit; the word bit
+        // selects the SMBus word protocol
+        const COMMAND: u8 = 0x80;
+        const COMMAND_WORD: u8 = 0x20;
+
+        const REGISTER_CONTROL: u8 = 0x00;
+        const REGISTER_TIMING: u8 = 0x01;
+        const REGISTER_DATA0_LOW: u8 = 0x0C; // broadband, visible and infrared
+        const REGISTER_DATA1_LOW: u8 = 0x0E; // infrared only
+
+        const CONTROL_POWER_ON: u8 = 0x03;
+        const TIMING_HIGH_GAIN: u8 = 0x10;
+
+        /// Gain of the ADCs
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Gain {
+            /// 1x, for bright light
+            Low,
+            /// 16x, for dim light
+            High,
+        }
+
+        /// Integration time of the ADCs, longer times giving more resolution
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum IntegrationTime {
+            /// 13.7 ms
+            Ms13,
+            /// 101 ms
+            Ms101,
+            /// 402 ms
+            Ms402,
+        }
+
+        impl IntegrationTime {
+            fn bits(self) -> u8 {
+                match self {
+                    IntegrationTime::Ms13 => 0b00,
+                    IntegrationTime::Ms101 => 0b01,
+                    IntegrationTime::Ms402 => 0b10,
+                }
+            }
+
+            fn millis(self) -> f32 {
+                match self {
+                    IntegrationTime::Ms13 => 13.7,
+                    IntegrationTime::Ms101 => 101.0,
+                    IntegrationTime::Ms402 => 402.0,
+                }
+            }
+
+            /// The largest count of a channel, which also means it saturated
+            fn max_count(self) -> u16 {
+                match self {
+                    IntegrationTime::Ms13 => 5047,
+                    IntegrationTime::Ms101 => 37177,
+                    IntegrationTime::Ms402 => 65535,
+                }
+            }
+        }
+
+        /// Calculate the illuminance in lux from the two channel counts
+        ///
+        /// The counts are first scaled to the 16x gain and 402 ms
+        /// integration time the datasheet formula is given for, then the
+        /// formula for the T, FN and CL packages is applied, which depends
+        /// on the ratio of infrared to broadband light.
+        pub fn calculate_lux(ch0: u16, ch1: u16, gain: Gain, integration: IntegrationTime) -> f32 {
+            let mut scale = IntegrationTime::Ms402.millis() / integration.millis();
+            if gain == Gain::Low {
+                scale *= 16.0;
+            }
+            let ch0 = f32::from(ch0) * scale;
+            let ch1 = f32::from(ch1) * scale;
+            if ch0 == 0.0 {
+                return 0.0;
+            }
+            let ratio = ch1 / ch0;
+            let lux = if ratio <= 0.50 {
+                0.0304 * ch0 - 0.062 * ch0 * ratio.powf(1.4)
+            } else if ratio <= 0.61 {
+                0.0224 * ch0 - 0.031 * ch1
+            } else if ratio <= 0.80 {
+                0.0128 * ch0 - 0.0153 * ch1
+            } else if ratio <= 1.30 {
+                0.00146 * ch0 - 0.00112 * ch1
+            } else {
+                0.0
+            };
+            lux.max(0.0)
+        }
+
+        /// Provides access to the TAOS TSL2561 light-to-digital converter
+        ///
+        /// https://cdn-shop.adafruit.com/datasheets/TSL2561.pdf
+        pub struct TSL2561<T: I2CDevice + Sized> {
+            pub i2cdev: T,
+            gain: Gain,
+            integration: IntegrationTime,
+        }
+
+        impl<T> TSL2561<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            /// Power the sensor up with the given gain and integration
+            /// time, and wait for its first conversion
+            pub fn new(
+                mut i2cdev: T,
+                gain: Gain,
+                integration: IntegrationTime,
+            ) -> Result<TSL2561<T>, T::Error> {
+                i2cdev.smbus_write_byte_data(COMMAND | REGISTER_CONTROL, CONTROL_POWER_ON)?;
+                let mut timing = integration.bits();
+                if gain == Gain::High {
+                    timing |= TIMING_HIGH_GAIN;
+                }
+                i2cdev.smbus_write_byte_data(COMMAND | REGISTER_TIMING, timing)?;
+                thread::sleep(Duration::from_micros(
+                    (integration.millis() * 1000.0) as u64,
+                ));
+                Ok(TSL2561 {
+                    i2cdev,
+                    gain,
+                    integration,
+                })
+            }
+
+            /// Read the counts of the broadband and infrared channels
+            pub fn channels(&mut self) -> Result<(u16, u16), T::Error> {
+                let ch0 = self
+                    .i2cdev
+                    .smbus_read_word_data(COMMAND | COMMAND_WORD | REGISTER_DATA0_LOW)?;
+                let ch1 = self
+                    .i2cdev
+                    .smbus_read_word_data(COMMAND | COMMAND_WORD | REGISTER_DATA1_LOW)?;
+                Ok((ch0, ch1))
+            }
+        }
+
+        impl<T> Photometer for TSL2561<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            type Error = SensorError<T::Error>;
+
+            fn lux(&mut self) -> Result<f32, Self::Error> {
+                let (ch0, ch1) = self.channels()?;
+                let max = self.integration.max_count();
+                if ch0 >= max || ch1 >= max {
+                    return Err(SensorError::Saturated);
+                }
+                Ok(calculate_lux(ch0, ch1, self.gain, self.integration))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::MockI2CDevice;
+
+            macro_rules! assert_almost_eq {
+                ($left:expr, $right:expr) => {{
+                    match (&($left), &($right)) {
+                        (left_val, right_val) => {
+                            if (*left_val - *right_val).abs() > 0.001 {
+                                panic!("assertion failed: ({:?} != {:?})", *left_val, *right_val);
+                            }
+                        }
+                    }
+                }};
+            }
+
+            #[test]
+            fn test_calculate_lux() {
+                use self::Gain::*;
+                use self::IntegrationTime::*;
+
+                assert_almost_eq!(calculate_lux(1000, 400, High, Ms402), 13.21001);
+                assert_almost_eq!(calculate_lux(2000, 1100, High, Ms402), 10.7);
+                // 62 and 25 counts are 3948.36 and 1592.08 once scaled
+                assert_almost_eq!(calculate_lux(62, 25, Low, Ms101), 51.39029);
+                // mostly infrared light
+                assert_almost_eq!(calculate_lux(100, 200, High, Ms402), 0.0);
+                assert_almost_eq!(calculate_lux(0, 0, High, Ms402), 0.0);
+            }
+
+            #[test]
+            fn test_tsl2561() {
+                let mut i2cdev = MockI2CDevice::new();
+                i2cdev.regmap.write_regs(
+                    (COMMAND | COMMAND_WORD | REGISTER_DATA0_LOW) as usize,
+                    &[0xE8, 0x03, 0x90, 0x01], // 1000 and 400
+                );
+                let mut dev = TSL2561::new(i2cdev, Gain::High, IntegrationTime::Ms13).unwrap();
+                assert_eq!(
+                    dev.i2cdev
+                        .smbus_read_byte_data(COMMAND | REGISTER_CONTROL)
+                        .unwrap(),
+                    CONTROL_POWER_ON
+                );
+                assert_eq!(
+                    dev.i2cdev
+                        .smbus_read_byte_data(COMMAND | REGISTER_TIMING)
+                        .unwrap(),
+                    0x10
+                );
+                assert_eq!(dev.channels().unwrap(), (1000, 400));
+                // 402 / 13.7 times the counts at full integration time
+                assert_almost_eq!(dev.lux().unwrap(), 387.6221);
+            }
+
+            #[test]
+            fn test_saturated() {
+                let mut i2cdev = MockI2CDevice::new();
+                i2cdev.regmap.write_regs(
+                    (COMMAND | COMMAND_WORD | REGISTER_DATA0_LOW) as usize,
+                    &[0xB7, 0x13, 0x00, 0x01], // 5047 and 256
+                );
+                let mut dev = TSL2561::new(i2cdev, Gain::Low, IntegrationTime::Ms13).unwrap();
+                match dev.lux() {
+                    Err(SensorError::Saturated) => {}
+                    other => panic!("unexpected result {:?}", other),
+                }
+            }
+        }
+    }
+
+    pub mod ads1115 {
+        use super::*;
+        use i2cdev::core::I2CDevice;
+        use std::error::Error;
+        use std::thread;
+        use std::time::Duration;
+
+        pub const SLAVE_ADDR_GND: u16 = 0x48;
+        pub const SLAVE_ADDR_VDD: u16 = 0x49;
+        pub const SLAVE_ADDR_SDA: u16 = 0x4A;
+        pub const SLAVE_ADDR_SCL: u16 = 0x4B;
+
+        const REGISTER_CONVERSION: u8 = 0x00;
+        const REGISTER_CONFIG: u8 = 0x01;
+
+        // writing OS starts a single-shot conversion; it reads back as set
+        // once the conversion is done
+        const CONFIG_OS: u16 = 0x8000;
+        const CONFIG_MODE_SINGLE_SHOT: u16 = 0x0100;
+        const CONFIG_COMPARATOR_DISABLED: u16 = 0x0003;
+
+        // polls of the OS bit after the nominal conversion time has passed
+        const POLL_ATTEMPTS: u32 = 10;
+        const POLL_INTERVAL_MS: u64 = 1;
+
+        /// Inputs of the ADC, selected by the input multiplexer
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Mux {
+            /// AIN0 relative to AIN1
+            Diff01,
+            /// AIN0 relative to AIN3
+            Diff03,
+            /// AIN1 relative to AIN3
+            Diff13,
+            /// AIN2 relative to AIN3
+            Diff23,
+            /// AIN0 relative to ground
+            Single0,
+            /// AIN1 relative to ground
+            Single1,
+            /// AIN2 relative to ground
+            Single2,
+            /// AIN3 relative to ground
+            Single3,
+        }
+
+        impl Mux {
+            fn bits(self) -> u16 {
+                (self as u16) << 12
+            }
+        }
+
+        /// Gain of the programmable gain amplifier, given as the full-scale
+        /// range of the input
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Gain {
+            /// ±6.144 V
+            Fsr6_144,
+            /// ±4.096 V
+            Fsr4_096,
+            /// ±2.048 V, the power-on default
+            Fsr2_048,
+            /// ±1.024 V
+            Fsr1_024,
+            /// ±0.512 V
+            Fsr0_512,
+            /// ±0.256 V
+            Fsr0_256,
+        }
+
+        impl Gain {
+            fn bits(self) -> u16 {
+                (self as u16) << 9
+            }
+
+            /// The full-scale range in volts
+            pub fn full_scale_volts(self) -> f32 {
+                match self {
+                    Gain::Fsr6_144 => 6.144,
+                    Gain::Fsr4_096 => 4.096,
+                    Gain::Fsr2_048 => 2.048,
+                    Gain::Fsr1_024 => 1.024,
+                    Gain::Fsr0_512 => 0.512,
+                    Gain::Fsr0_256 => 0.256,
+                }
+            }
+        }
+
+        /// Conversions per second
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum DataRate {
+            Sps8,
+            Sps16,
+            Sps32,
+            Sps64,
+            /// The power-on default
+            Sps128,
+            Sps250,
+            Sps475,
+            Sps860,
+        }
+
+        impl DataRate {
+            fn bits(self) -> u16 {
+                (self as u16) << 5
+            }
+
+            fn samples_per_second(self) -> u64 {
+                match self {
+                    DataRate::Sps8 => 8,
+                    DataRate::Sps16 => 16,
+                    DataRate::Sps32 => 32,
+                    DataRate::Sps64 => 64,
+                    DataRate::Sps128 => 128,
+                    DataRate::Sps250 => 250,
+                    DataRate::Sps475 => 475,
+                    DataRate::Sps860 => 860,
+                }
+            }
+
+            /// The nominal time of a conversion, plus a microsecond for rounding
+            fn conversion_time(self) -> Duration {
+                let sps = self.samples_per_second();
+                Duration::from_micros(1_000_000 / sps + 1)
+            }
+        }
+
+        /// Convert a conversion result to volts for the given gain
+        pub fn raw_to_volts(raw: i16, gain: Gain) -> f32 {
+            f32::from(raw) * gain.full_scale_volts() / 32768.0
+        }
+
+        /// Provides access to the TI ADS1115 16-bit ADC with four inputs
+        ///
+        /// Every reading is a single-shot conversion, so the ADC powers down
+        /// between readings.
+        ///
+        /// https://www.ti.com/lit/ds/symlink/ads1115.pdf
+        pub struct ADS1115<T: I2CDevice + Sized> {
+            pub i2cdev: T,
+            gain: Gain,
+            rate: DataRate,
+        }
+
+        impl<T> ADS1115<T>
+        where
+            T: I2CDevice + Sized,
+            T::Error: Error,
+        {
+            /// Create an ADC on the provided device; the gain and data rate
+            /// are written with each conversion
+            pub fn new(i2cdev: T, gain: Gain, rate: DataRate) -> ADS1115<T> {
+                ADS1115 { i2cdev, gain, rate }
+            }
+
+            /// Change the gain used from the next conversion on
+            pub fn set_gain(&mut self, gain: Gain) {
+                self.gain = gain;
+            }
+
+            /// Change the data rate used from the next conversion on
+            pub fn set_data_rate(&mut self, rate: DataRate) {
+                self.rate = rate;
+            }
+
+            /// Run a single-shot conversion of `mux` and read its signed
+            /// result
+            pub fn read_raw(&mut self, mux: Mux) -> Result<i16, SensorError<T::Error>> {
+                let config = CONFIG_OS
+                    | mux.bits()
+                    | self.gain.bits()
+                    | CONFIG_MODE_SINGLE_SHOT
+                    | self.rate.bits()
+                    | CONFIG_COMPARATOR_DISABLED;
+                // the registers hold the most significant byte first
+                self.i2cdev
+                    .smbus_write_word_data_be(REGISTER_CONFIG, config)?;
+                thread::sleep(self.rate.conversion_time());
+                let mut attempts = 0;
+                while self.i2cdev.smbus_read_word_data_be(REGISTER_CONFIG)? & CONFIG_OS == 0 {
+                    attempts += 1;
+                    if attempts == POLL_ATTEMPTS {
+                        return Err(SensorError::Timeout);
+                    }
+                    thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+                }
+                let raw = self.i2cdev.smbus_read_word_data_be(REGISTER_CONVERSION)?;
+                Ok(raw as i16)
+            }
+
+            /// Run a single-shot conversion of `mux` and return the input
+            /// voltage
+            pub fn read_volts(&mut self, mux: Mux) -> Result<f32, SensorError<T::Error>> {
+                let raw = self.read_raw(mux)?;
+                Ok(raw_to_volts(raw, self.gain))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use i2cdev::mock::{MockI2CDevice, MockTransaction};
+
+            macro_rules! assert_almost_eq {
+                ($left:expr, $right:expr) => {{
+                    match (&($left), &($right)) {
+                        (left_val, right_val) => {
+                            if (*left_val - *right_val).abs() > 0.0001 {
+                                panic!("assertion failed: ({:?} != {:?})", *left_val, *right_val);
+                            }
+                        }
+                    }
+                }};
+            }
+
+            /// The transactions of a conversion with `config`, finishing at
+            /// the first poll with `result` in the conversion register
+            fn conversion(config: u16, result: [u8; 2]) -> Vec<MockTransaction> {
+                let [msb, lsb] = config.to_be_bytes();
+                vec![
+                    MockTransaction::Write(vec![REGISTER_CONFIG, msb, lsb]),
+                    MockTransaction::Write(vec![REGISTER_CONFIG]),
+                    MockTransaction::Read(vec![msb, lsb]),
+                    MockTransaction::Write(vec![REGISTER_CONVERSION]),
+                    MockTransaction::Read(result.to_vec()),
+                ]
+            }
+
+            #[test]
+            fn test_raw_to_volts() {
+                assert_almost_eq!(raw_to_volts(16384, Gain::Fsr2_048), 1.024);
+                assert_almost_eq!(raw_to_volts(-32768, Gain::Fsr4_096), -4.096);
+                assert_almost_eq!(raw_to_volts(32767, Gain::Fsr0_256), 0.255992);
+            }
+
+            #[test]
+            fn test_ads1115() {
+                let mut i2cdev = MockI2CDevice::new();
+                // AIN1 at ±2.048 V and 860 SPS, then AIN0-AIN1 at ±0.512 V
+                let mut transactions = conversion(0xD5E3, [0x40, 0x00]);
+                transactions.extend(conversion(0x89E3, [0xC0, 0x00]));
+                i2cdev.expect(transactions);
+                let mut dev = ADS1115::new(i2cdev, Gain::Fsr2_048, DataRate::Sps860);
+                assert_almost_eq!(dev.read_volts(Mux::Single1).unwrap(), 1.024);
+                dev.set_gain(Gain::Fsr0_512);
+                assert_almost_eq!(dev.read_volts(Mux::Diff01).unwrap(), -0.256);
+                dev.i2cdev.done();
+            }
+
+            #[test]
+            fn test_timeout() {
+                let mut i2cdev = MockI2CDevice::new();
+                let mut transactions =
+                    vec![MockTransaction::Write(vec![REGISTER_CONFIG, 0xC5, 0xE3])];
+                for _ in 0..POLL_ATTEMPTS {
+                    transactions.push(MockTransaction::Write(vec![REGISTER_CONFIG]));
+                    transactions.push(MockTransaction::Read(vec![0x45, 0xE3]));
+                }
+                i2cdev.expect(transactions);
+                let mut dev = ADS1115::new(i2cdev, Gain::Fsr2_048, DataRate::Sps860);
+                match dev.read_raw(Mux::Single0) {
+                    Err(SensorError::Timeout) => {}
+                    other => panic!("unexpected result {:?}", other),
+                }
+                dev.i2cdev.done();
+            }
+        }
+    }
 }
 
 const USAGE: &str = "
@@ -473,10 +1805,10 @@ fn main() {
         .unwrap_or_else(|e| e.exit());
     let device = args.get_str("<device>");
     let mpl115a2_i2cdev = LinuxI2CDevice::new(device, MPL115A2_I2C_ADDR).unwrap();
-    let adxl345_i2cdev = LinuxI2CDevice::new(device, 0x53).unwrap();
+    let adxl345_i2cdev = LinuxI2CDevice::new(device, SLAVE_ADDR_ALT).unwrap();
 
     let mut mpl115a2 = MPL115A2BarometerThermometer::new(mpl115a2_i2cdev).unwrap();
-    let mut adxl345 = ADXL345Accelerometer::new(adxl345_i2cdev).unwrap();
+    let mut adxl345 = ADXL345Accelerometer::new(adxl345_i2cdev, Default::default()).unwrap();
 
     println!("== ADXL345 ID: 0x{:X} ==", adxl345.device_id().unwrap());
 
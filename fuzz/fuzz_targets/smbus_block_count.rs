@@ -0,0 +1,41 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Feeds arbitrary SMBus block read results, whose count byte comes from
+//! the device, through the parsing of the block reads.
+//!
+//! Run with `cargo fuzz run smbus_block_count` from the root of the crate.
+//! The first input byte is the largest count the read accepts, the rest
+//! the `i2c_smbus_data` the kernel returned.
+
+#![no_main]
+
+use i2cdev::fuzzing::fuzz_block_data;
+use libfuzzer_sys::fuzz_target;
+
+const BLOCK_MAX: u8 = 32;
+
+fuzz_target!(|input: &[u8]| {
+    let (&max, bytes) = match input.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let count = bytes.first().copied().unwrap_or(0);
+
+    let (block, raw_count, raw) = fuzz_block_data(bytes, max);
+    match block {
+        Some(block) => {
+            assert!(count <= max.min(BLOCK_MAX));
+            assert_eq!(block.len(), usize::from(count));
+            assert_eq!(block, raw);
+        }
+        None => assert!(count > max.min(BLOCK_MAX)),
+    }
+    assert_eq!(raw_count, count);
+    assert_eq!(raw.len(), usize::from(count.min(BLOCK_MAX)));
+});
@@ -6,27 +6,286 @@
 // option.  This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use ::core::convert::TryFrom;
+use ::core::fmt;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 use byteorder::{ByteOrder, LittleEndian};
-use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// I2C slave address
+///
+/// Most devices use 7-bit addresses, but the bus also allows 10-bit
+/// addressing.  Plain `u16` addresses convert into a 7-bit `Address`, so
+/// existing callers passing a `u16` keep working; 10-bit addresses are
+/// always given explicitly as `Address::TenBit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Address {
+    /// 7-bit address in the range 0x00 to 0x7F
+    SevenBit(u8),
+    /// 10-bit address in the range 0x000 to 0x3FF
+    TenBit(u16),
+}
+
+impl Address {
+    /// Create a 7-bit address, returning `None` if it is out of range
+    pub fn seven_bit(address: u8) -> Option<Address> {
+        let address = Address::SevenBit(address);
+        if address.is_valid() {
+            Some(address)
+        } else {
+            None
+        }
+    }
+
+    /// Create a 10-bit address, returning `None` if it is out of range
+    pub fn ten_bit(address: u16) -> Option<Address> {
+        let address = Address::TenBit(address);
+        if address.is_valid() {
+            Some(address)
+        } else {
+            None
+        }
+    }
+
+    /// Create a 7-bit address, failing if it is above 0x7F
+    ///
+    /// Unlike `From<u16>`, this reports an 8-bit address taken from a
+    /// datasheet, such as 0xA0, straight away.
+    pub fn new_seven_bit(address: u16) -> Result<Address, InvalidAddress> {
+        u8::try_from(address)
+            .ok()
+            .and_then(Address::seven_bit)
+            .ok_or(InvalidAddress(address))
+    }
+
+    /// Create a 10-bit address, failing if it is above 0x3FF
+    pub fn new_ten_bit(address: u16) -> Result<Address, InvalidAddress> {
+        Address::ten_bit(address).ok_or(InvalidAddress(address))
+    }
+
+    /// Check that the address fits in the number of bits for its kind
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Address::SevenBit(address) => address <= 0x7F,
+            Address::TenBit(address) => address <= 0x3FF,
+        }
+    }
+
+    /// Check if this is one of the 7-bit addresses reserved by the I2C
+    /// specification (0x00 to 0x07 and 0x78 to 0x7F)
+    pub fn is_reserved(&self) -> bool {
+        match *self {
+            Address::SevenBit(address) => address <= 0x07 || address >= 0x78,
+            Address::TenBit(_) => false,
+        }
+    }
+
+    /// Check if this is a 10-bit address
+    pub fn is_ten_bit(&self) -> bool {
+        match *self {
+            Address::SevenBit(_) => false,
+            Address::TenBit(_) => true,
+        }
+    }
+
+    /// The numeric value of the address
+    pub fn value(&self) -> u16 {
+        match *self {
+            Address::SevenBit(address) => u16::from(address),
+            Address::TenBit(address) => address,
+        }
+    }
+}
+
+/// A plain `u16` is a 7-bit address
+///
+/// Values above 0x7F do not fit and give an invalid `SevenBit` address,
+/// which is rejected with `EINVAL` once it is used; values above 0xFF
+/// become `SevenBit(0xFF)`.  Use [`Address::new_seven_bit`] to catch them
+/// up front, or `Address::TenBit` for a 10-bit address.
+impl From<u16> for Address {
+    fn from(address: u16) -> Address {
+        Address::SevenBit(u8::try_from(address).unwrap_or(u8::MAX))
+    }
+}
+
+impl From<Address> for u16 {
+    fn from(address: Address) -> u16 {
+        address.value()
+    }
+}
+
+/// Error of [`Address::new_seven_bit`] and [`Address::new_ten_bit`] for an
+/// address out of range, holding the address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InvalidAddress(pub u16);
+
+impl fmt::Display for InvalidAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid I2C address 0x{:x}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidAddress {}
+
+/// Convert a 7-bit address to the 8-bit form some datasheets give
+///
+/// On the wire, the 7-bit address is followed by the read/write bit, so
+/// datasheets sometimes list the first byte of a write, the address shifted
+/// left by one, as an "8-bit address", or a pair of write and read
+/// addresses such as 0xA0/0xA1.  This returns the write form; the read form
+/// has the low bit set.
+///
+/// This crate takes 7-bit addresses everywhere, including
+/// `LinuxI2CDevice::new`, so an 8-bit address from a datasheet has to be
+/// converted with [`addr_8bit_to_7bit`] first.
+pub fn addr_7bit_to_8bit(address: u16) -> u16 {
+    address << 1
+}
+
+/// Convert an 8-bit address, the write or the read form, to the 7-bit
+/// address this crate takes
+///
+/// See [`addr_7bit_to_8bit`] for the difference.
+///
+/// ```rust
+/// use i2cdev::core::addr_8bit_to_7bit;
+///
+/// // a 24C02 EEPROM listed as 0xA0 (write) and 0xA1 (read)
+/// assert_eq!(addr_8bit_to_7bit(0xA0), 0x50);
+/// assert_eq!(addr_8bit_to_7bit(0xA1), 0x50);
+/// ```
+pub fn addr_8bit_to_7bit(address: u16) -> u16 {
+    address >> 1
+}
+
+/// Combine two bytes a device sent most significant first into a word
+///
+/// The bytes are taken in the order they arrive, so for a register pair
+/// like `OUT_H`, `OUT_L` read in one go, pass `buf[0], buf[1]`.  Cast the
+/// result with `as i16` for a two's complement value.
+///
+/// ```rust
+/// use i2cdev::core::u16_from_be;
+///
+/// let buf = [0x12, 0x34];
+/// assert_eq!(u16_from_be(buf[0], buf[1]), 0x1234);
+/// assert_eq!(u16_from_be(0xFF, 0xFE) as i16, -2);
+/// ```
+pub const fn u16_from_be(hi: u8, lo: u8) -> u16 {
+    (hi as u16) << 8 | lo as u16
+}
+
+/// Combine two bytes a device sent least significant first into a word
+///
+/// The bytes are taken in the order they arrive, so for a register pair
+/// like `OUT_L`, `OUT_H` read in one go, pass `buf[0], buf[1]`.  This is
+/// the order of SMBus words, which `smbus_read_word_data` already returns
+/// combined.
+///
+/// ```rust
+/// use i2cdev::core::u16_from_le;
+///
+/// let buf = [0x34, 0x12];
+/// assert_eq!(u16_from_le(buf[0], buf[1]), 0x1234);
+/// ```
+pub const fn u16_from_le(lo: u8, hi: u8) -> u16 {
+    (hi as u16) << 8 | lo as u16
+}
+
+/// Largest number of data bytes in an SMBus block
+///
+/// This is `I2C_SMBUS_BLOCK_MAX` of the kernel, and the size of the buffers
+/// the block methods such as
+/// [`smbus_read_block_data_into`](trait.I2CDevice.html#tymethod.smbus_read_block_data_into)
+/// take.
+pub const SMBUS_BLOCK_MAX: usize = 32;
+
+/// Direction of an SMBus Quick command, see
+/// [`I2CDevice::smbus_quick`](trait.I2CDevice.html#method.smbus_quick)
+///
+/// A Quick command carries no data: the direction bit after the address is
+/// all the device gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuickCommand {
+    /// Send the address with the write bit, 0
+    Write,
+    /// Send the address with the read bit, 1
+    Read,
+}
 
 /// Interface to an I2C Slave Device from an I2C Master
 ///
 /// Typical implementations will store state with references to the bus
 /// in use and the address of the slave device.  The trait is based on the
 /// Linux i2cdev interface.
+///
+/// This trait does not need an allocator; the SMBus methods returning a
+/// `Vec` are in [`I2CDeviceExt`](trait.I2CDeviceExt.html).
 pub trait I2CDevice {
     /// Error type
-    type Error: Error;
+    type Error: fmt::Debug;
 
     /// Read data from the device to fill the provided slice
     fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
 
+    /// Read data from the device into the provided slice, returning the
+    /// number of bytes which are valid
+    ///
+    /// Unlike `read`, a short read from the device is not an error.  The
+    /// default implementation fills the whole slice with `read`.
+    fn read_len(&mut self, data: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(data)?;
+        Ok(data.len())
+    }
+
     /// Write the provided buffer to the device
     fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
 
+    /// Make sure the data of previous writes has been sent to the device
+    ///
+    /// Call this before waiting for something a write started, like a
+    /// conversion, on implementations which may buffer writes.  The default
+    /// implementation does nothing, which suits implementations whose writes
+    /// complete before they return.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// This sends a single bit to the device, at the place of the Rd/Wr bit
+    ///
+    /// `true` is the read bit and `false` the write bit; prefer
+    /// [`smbus_quick`](#method.smbus_quick), which names the direction.
     fn smbus_write_quick(&mut self, bit: bool) -> Result<(), Self::Error>;
 
+    /// Send an SMBus Quick command in the direction `cmd`
+    ///
+    /// The device only sees its address and the direction bit, which some
+    /// devices use as an on/off switch.  This is the same as
+    /// `smbus_write_quick`, with the bit given by its meaning.
+    ///
+    #[cfg_attr(feature = "std", doc = "```rust,no_run")]
+    #[cfg_attr(not(feature = "std"), doc = "```ignore")]
+    /// # use i2cdev::core::{I2CDevice, QuickCommand};
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut dev = LinuxI2CDevice::new("/dev/i2c-1", 0x20)?;
+    /// dev.smbus_quick(QuickCommand::Write)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn smbus_quick(&mut self, cmd: QuickCommand) -> Result<(), Self::Error> {
+        self.smbus_write_quick(cmd == QuickCommand::Read)
+    }
+
     /// Read a single byte from a device, without specifying a device register
     ///
     /// Some devices are so simple that this interface is enough; for
@@ -62,6 +321,11 @@ pub trait I2CDevice {
     }
 
     /// Read 2 bytes from a given register on a device (lsb first)
+    ///
+    /// The SMBus specification sends the least significant byte of a word
+    /// first, so the value is the same on little and big endian hosts.
+    /// Devices whose registers hold the most significant byte first need
+    /// `smbus_read_word_data_be`.
     fn smbus_read_word_data(&mut self, register: u8) -> Result<u16, Self::Error> {
         let mut buf: [u8; 2] = [0x00; 2];
         self.smbus_write_byte(register)?;
@@ -69,13 +333,40 @@ pub trait I2CDevice {
         Ok(LittleEndian::read_u16(&buf))
     }
 
+    /// Read 2 bytes from a given register on a device, lsb first
+    ///
+    /// The same as `smbus_read_word_data`, with the byte order in the name.
+    fn smbus_read_word_data_le(&mut self, register: u8) -> Result<u16, Self::Error> {
+        self.smbus_read_word_data(register)
+    }
+
+    /// Read 2 bytes from a given register on a device, msb first
+    fn smbus_read_word_data_be(&mut self, register: u8) -> Result<u16, Self::Error> {
+        Ok(self.smbus_read_word_data(register)?.swap_bytes())
+    }
+
     /// Write 2 bytes to a given register on a device (lsb first)
+    ///
+    /// As with `smbus_read_word_data`, the least significant byte is sent
+    /// first whatever the endianness of the host.
     fn smbus_write_word_data(&mut self, register: u8, value: u16) -> Result<(), Self::Error> {
         let mut buf: [u8; 3] = [register, 0, 0];
         LittleEndian::write_u16(&mut buf[1..], value);
         self.write(&buf)
     }
 
+    /// Write 2 bytes to a given register on a device, lsb first
+    ///
+    /// The same as `smbus_write_word_data`, with the byte order in the name.
+    fn smbus_write_word_data_le(&mut self, register: u8, value: u16) -> Result<(), Self::Error> {
+        self.smbus_write_word_data(register, value)
+    }
+
+    /// Write 2 bytes to a given register on a device, msb first
+    fn smbus_write_word_data_be(&mut self, register: u8, value: u16) -> Result<(), Self::Error> {
+        self.smbus_write_word_data(register, value.swap_bytes())
+    }
+
     /// Select a register, send 16 bits of data to it, and read 16 bits of data
     fn smbus_process_word(&mut self, register: u8, value: u16) -> Result<u16, Self::Error> {
         let mut buf: [u8; 2] = [0x00; 2];
@@ -84,17 +375,54 @@ pub trait I2CDevice {
         Ok(LittleEndian::read_u16(&buf))
     }
 
-    /// Read a block of up to 32 bytes from a device
+    /// Read a block of up to 32 bytes from a device into `buf`
     ///
-    /// The actual number of bytes available to read is returned in the count
-    /// byte.  This code returns a correctly sized vector containing the
-    /// count bytes read from the device.
-    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, Self::Error>;
+    /// The actual number of bytes available to read is returned in the
+    /// count byte, so an empty block reads as `Ok(0)`.  The default
+    /// implementation writes the register and reads the count byte along
+    /// with 32 bytes of data, so it does not use a repeated start and reads
+    /// past the end of a short block; a count above 32 is cut to 32.
+    fn smbus_read_block_data_into(
+        &mut self,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize, Self::Error> {
+        let mut block = [0; SMBUS_BLOCK_MAX + 1];
+        self.smbus_write_byte(register)?;
+        self.read(&mut block)?;
+        let count = usize::from(block[0]).min(SMBUS_BLOCK_MAX);
+        buf[..count].copy_from_slice(&block[1..=count]);
+        Ok(count)
+    }
 
-    /// Read a block of up to 32 bytes from a device
+    /// Read a block into `buf`, which may have any length, returning the
+    /// number of bytes read
     ///
-    /// Uses read_i2c_block_data instead read_block_data.
-    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> Result<Vec<u8>, Self::Error>;
+    /// This suits a buffer sized for the largest block a device is known to
+    /// send.  If the block does not fit in `buf`, the read fails and the
+    /// contents of `buf` are unspecified; the block has been read from the
+    /// device all the same.  The default implementation reads the block
+    /// with `smbus_read_block_data_into` and copies it, failing with
+    /// `InvalidInput`.
+    ///
+    /// This method is available with the `std` feature.
+    #[cfg(feature = "std")]
+    fn smbus_read_block_data_into_slice(
+        &mut self,
+        register: u8,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Error>
+    where
+        Self::Error: From<io::Error>,
+    {
+        let mut block = [0; SMBUS_BLOCK_MAX];
+        let count = self.smbus_read_block_data_into(register, &mut block)?;
+        let buf = buf.get_mut(..count).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "block longer than the buffer")
+        })?;
+        buf.copy_from_slice(&block[..count]);
+        Ok(count)
+    }
 
     /// Write a block of up to 32 bytes to a device
     ///
@@ -112,11 +440,74 @@ pub trait I2CDevice {
         values: &[u8],
     ) -> Result<(), Self::Error>;
 
+    /// Reset the device by writing `reset_value` to `reset_register`, then
+    /// wait `settle` for it to come back
+    ///
+    /// Datasheets give the time a device needs after a soft reset before
+    /// it answers again, such as 2ms for the BME280 (0xB6 to 0xE0); the
+    /// wait is part of the call so it cannot be forgotten.  Some devices
+    /// reset before acknowledging the value, so the write may fail even
+    /// though the device did reset.
+    ///
+    /// This method is available with the `std` feature.
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use i2cdev::core::I2CDevice;
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut bme280 = LinuxI2CDevice::new("/dev/i2c-1", 0x76)?;
+    /// bme280.soft_reset(0xE0, 0xB6, Duration::from_millis(2))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    fn soft_reset(
+        &mut self,
+        reset_register: u8,
+        reset_value: u8,
+        settle: Duration,
+    ) -> Result<(), Self::Error> {
+        self.smbus_write_byte_data(reset_register, reset_value)?;
+        thread::sleep(settle);
+        Ok(())
+    }
+}
+
+/// SMBus block methods of an [`I2CDevice`](trait.I2CDevice.html) which
+/// return their data in a `Vec`
+///
+/// This trait is available with the `alloc` feature, which is enabled by
+/// `std`.
+#[cfg(feature = "alloc")]
+pub trait I2CDeviceExt: I2CDevice {
+    /// Read a block of up to 32 bytes from a device
+    ///
+    /// The actual number of bytes available to read is returned in the count
+    /// byte.  This code returns a correctly sized vector containing the
+    /// count bytes read from the device.  The default implementation goes
+    /// through `smbus_read_block_data_into`.
+    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, Self::Error> {
+        let mut buf = [0; SMBUS_BLOCK_MAX];
+        let count = self.smbus_read_block_data_into(register, &mut buf)?;
+        Ok(buf[..count].to_vec())
+    }
+
+    /// Read a block of up to 32 bytes from a device
+    ///
+    /// Uses read_i2c_block_data instead read_block_data.
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> Result<Vec<u8>, Self::Error>;
+
     /// Select a register, send 1 to 31 bytes of data to it, and reads
     /// 1 to 31 bytes of data from it.
     fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> Result<Vec<u8>, Self::Error>;
 }
 
+/// Maximum number of messages in a single transfer
+///
+/// This is the limit the Linux kernel enforces for the `I2C_RDWR` ioctl.
+pub const MAX_TRANSFER_MESSAGES: usize = 42;
+
 /// Interface to an I2C Bus from an I2C Master
 ///
 /// This is used when the client wants to interact directly with the bus
@@ -130,7 +521,7 @@ pub trait I2CDevice {
 /// in use.  The trait is based on the Linux i2cdev interface.
 pub trait I2CTransfer<'a> {
     /// I2C transfer error type
-    type Error: Error;
+    type Error: fmt::Debug;
     /// I2C transfer message type
     type Message: I2CMessage<'a>;
 
@@ -138,6 +529,158 @@ pub trait I2CTransfer<'a> {
     /// success the return code is the number of successfully executed
     /// transactions
     fn transfer(&mut self, msgs: &mut [Self::Message]) -> Result<u32, Self::Error>;
+
+    /// Performs the I2C read/write transactions produced by an iterator
+    ///
+    /// Messages are collected into batches of up to `MAX_TRANSFER_MESSAGES`,
+    /// each of which is issued with its own call to `transfer`, so long or
+    /// lazily built sequences do not need to be chunked by hand.  Messages
+    /// from different batches are not part of a single combined transaction.
+    /// On success the return code is the total number of successfully
+    /// executed transactions.
+    ///
+    /// This method is available with the `alloc` feature.
+    #[cfg(feature = "alloc")]
+    fn transfer_iter<I>(&mut self, msgs: I) -> Result<u32, Self::Error>
+    where
+        I: IntoIterator<Item = Self::Message>,
+        Self: Sized,
+    {
+        let mut batch = Vec::with_capacity(MAX_TRANSFER_MESSAGES);
+        let mut total = 0;
+        for msg in msgs {
+            batch.push(msg);
+            if batch.len() == MAX_TRANSFER_MESSAGES {
+                total += self.transfer(&mut batch)?;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            total += self.transfer(&mut batch)?;
+        }
+        Ok(total)
+    }
+}
+
+/// SMBus operations on a bus, addressing the slave with every call
+///
+/// These are the SMBus methods of [`I2CDevice`](trait.I2CDevice.html)
+/// with an explicit slave address, so a single SMBus command can be sent
+/// to any device on a bus without creating a device for it first.  The
+/// methods have the same names as on `I2CDevice`, so this trait is not
+/// implemented for devices, which would make calls ambiguous.
+pub trait I2CSmbus {
+    /// Error type
+    type Error: fmt::Debug;
+
+    /// Send a single bit to the device, at the place of the Rd/Wr bit
+    fn smbus_write_quick<A: Into<Address>>(
+        &mut self,
+        address: A,
+        bit: bool,
+    ) -> Result<(), Self::Error>;
+
+    /// Read a single byte from a device, without specifying a device register
+    fn smbus_read_byte<A: Into<Address>>(&mut self, address: A) -> Result<u8, Self::Error>;
+
+    /// Write a single byte to a device, without specifying a device register
+    fn smbus_write_byte<A: Into<Address>>(
+        &mut self,
+        address: A,
+        value: u8,
+    ) -> Result<(), Self::Error>;
+
+    /// Read a single byte from a device, from a designated register
+    fn smbus_read_byte_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+    ) -> Result<u8, Self::Error>;
+
+    /// Write a single byte to a specific register on a device
+    fn smbus_write_byte_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u8,
+    ) -> Result<(), Self::Error>;
+
+    /// Read 2 bytes from a given register on a device (lsb first)
+    fn smbus_read_word_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+    ) -> Result<u16, Self::Error>;
+
+    /// Write 2 bytes to a given register on a device (lsb first)
+    fn smbus_write_word_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u16,
+    ) -> Result<(), Self::Error>;
+
+    /// Select a register, send 16 bits of data to it, and read 16 bits of data
+    fn smbus_process_word<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u16,
+    ) -> Result<u16, Self::Error>;
+
+    /// Read a block of up to 32 bytes from a device into `buf`, returning
+    /// the count byte
+    fn smbus_read_block_data_into<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize, Self::Error>;
+
+    /// Write a block of up to 32 bytes to a device, preceded by its count
+    fn smbus_write_block_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        values: &[u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Write a block of up to 32 bytes to a device, without a count byte
+    fn smbus_write_i2c_block_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        values: &[u8],
+    ) -> Result<(), Self::Error>;
+}
+
+#[cfg(feature = "std")]
+bitflags! {
+    /// Various flags used by the i2c_rdwr ioctl on Linux. For details, see
+    /// https://www.kernel.org/doc/Documentation/i2c/i2c-protocol
+    ///
+    /// In general, these are for special cases and should not be needed.
+    /// The mock messages carry them too, so drivers relying on them can be
+    /// tested without hardware.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct I2CMessageFlags: u16 {
+        /// Use ten bit addressing on this message
+        const TEN_BIT_ADDRESS = 0x0010;
+        /// Read data, from slave to master
+        const READ = 0x0001;
+        /// Force an I2C stop condition on this message
+        const STOP = 0x8000;
+        /// Avoid sending an I2C start condition on this message
+        const NO_START = 0x4000;
+        /// If you need to invert a 'read' command bit to a 'write'
+        const INVERT_COMMAND = 0x2000;
+        /// Force this message to ignore I2C negative acknowlegements
+        const IGNORE_NACK = 0x1000;
+        /// Force message to ignore acknowledgement
+        const IGNORE_ACK = 0x0800;
+        /// Allow the client to specify how many bytes it will send
+        const USE_RECEIVE_LENGTH = 0x0400;
+    }
 }
 
 /// Read/Write I2C message
@@ -148,3 +691,217 @@ pub trait I2CMessage<'a> {
     /// Write data to device
     fn write(data: &'a [u8]) -> Self;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::mock::{MockI2CDevice, MockI2CMessage};
+    #[cfg(feature = "std")]
+    use std::io;
+
+    /// Mock bus counting the calls to `transfer`
+    #[cfg(feature = "std")]
+    struct CountingTransfer {
+        dev: MockI2CDevice,
+        calls: usize,
+    }
+
+    #[cfg(feature = "std")]
+    impl<'a> I2CTransfer<'a> for CountingTransfer {
+        type Error = io::Error;
+        type Message = MockI2CMessage<'a>;
+
+        fn transfer(&mut self, msgs: &mut [Self::Message]) -> io::Result<u32> {
+            assert!(msgs.len() <= MAX_TRANSFER_MESSAGES);
+            self.calls += 1;
+            self.dev.transfer(msgs)
+        }
+    }
+
+    /// Device answering every read with a fixed block
+    struct BlockDevice {
+        register: Option<u8>,
+        block: [u8; SMBUS_BLOCK_MAX + 1],
+    }
+
+    impl I2CDevice for BlockDevice {
+        type Error = ();
+
+        fn read(&mut self, data: &mut [u8]) -> Result<(), ()> {
+            data.copy_from_slice(&self.block[..data.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), ()> {
+            self.register = Some(data[0]);
+            Ok(())
+        }
+
+        fn smbus_write_quick(&mut self, _bit: bool) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn smbus_write_block_data(&mut self, _register: u8, _values: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn smbus_write_i2c_block_data(&mut self, _register: u8, _values: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn test_default_block_read() {
+        let mut dev = BlockDevice {
+            register: None,
+            block: [0xAA; SMBUS_BLOCK_MAX + 1],
+        };
+        dev.block[..4].copy_from_slice(&[3, 1, 2, 3]);
+        let mut buf = [0; SMBUS_BLOCK_MAX];
+        assert_eq!(dev.smbus_read_block_data_into(0x20, &mut buf), Ok(3));
+        assert_eq!(dev.register, Some(0x20));
+        assert_eq!(buf[..3], [1, 2, 3]);
+
+        dev.block[0] = 0;
+        assert_eq!(dev.smbus_read_block_data_into(0x20, &mut buf), Ok(0));
+
+        // a count out of range reads the whole block
+        dev.block[0] = 0xFF;
+        assert_eq!(
+            dev.smbus_read_block_data_into(0x20, &mut buf),
+            Ok(SMBUS_BLOCK_MAX)
+        );
+        assert_eq!(buf[..], dev.block[1..]);
+    }
+
+    /// Device recording the bit of the last quick command
+    struct QuickDevice {
+        bit: Option<bool>,
+    }
+
+    impl I2CDevice for QuickDevice {
+        type Error = ();
+
+        fn read(&mut self, _data: &mut [u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn write(&mut self, _data: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn smbus_write_quick(&mut self, bit: bool) -> Result<(), ()> {
+            self.bit = Some(bit);
+            Ok(())
+        }
+
+        fn smbus_write_block_data(&mut self, _register: u8, _values: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn smbus_write_i2c_block_data(&mut self, _register: u8, _values: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn test_smbus_quick_direction() {
+        let mut dev = QuickDevice { bit: None };
+        dev.smbus_quick(QuickCommand::Read).unwrap();
+        assert_eq!(dev.bit, Some(true));
+        dev.smbus_quick(QuickCommand::Write).unwrap();
+        assert_eq!(dev.bit, Some(false));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_soft_reset() {
+        use std::time::{Duration, Instant};
+
+        let mut dev = MockI2CDevice::new();
+        let settle = Duration::from_millis(2);
+        let start = Instant::now();
+        dev.soft_reset(0xE0, 0xB6, settle).unwrap();
+        assert!(start.elapsed() >= settle);
+        assert_eq!(dev.smbus_read_byte_data(0xE0).unwrap(), 0xB6);
+
+        dev.fail_next(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(dev.soft_reset(0xE0, 0xB6, settle).is_err());
+    }
+
+    #[test]
+    fn test_seven_bit_address() {
+        assert_eq!(Address::seven_bit(0x52), Some(Address::SevenBit(0x52)));
+        assert_eq!(Address::seven_bit(0x7F), Some(Address::SevenBit(0x7F)));
+        assert_eq!(Address::seven_bit(0x80), None);
+        assert!(!Address::SevenBit(0xFF).is_valid());
+        assert!(Address::SevenBit(0x03).is_reserved());
+        assert!(Address::SevenBit(0x78).is_reserved());
+        assert!(!Address::SevenBit(0x52).is_reserved());
+    }
+
+    #[test]
+    fn test_ten_bit_address() {
+        assert_eq!(Address::ten_bit(0x3FF), Some(Address::TenBit(0x3FF)));
+        assert_eq!(Address::ten_bit(0x050), Some(Address::TenBit(0x050)));
+        assert_eq!(Address::ten_bit(0x400), None);
+        assert!(!Address::TenBit(0xFFFF).is_valid());
+        assert!(!Address::TenBit(0x003).is_reserved());
+    }
+
+    #[test]
+    fn test_address_from_u16() {
+        assert_eq!(Address::from(0x52), Address::SevenBit(0x52));
+        assert_eq!(Address::from(0x7F), Address::SevenBit(0x7F));
+        // 8-bit datasheet addresses are not taken for 10-bit ones
+        assert_eq!(Address::from(0xA0), Address::SevenBit(0xA0));
+        assert!(!Address::from(0xA0).is_valid());
+        assert_eq!(Address::from(0x150), Address::SevenBit(0xFF));
+        assert!(!Address::from(0x400).is_valid());
+        assert_eq!(u16::from(Address::TenBit(0x123)), 0x123);
+    }
+
+    #[test]
+    fn test_new_address() {
+        assert_eq!(Address::new_seven_bit(0x50), Ok(Address::SevenBit(0x50)));
+        assert_eq!(Address::new_seven_bit(0x7F), Ok(Address::SevenBit(0x7F)));
+        assert_eq!(Address::new_seven_bit(0x80), Err(InvalidAddress(0x80)));
+        assert_eq!(Address::new_seven_bit(0xA0), Err(InvalidAddress(0xA0)));
+        assert_eq!(Address::new_seven_bit(0x150), Err(InvalidAddress(0x150)));
+
+        assert_eq!(Address::new_ten_bit(0x050), Ok(Address::TenBit(0x050)));
+        assert_eq!(Address::new_ten_bit(0x3FF), Ok(Address::TenBit(0x3FF)));
+        assert_eq!(Address::new_ten_bit(0x400), Err(InvalidAddress(0x400)));
+    }
+
+    #[test]
+    fn test_address_8bit_conversion() {
+        assert_eq!(addr_7bit_to_8bit(0x50), 0xA0);
+        assert_eq!(addr_7bit_to_8bit(0x7F), 0xFE);
+        assert_eq!(addr_8bit_to_7bit(0xA0), 0x50);
+        assert_eq!(addr_8bit_to_7bit(0xA1), 0x50);
+        assert_eq!(addr_8bit_to_7bit(addr_7bit_to_8bit(0x1D) | 1), 0x1D);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_transfer_iter_batches() {
+        let data: Vec<[u8; 2]> = (0..100).map(|i| [i as u8, !(i as u8)]).collect();
+        let mut bus = CountingTransfer {
+            dev: MockI2CDevice::new(),
+            calls: 0,
+        };
+
+        let msgs = data.iter().map(|d| MockI2CMessage::write(d));
+        assert_eq!(bus.transfer_iter(msgs).unwrap(), 100);
+        assert_eq!(bus.calls, 3);
+
+        let mut regs = [0u8; 100];
+        bus.dev.write(&[0]).unwrap();
+        bus.dev.read(&mut regs).unwrap();
+        for (i, value) in regs.iter().enumerate() {
+            assert_eq!(*value, !(i as u8));
+        }
+    }
+}
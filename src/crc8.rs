@@ -0,0 +1,42 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Compute the SMBus PEC of the given bytes
+///
+/// This is a CRC-8 with polynomial x^8 + x^2 + x + 1 (0x07), an initial
+/// value of zero and no reflection.  The PEC of an SMBus transaction covers
+/// every byte on the wire, including the address bytes, so for a block read
+/// from `address` it is computed over
+/// `[address << 1, command, address << 1 | 1, count, data...]`.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc8() {
+        assert_eq!(crc8(&[]), 0x00);
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        // appending the PEC gives a zero remainder
+        let message = [0x16, 0x09, 0x02, 0x34, 0x12];
+        let mut with_pec = message.to_vec();
+        with_pec.push(crc8(&message));
+        assert_eq!(crc8(&with_pec), 0x00);
+    }
+}
@@ -9,8 +9,9 @@
 #![allow(dead_code)]
 #![allow(non_camel_case_types)]
 
+use crate::core::SMBUS_BLOCK_MAX;
 use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
-use nix;
+use std::convert::TryFrom;
 use std::io::Cursor;
 use std::marker::PhantomData;
 use std::mem;
@@ -20,6 +21,27 @@ use std::ptr;
 pub type I2CError = nix::Error;
 
 /// Linux I2C message
+///
+/// A message borrows its buffer for `'a`, including after changing its
+/// address or flags, so it cannot outlive the data it points to:
+///
+/// ```rust,compile_fail
+/// extern crate i2cdev;
+///
+/// use i2cdev::core::I2CMessage;
+/// use i2cdev::linux::{I2CMessageFlags, LinuxI2CMessage};
+///
+/// fn main() {
+///     let msg;
+///     {
+///         let mut data = [0; 4];
+///         msg = LinuxI2CMessage::read(&mut data)
+///             .with_address(0x50)
+///             .with_flags(I2CMessageFlags::READ);
+///     }
+///     drop(msg); // error: `data` does not live long enough
+/// }
+/// ```
 #[repr(C)]
 pub struct i2c_msg<'a> {
     /// slave address
@@ -35,36 +57,64 @@ pub struct i2c_msg<'a> {
 }
 
 bitflags! {
-    struct I2CFunctions: u32 {
+    /// Functionality supported by an I2C adapter, as reported by the
+    /// `I2C_FUNCS` ioctl. For details, see
+    /// https://www.kernel.org/doc/Documentation/i2c/functionality
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct I2CFunctions: u32 {
+        /// Plain i2c-level commands (`I2C_RDWR`)
         const I2C_FUNC_I2C = 0x0000_0001;
+        /// Ten bit addressing
         const I2C_FUNC_10BIT_ADDR = 0x0000_0002;
+        /// Protocol mangling flags such as `IGNORE_NACK`
         const I2C_FUNC_PROTOCOL_MANGLING = 0x0000_0004; /* I2C_M_IGNORE_NAK etc. */
+        /// SMBus packet error checking
         const I2C_FUNC_SMBUS_PEC = 0x0000_0008;
+        /// Messages without a start condition (`NO_START`)
         const I2C_FUNC_NOSTART = 0x0000_0010; /* I2C_M_NOSTART */
+        /// SMBus block process call
         const I2C_FUNC_SMBUS_BLOCK_PROC_CALL = 0x0000_8000; /* SMBus 2.0 */
+        /// SMBus quick command
         const I2C_FUNC_SMBUS_QUICK = 0x0001_0000;
+        /// SMBus receive byte
         const I2C_FUNC_SMBUS_READ_BYTE = 0x0002_0000;
+        /// SMBus send byte
         const I2C_FUNC_SMBUS_WRITE_BYTE = 0x0004_0000;
+        /// SMBus read byte
         const I2C_FUNC_SMBUS_READ_BYTE_DATA = 0x0008_0000;
+        /// SMBus write byte
         const I2C_FUNC_SMBUS_WRITE_BYTE_DATA = 0x0010_0000;
+        /// SMBus read word
         const I2C_FUNC_SMBUS_READ_WORD_DATA = 0x0020_0000;
+        /// SMBus write word
         const I2C_FUNC_SMBUS_WRITE_WORD_DATA = 0x0040_0000;
+        /// SMBus process call
         const I2C_FUNC_SMBUS_PROC_CALL = 0x0080_0000;
+        /// SMBus block read
         const I2C_FUNC_SMBUS_READ_BLOCK_DATA = 0x0100_0000;
+        /// SMBus block write
         const I2C_FUNC_SMBUS_WRITE_BLOCK_DATA  = 0x0200_0000;
+        /// I2C-like block read
         const I2C_FUNC_SMBUS_READ_I2C_BLOCK = 0x0400_0000; /* I2C-like block xfer  */
+        /// I2C-like block write
         const I2C_FUNC_SMBUS_WRITE_I2C_BLOCK = 0x0800_0000; /* w/ 1-byte reg. addr. */
 
+        /// SMBus receive and send byte
         const I2C_FUNC_SMBUS_BYTE = (I2CFunctions::I2C_FUNC_SMBUS_READ_BYTE.bits() |
                                      I2CFunctions::I2C_FUNC_SMBUS_WRITE_BYTE.bits());
+        /// SMBus read and write byte
         const I2C_FUNC_SMBUS_BYTE_DATA = (I2CFunctions::I2C_FUNC_SMBUS_READ_BYTE_DATA.bits() |
                                           I2CFunctions::I2C_FUNC_SMBUS_WRITE_BYTE_DATA.bits());
+        /// SMBus read and write word
         const I2C_FUNC_SMBUS_WORD_DATA = (I2CFunctions::I2C_FUNC_SMBUS_READ_WORD_DATA.bits() |
                                           I2CFunctions::I2C_FUNC_SMBUS_WRITE_WORD_DATA.bits());
+        /// SMBus block read and write
         const I2C_FUNC_SMBUS_BLOCK_DATA = (I2CFunctions::I2C_FUNC_SMBUS_READ_BLOCK_DATA.bits() |
                                            I2CFunctions::I2C_FUNC_SMBUS_WRITE_BLOCK_DATA.bits());
+        /// I2C-like block read and write
         const I2C_FUNC_SMBUS_I2C_BLOCK = (I2CFunctions::I2C_FUNC_SMBUS_READ_I2C_BLOCK.bits() |
                                           I2CFunctions::I2C_FUNC_SMBUS_WRITE_I2C_BLOCK.bits());
+        /// Everything that can be emulated on top of plain i2c-level commands
         const I2C_FUNC_SMBUS_EMUL = (I2CFunctions::I2C_FUNC_SMBUS_QUICK.bits() |
                                      I2CFunctions::I2C_FUNC_SMBUS_BYTE.bits() |
                                      I2CFunctions::I2C_FUNC_SMBUS_BYTE_DATA.bits() |
@@ -76,8 +126,70 @@ bitflags! {
     }
 }
 
+impl I2CFunctions {
+    /// Names of the individual capabilities which are set, in the order
+    /// `i2cdetect -F` lists them
+    pub fn describe(&self) -> Vec<&'static str> {
+        const NAMES: [(I2CFunctions, &str); 18] = [
+            (I2CFunctions::I2C_FUNC_I2C, "I2C"),
+            (I2CFunctions::I2C_FUNC_10BIT_ADDR, "10BIT_ADDR"),
+            (
+                I2CFunctions::I2C_FUNC_PROTOCOL_MANGLING,
+                "PROTOCOL_MANGLING",
+            ),
+            (I2CFunctions::I2C_FUNC_SMBUS_PEC, "SMBUS_PEC"),
+            (I2CFunctions::I2C_FUNC_NOSTART, "NOSTART"),
+            (I2CFunctions::I2C_FUNC_SMBUS_QUICK, "SMBUS_QUICK"),
+            (I2CFunctions::I2C_FUNC_SMBUS_READ_BYTE, "SMBUS_READ_BYTE"),
+            (I2CFunctions::I2C_FUNC_SMBUS_WRITE_BYTE, "SMBUS_WRITE_BYTE"),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_READ_BYTE_DATA,
+                "SMBUS_READ_BYTE_DATA",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_WRITE_BYTE_DATA,
+                "SMBUS_WRITE_BYTE_DATA",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_READ_WORD_DATA,
+                "SMBUS_READ_WORD_DATA",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_WRITE_WORD_DATA,
+                "SMBUS_WRITE_WORD_DATA",
+            ),
+            (I2CFunctions::I2C_FUNC_SMBUS_PROC_CALL, "SMBUS_PROC_CALL"),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_READ_BLOCK_DATA,
+                "SMBUS_READ_BLOCK_DATA",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_WRITE_BLOCK_DATA,
+                "SMBUS_WRITE_BLOCK_DATA",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_BLOCK_PROC_CALL,
+                "SMBUS_BLOCK_PROC_CALL",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_READ_I2C_BLOCK,
+                "SMBUS_READ_I2C_BLOCK",
+            ),
+            (
+                I2CFunctions::I2C_FUNC_SMBUS_WRITE_I2C_BLOCK,
+                "SMBUS_WRITE_I2C_BLOCK",
+            ),
+        ];
+        NAMES
+            .iter()
+            .filter(|&&(flag, _)| self.contains(flag))
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
 /// As specified in SMBus standard
-const I2C_SMBUS_BLOCK_MAX: u8 = 32;
+const I2C_SMBUS_BLOCK_MAX: u8 = SMBUS_BLOCK_MAX as u8;
 
 // In C, this is a union, but the largest item is clearly
 // the largest.  Rust does not have unions at this time,
@@ -157,11 +269,19 @@ pub struct i2c_rdwr_ioctl_data<'a> {
 mod ioctl {
     pub use super::i2c_rdwr_ioctl_data;
     pub use super::i2c_smbus_ioctl_data;
-    use super::{I2C_PEC, I2C_RDWR, I2C_SLAVE, I2C_SLAVE_FORCE, I2C_SMBUS};
+    use super::{
+        I2C_FUNCS, I2C_PEC, I2C_RDWR, I2C_RETRIES, I2C_SLAVE, I2C_SLAVE_FORCE, I2C_SMBUS,
+        I2C_TENBIT, I2C_TIMEOUT,
+    };
+    use libc::c_ulong;
 
     ioctl_write_int_bad!(set_i2c_slave_address, I2C_SLAVE);
     ioctl_write_int_bad!(set_i2c_slave_address_force, I2C_SLAVE_FORCE);
+    ioctl_write_int_bad!(set_i2c_tenbit, I2C_TENBIT);
     ioctl_write_int_bad!(set_smbus_pec, I2C_PEC);
+    ioctl_write_int_bad!(set_retries, I2C_RETRIES);
+    ioctl_write_int_bad!(set_timeout, I2C_TIMEOUT);
+    ioctl_read_bad!(get_functionality, I2C_FUNCS, c_ulong);
     ioctl_write_ptr_bad!(i2c_smbus, I2C_SMBUS, i2c_smbus_ioctl_data);
     ioctl_write_ptr_bad!(i2c_rdwr, I2C_RDWR, i2c_rdwr_ioctl_data);
 }
@@ -180,6 +300,37 @@ pub fn i2c_set_slave_address_force(fd: RawFd, slave_address: u16) -> Result<(),
     Ok(())
 }
 
+pub fn i2c_set_tenbit(fd: RawFd, enable: bool) -> Result<(), nix::Error> {
+    unsafe {
+        ioctl::set_i2c_tenbit(fd, i32::from(enable))?;
+    }
+    Ok(())
+}
+
+pub fn i2c_set_retries(fd: RawFd, count: u32) -> Result<(), nix::Error> {
+    let count = i32::try_from(count).unwrap_or(i32::MAX);
+    unsafe {
+        ioctl::set_retries(fd, count)?;
+    }
+    Ok(())
+}
+
+/// Set the adapter timeout, in units of 10ms
+pub fn i2c_set_timeout(fd: RawFd, timeout: i32) -> Result<(), nix::Error> {
+    unsafe {
+        ioctl::set_timeout(fd, timeout)?;
+    }
+    Ok(())
+}
+
+pub fn i2c_get_functionality(fd: RawFd) -> Result<I2CFunctions, nix::Error> {
+    let mut funcs: libc::c_ulong = 0;
+    unsafe {
+        ioctl::get_functionality(fd, &mut funcs)?;
+    }
+    Ok(I2CFunctions::from_bits_truncate(funcs as u32))
+}
+
 pub fn i2c_set_smbus_pec(fd: RawFd, enable: bool) -> Result<(), nix::Error> {
     unsafe {
         ioctl::set_smbus_pec(fd, i32::from(enable))?;
@@ -205,6 +356,26 @@ unsafe fn i2c_smbus_access(
     ioctl::i2c_smbus(fd, &args).map(drop)
 }
 
+/// Issue an SMBus transaction with a caller-provided `i2c_smbus_data`
+///
+/// The buffer has the size of the kernel union, which is the most the kernel
+/// reads or writes whatever the size requested.
+pub fn i2c_smbus_transfer(
+    fd: RawFd,
+    read_write: u8,
+    command: u8,
+    size: u32,
+    data: &mut [u8; (I2C_SMBUS_BLOCK_MAX + 2) as usize],
+) -> Result<(), I2CError> {
+    let args = i2c_smbus_ioctl_data {
+        read_write,
+        command,
+        size,
+        data: data.as_mut_ptr() as *mut i2c_smbus_data,
+    };
+    unsafe { ioctl::i2c_smbus(fd, &args).map(drop) }
+}
+
 #[inline]
 pub fn i2c_smbus_write_quick(fd: RawFd, bit: bool) -> Result<(), I2CError> {
     let read_write = if bit {
@@ -281,6 +452,7 @@ pub fn i2c_smbus_write_byte_data(fd: RawFd, register: u8, value: u8) -> Result<(
     }
 }
 
+/// Read a word register with an `I2C_SMBUS` ioctl
 #[inline]
 pub fn i2c_smbus_read_word_data(fd: RawFd, register: u8) -> Result<u16, I2CError> {
     let mut data = i2c_smbus_data::empty();
@@ -294,6 +466,8 @@ pub fn i2c_smbus_read_word_data(fd: RawFd, register: u8) -> Result<u16, I2CError
         )?;
     };
 
+    // the kernel stores the word in a native `__u16`, having already put the
+    // low byte on the bus first
     Ok(Cursor::new(&data.block[..])
         .read_u16::<NativeEndian>()
         .unwrap())
@@ -340,6 +514,54 @@ pub fn i2c_smbus_process_call(fd: RawFd, register: u8, value: u16) -> Result<u16
 
 #[inline]
 pub fn i2c_smbus_read_block_data(fd: RawFd, register: u8) -> Result<Vec<u8>, I2CError> {
+    let mut buf = [0; I2C_SMBUS_BLOCK_MAX as usize];
+    let count = i2c_smbus_read_block_data_into(fd, register, &mut buf)?;
+    Ok(buf[..count].to_vec())
+}
+
+/// Read a block with an `I2C_SMBUS` ioctl, returning the number of bytes
+/// stored in `buf`
+pub fn i2c_smbus_read_block_data_into(
+    fd: RawFd,
+    register: u8,
+    buf: &mut [u8; I2C_SMBUS_BLOCK_MAX as usize],
+) -> Result<usize, I2CError> {
+    let data = read_block_data(fd, register)?;
+    let block = block_data(&data, I2C_SMBUS_BLOCK_MAX)?;
+    buf[..block.len()].copy_from_slice(block);
+    Ok(block.len())
+}
+
+/// Read a block into a buffer of any length, failing with `EOVERFLOW` if
+/// the block does not fit
+pub fn i2c_smbus_read_block_data_into_slice(
+    fd: RawFd,
+    register: u8,
+    buf: &mut [u8],
+) -> Result<usize, I2CError> {
+    let data = read_block_data(fd, register)?;
+    let block = block_data(&data, I2C_SMBUS_BLOCK_MAX)?;
+    if block.len() > buf.len() {
+        return Err(nix::Error::EOVERFLOW);
+    }
+    buf[..block.len()].copy_from_slice(block);
+    Ok(block.len())
+}
+
+/// Read a block, returning the count as the adapter reported it, even if
+/// it is larger than a block; at most 32 bytes are stored in `buf`
+pub fn i2c_smbus_read_block_data_raw(
+    fd: RawFd,
+    register: u8,
+    buf: &mut [u8; I2C_SMBUS_BLOCK_MAX as usize],
+) -> Result<u8, I2CError> {
+    let data = read_block_data(fd, register)?;
+    let (count, block) = raw_block_data(&data);
+    buf[..block.len()].copy_from_slice(block);
+    Ok(count)
+}
+
+fn read_block_data(fd: RawFd, register: u8) -> Result<i2c_smbus_data, I2CError> {
     let mut data = i2c_smbus_data::empty();
     unsafe {
         i2c_smbus_access(
@@ -350,18 +572,32 @@ pub fn i2c_smbus_read_block_data(fd: RawFd, register: u8) -> Result<Vec<u8>, I2C
             &mut data,
         )?;
     }
-
-    // create a vector from the data in the block starting at byte
-    // 1 and ending after count bytes after that
-    let count = data.block[0];
-    Ok((data.block[1..(count + 1) as usize]).to_vec())
+    Ok(data)
 }
 
+#[inline]
 pub fn i2c_smbus_read_i2c_block_data(
     fd: RawFd,
     register: u8,
     len: u8,
 ) -> Result<Vec<u8>, I2CError> {
+    let mut buf = [0; I2C_SMBUS_BLOCK_MAX as usize];
+    let buf = buf.get_mut(..usize::from(len)).ok_or(nix::Error::EINVAL)?;
+    let count = i2c_smbus_read_i2c_block_data_into(fd, register, buf)?;
+    Ok(buf[..count].to_vec())
+}
+
+/// Read up to `buf.len()` bytes into `buf`, failing with `EINVAL` for a
+/// buffer longer than 32 bytes
+pub fn i2c_smbus_read_i2c_block_data_into(
+    fd: RawFd,
+    register: u8,
+    buf: &mut [u8],
+) -> Result<usize, I2CError> {
+    let len = u8::try_from(buf.len())
+        .ok()
+        .filter(|&len| len <= I2C_SMBUS_BLOCK_MAX)
+        .ok_or(nix::Error::EINVAL)?;
     let mut data = i2c_smbus_data::empty();
     data.block[0] = len;
     unsafe {
@@ -374,10 +610,54 @@ pub fn i2c_smbus_read_i2c_block_data(
         )?;
     }
 
-    // create a vector from the data in the block starting at byte
-    // 1 and ending after count bytes after that
+    let block = block_data(&data, len)?;
+    buf[..block.len()].copy_from_slice(block);
+    Ok(block.len())
+}
+
+/// The count of a block read from the device, failing with `EPROTO` when it
+/// is larger than `max`
+///
+/// The kernel validates the counts of SMBus block transfers, but adapters
+/// emulating SMBus in their drivers do not always, and the count indexes
+/// into the block.
+fn block_count(data: &i2c_smbus_data, max: u8) -> Result<usize, I2CError> {
+    let count = data.block[0];
+    if count > max.min(I2C_SMBUS_BLOCK_MAX) {
+        return Err(nix::Error::EPROTO);
+    }
+    Ok(count as usize)
+}
+
+/// The data of a block read from the device, failing with `EPROTO` when its
+/// count is larger than `max`
+fn block_data(data: &i2c_smbus_data, max: u8) -> Result<&[u8], I2CError> {
+    // the block starts at byte 1 and is followed by count bytes
+    let count = block_count(data, max)?;
+    Ok(&data.block[1..(count + 1)])
+}
+
+/// The count of a block read from the device as the adapter reported it,
+/// and as much of its data as fits in a block
+fn raw_block_data(data: &i2c_smbus_data) -> (u8, &[u8]) {
     let count = data.block[0];
-    Ok((data.block[1..(count + 1) as usize]).to_vec())
+    let len = count.min(I2C_SMBUS_BLOCK_MAX) as usize;
+    (count, &data.block[1..(len + 1)])
+}
+
+/// Parse `bytes`, as much of them as fits, as an `i2c_smbus_data` the
+/// kernel returned for a block read with at most `max` bytes, for the fuzz
+/// targets
+///
+/// Returns the data `block_data` and `raw_block_data` find in it.
+#[cfg(fuzzing)]
+pub fn fuzz_block_data(bytes: &[u8], max: u8) -> (Option<Vec<u8>>, u8, Vec<u8>) {
+    let mut data = i2c_smbus_data::empty();
+    let len = bytes.len().min(data.block.len());
+    data.block[..len].copy_from_slice(&bytes[..len]);
+    let block = block_data(&data, max).ok().map(<[u8]>::to_vec);
+    let (count, raw) = raw_block_data(&data);
+    (block, count, raw.to_vec())
 }
 
 #[inline]
@@ -395,7 +675,7 @@ fn copy_to_i2c_block_data(values: &[u8], max_size: usize) -> i2c_smbus_data {
 
 #[inline]
 pub fn i2c_smbus_write_block_data(fd: RawFd, register: u8, values: &[u8]) -> Result<(), I2CError> {
-    let mut data = copy_to_i2c_block_data(values, 32);
+    let mut data = copy_to_i2c_block_data(values, SMBUS_BLOCK_MAX);
     unsafe {
         i2c_smbus_access(
             fd,
@@ -413,7 +693,7 @@ pub fn i2c_smbus_write_i2c_block_data(
     register: u8,
     values: &[u8],
 ) -> Result<(), I2CError> {
-    let mut data = copy_to_i2c_block_data(values, 32);
+    let mut data = copy_to_i2c_block_data(values, SMBUS_BLOCK_MAX);
     unsafe {
         i2c_smbus_access(
             fd,
@@ -442,12 +722,13 @@ pub fn i2c_smbus_process_call_block(
         )?;
     };
 
-    // create a vector from the data in the block starting at byte
-    // 1 and ending after count bytes after that
-    let count = data.block[0];
-    Ok((data.block[1..(count + 1) as usize]).to_vec())
+    // the kernel replaces the request with the response of the read half:
+    // a count byte followed by count bytes
+    Ok(block_data(&data, I2C_SMBUS_BLOCK_MAX)?.to_vec())
 }
 
+/// Issue the messages with an `I2C_RDWR` ioctl, returning the number the
+/// kernel executed
 #[inline]
 pub fn i2c_rdwr(fd: RawFd, values: &mut [i2c_msg]) -> Result<u32, I2CError> {
     let i2c_data = i2c_rdwr_ioctl_data {
@@ -461,3 +742,88 @@ pub fn i2c_rdwr(fd: RawFd, values: &mut [i2c_msg]) -> Result<u32, I2CError> {
     }
     Ok(n as u32)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_block_data_layout() {
+        let data = copy_to_i2c_block_data(&[0xAA, 0xBB, 0xCC], 32);
+        assert_eq!(data.block[0], 3);
+        assert_eq!(data.block[1..4], [0xAA, 0xBB, 0xCC]);
+        assert!(data.block[4..].iter().all(|&b| b == 0));
+
+        let values: Vec<u8> = (0..40).collect();
+        let data = copy_to_i2c_block_data(&values, 32);
+        assert_eq!(data.block[0], 32);
+        assert_eq!(data.block[1..33], values[..32]);
+        assert_eq!(data.block[33], 0);
+    }
+
+    #[test]
+    fn test_i2c_block_read_too_long() {
+        // rejected before the ioctl, which fails with ENOTTY on /dev/null
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let fd = file.as_raw_fd();
+        assert_eq!(
+            i2c_smbus_read_i2c_block_data(fd, 0, 33),
+            Err(nix::Error::EINVAL)
+        );
+        assert_eq!(
+            i2c_smbus_read_i2c_block_data(fd, 0, 32),
+            Err(nix::Error::ENOTTY)
+        );
+        let mut buf = [0; 33];
+        assert_eq!(
+            i2c_smbus_read_i2c_block_data_into(fd, 0, &mut buf),
+            Err(nix::Error::EINVAL)
+        );
+        assert_eq!(
+            i2c_smbus_read_i2c_block_data_into(fd, 0, &mut buf[..32]),
+            Err(nix::Error::ENOTTY)
+        );
+    }
+
+    #[test]
+    fn test_block_count() {
+        let mut data = i2c_smbus_data::empty();
+        data.block[0] = 32;
+        assert_eq!(block_count(&data, I2C_SMBUS_BLOCK_MAX), Ok(32));
+        assert_eq!(block_count(&data, 4), Err(nix::Error::EPROTO));
+        data.block[0] = 33;
+        assert_eq!(
+            block_count(&data, I2C_SMBUS_BLOCK_MAX),
+            Err(nix::Error::EPROTO)
+        );
+        data.block[0] = 255;
+        assert_eq!(block_count(&data, 255), Err(nix::Error::EPROTO));
+    }
+
+    #[test]
+    fn test_block_data_adversarial_counts() {
+        let mut data = i2c_smbus_data::empty();
+        for (i, byte) in data.block.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        for count in 0..=u8::MAX {
+            data.block[0] = count;
+            for max in 0..=u8::MAX {
+                match block_data(&data, max) {
+                    Ok(block) => {
+                        assert!(count <= max && count <= I2C_SMBUS_BLOCK_MAX);
+                        assert_eq!(block.len(), count as usize);
+                        assert_eq!(block.first(), if count > 0 { Some(&1) } else { None });
+                    }
+                    Err(e) => {
+                        assert_eq!(e, nix::Error::EPROTO);
+                        assert!(count > max.min(I2C_SMBUS_BLOCK_MAX));
+                    }
+                }
+            }
+            let (raw_count, raw) = raw_block_data(&data);
+            assert_eq!(raw_count, count);
+            assert_eq!(raw.len(), count.min(I2C_SMBUS_BLOCK_MAX) as usize);
+        }
+    }
+}
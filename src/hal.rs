@@ -0,0 +1,153 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Implementation of the `embedded-hal` 1.0 I2C traits for Linux devices
+
+use crate::core::{Address, I2CMessage};
+use crate::linux::{
+    check_transfer, LinuxI2CDevice, LinuxI2CError, LinuxI2CErrorKind, LinuxI2CMessage,
+};
+use embedded_hal::i2c::{
+    self, ErrorKind, NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress,
+};
+
+impl i2c::Error for LinuxI2CError {
+    fn kind(&self) -> ErrorKind {
+        match LinuxI2CError::kind(self) {
+            LinuxI2CErrorKind::NoDevice => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            LinuxI2CErrorKind::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            LinuxI2CErrorKind::Arbitration => ErrorKind::ArbitrationLoss,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Convert the error of an `embedded-hal` driver into an errno, for code
+/// mixing such drivers with this crate
+///
+/// The errno is chosen so that [`LinuxI2CError::kind`] and the `Error`
+/// implementation above classify the result as the original kind where they
+/// can: a missing acknowledgement of the address becomes `ENXIO`, of data
+/// `EREMOTEIO`, and a lost arbitration `EAGAIN`.  Kinds with no matching
+/// fault code, including an acknowledgement from an unknown source, become
+/// `EIO`.
+impl From<ErrorKind> for LinuxI2CError {
+    fn from(kind: ErrorKind) -> LinuxI2CError {
+        LinuxI2CError::Errno(match kind {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => libc::ENXIO,
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => libc::EREMOTEIO,
+            ErrorKind::ArbitrationLoss => libc::EAGAIN,
+            ErrorKind::Overrun => libc::EOVERFLOW,
+            _ => libc::EIO,
+        })
+    }
+}
+
+impl i2c::ErrorType for LinuxI2CDevice {
+    type Error = LinuxI2CError;
+}
+
+fn transaction(
+    device: &mut LinuxI2CDevice,
+    address: Address,
+    operations: &mut [Operation],
+) -> Result<(), LinuxI2CError> {
+    if operations.is_empty() {
+        return Ok(());
+    }
+    let mut messages: Vec<LinuxI2CMessage> = operations
+        .iter_mut()
+        .map(|operation| match *operation {
+            Operation::Read(ref mut buffer) => LinuxI2CMessage::read(buffer),
+            Operation::Write(buffer) => LinuxI2CMessage::write(buffer),
+        })
+        .collect();
+    let completed = device.transfer_to(address, &mut messages)?;
+    check_transfer(completed, messages.len())
+}
+
+/// Transactions are issued with `I2C_RDWR` to the address passed in, which
+/// need not be the address the device was opened for.
+impl i2c::I2c<SevenBitAddress> for LinuxI2CDevice {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation],
+    ) -> Result<(), LinuxI2CError> {
+        transaction(self, Address::SevenBit(address), operations)
+    }
+}
+
+impl i2c::I2c<TenBitAddress> for LinuxI2CDevice {
+    fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation],
+    ) -> Result<(), LinuxI2CError> {
+        transaction(self, Address::TenBit(address), operations)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal::i2c::{Error, I2c};
+    use std::io;
+
+    fn assert_i2c<T: I2c<SevenBitAddress> + I2c<TenBitAddress>>() {}
+
+    #[test]
+    fn test_implements_i2c() {
+        assert_i2c::<LinuxI2CDevice>();
+    }
+
+    fn hal_kind(error: LinuxI2CError) -> ErrorKind {
+        Error::kind(&error)
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            hal_kind(LinuxI2CError::Errno(libc::ENXIO)),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+        );
+        assert_eq!(
+            hal_kind(LinuxI2CError::Io(io::Error::from_raw_os_error(
+                libc::EREMOTEIO
+            ))),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+        );
+        assert_eq!(
+            hal_kind(LinuxI2CError::Errno(libc::EAGAIN)),
+            ErrorKind::ArbitrationLoss
+        );
+        assert_eq!(
+            hal_kind(LinuxI2CError::Errno(libc::ENOTTY)),
+            ErrorKind::Other
+        );
+    }
+
+    #[test]
+    fn test_from_error_kind() {
+        for kind in [
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            ErrorKind::ArbitrationLoss,
+        ] {
+            assert_eq!(hal_kind(LinuxI2CError::from(kind)), kind);
+        }
+        assert_eq!(
+            LinuxI2CError::from(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown)),
+            LinuxI2CError::Errno(libc::EIO)
+        );
+        assert_eq!(
+            LinuxI2CError::from(ErrorKind::Bus),
+            LinuxI2CError::Errno(libc::EIO)
+        );
+    }
+}
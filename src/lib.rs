@@ -17,7 +17,8 @@
 //! ## Examples
 //!
 //! ### Using the SMBus methods to read from a Wii Nunchuk
-//! ```rust,no_run
+#![cfg_attr(feature = "std", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "std"), doc = "```ignore")]
 //! extern crate i2cdev;
 //!
 //! use std::thread;
@@ -48,7 +49,8 @@
 //! ```
 //!
 //! ### Using the `transfer` API with an individual device
-//! ```rust,no_run
+#![cfg_attr(feature = "std", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "std"), doc = "```ignore")]
 //! extern crate i2cdev;
 //!
 //! use i2cdev::core::*;
@@ -71,8 +73,12 @@
 //! }
 //! ```
 //!
+//! A write followed by a read like this is common enough that
+//! `dev.write_read(&[0x01], &mut read_data)` does the same in one call.
+//!
 //! ### Using `transfer` with `LinuxI2CBus`
-//! ```rust,no_run
+#![cfg_attr(feature = "std", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "std"), doc = "```ignore")]
 //! extern crate i2cdev;
 //!
 //! use i2cdev::core::*;
@@ -97,24 +103,73 @@
 
 #![crate_name = "i2cdev"]
 #![crate_type = "lib"]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate bitflags;
-extern crate byteorder;
-extern crate libc;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate nix;
 
-#[cfg(any(target_os = "linux", target_os = "android"))]
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+#[macro_use]
+mod trace;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
 mod ffi;
 
+/// Entry points for the fuzz targets in `fuzz/`, only built with
+/// `--cfg fuzzing` as `cargo fuzz` does
+#[cfg(all(
+    fuzzing,
+    feature = "std",
+    any(target_os = "linux", target_os = "android")
+))]
+#[doc(hidden)]
+pub mod fuzzing {
+    pub use crate::ffi::fuzz_block_data;
+}
+
 /// Core I2C abstractions
 pub mod core;
 
+/// CRC-8 as used for the SMBus Packet Error Code (PEC)
+pub mod crc8;
+
 /// Linux I2C device support
-#[cfg(any(target_os = "linux", target_os = "android"))]
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
 pub mod linux;
 
+#[cfg(all(
+    feature = "std",
+    feature = "embedded-hal",
+    any(target_os = "linux", target_os = "android")
+))]
+mod hal;
+
+/// Asynchronous access to Linux I2C devices with tokio
+#[cfg(all(
+    feature = "std",
+    feature = "tokio",
+    any(target_os = "linux", target_os = "android")
+))]
+pub mod linux_async;
+
 /// Mock I2C device
+#[cfg(feature = "std")]
 pub mod mock;
+
+/// Retrying operations which fail with transient errors
+#[cfg(feature = "std")]
+pub mod retry;
+
+/// Register maps for devices with many registers
+pub mod regmap;
+
+/// I2C multiplexer support
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub mod mux;
@@ -6,27 +6,38 @@
 // option.  This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use core::{I2CDevice, I2CTransfer};
-use ffi;
-use nix;
+use crate::core::{I2CDevice, I2CDeviceExt, I2CSmbus, I2CTransfer};
+use crate::ffi;
+use crate::trace::HexDump;
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fmt;
-use std::fs::File;
-use std::fs::OpenOptions;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::prelude::*;
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::{AsFd, BorrowedFd};
 use std::os::unix::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+use std::time::Duration;
 
 // Expose these core structs from this module
-pub use core::I2CMessage;
+pub use crate::core::{
+    Address, I2CMessage, I2CMessageFlags, QuickCommand, MAX_TRANSFER_MESSAGES, SMBUS_BLOCK_MAX,
+};
 
 /// Concrete linux I2C device
 pub struct LinuxI2CDevice {
-    devfile: File,
-    slave_address: u16,
+    bus: LinuxI2CBus,
+    slave_address: Address,
     pec: bool,
+    functions: Option<I2CFunctions>,
 }
 
 /// Linux I2C bus
@@ -35,7 +46,16 @@ pub struct LinuxI2CBus {
 }
 
 /// Linux I2C errors
+///
+/// Errors compare equal when they have the same variant and contents.  As
+/// `io::Error` cannot be compared, `Io` errors are compared by their raw OS
+/// error and their kind, ignoring any message.  `Errno(e)` and an `Io` error
+/// carrying the same raw OS error are not equal.
+///
+/// New variants may be added in minor releases, so matches need a wildcard
+/// arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum LinuxI2CError {
     /// Errno from a failing `libc` call. Sourced  from [`nix`].
     ///
@@ -50,6 +70,72 @@ pub enum LinuxI2CError {
     Errno(i32),
     /// Input/output error
     Io(io::Error),
+    /// A transfer stopped before all of its messages were executed, see
+    /// [`LinuxI2CDevice::transfer_all`]
+    PartialTransfer {
+        /// Number of messages executed
+        completed: u32,
+        /// Number of messages submitted
+        total: u32,
+    },
+    /// More messages were submitted in one transfer than the kernel
+    /// accepts, see [`MAX_TRANSFER_MESSAGES`]
+    TooManyMessages {
+        /// Number of messages submitted
+        count: usize,
+    },
+    /// The slave address is claimed by a kernel driver (`EBUSY`), see
+    /// [`LinuxI2CDevice::force_new`]
+    AddressInUse(u16),
+}
+
+/// Classification of a [`LinuxI2CError`], see [`LinuxI2CError::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LinuxI2CErrorKind {
+    /// No device acknowledged its address (`ENXIO` or `ENODEV`)
+    NoDevice,
+    /// The device did not acknowledge a data byte (`EREMOTEIO`)
+    Nack,
+    /// The transfer did not complete in time (`ETIMEDOUT`)
+    Timeout,
+    /// Another master won arbitration of the bus (`EAGAIN`), so the
+    /// transfer may be retried
+    Arbitration,
+    /// Any other input/output failure, including `EIO`
+    Io,
+    /// Any other error, such as an invalid argument or an operation the
+    /// adapter does not support
+    Other,
+}
+
+impl LinuxI2CError {
+    /// Classify the error by the errno it carries
+    ///
+    /// The classification follows the fault codes the kernel documents for
+    /// I2C adapter drivers in `Documentation/i2c/fault-codes.rst`, but not
+    /// every adapter driver sticks to them; some report a missing
+    /// acknowledgement as a plain `EIO`, for instance.
+    pub fn kind(&self) -> LinuxI2CErrorKind {
+        let errno = match *self {
+            LinuxI2CError::Errno(e) => e,
+            LinuxI2CError::Io(ref e) => match e.raw_os_error() {
+                Some(e) => e,
+                None => return LinuxI2CErrorKind::Io,
+            },
+            LinuxI2CError::PartialTransfer { .. } => return LinuxI2CErrorKind::Io,
+            LinuxI2CError::TooManyMessages { .. } => return LinuxI2CErrorKind::Other,
+            LinuxI2CError::AddressInUse(_) => return LinuxI2CErrorKind::Other,
+        };
+        match errno {
+            libc::ENXIO | libc::ENODEV => LinuxI2CErrorKind::NoDevice,
+            libc::EREMOTEIO => LinuxI2CErrorKind::Nack,
+            libc::ETIMEDOUT => LinuxI2CErrorKind::Timeout,
+            libc::EAGAIN => LinuxI2CErrorKind::Arbitration,
+            libc::EIO => LinuxI2CErrorKind::Io,
+            _ => LinuxI2CErrorKind::Other,
+        }
+    }
 }
 
 impl From<nix::Error> for LinuxI2CError {
@@ -64,11 +150,16 @@ impl From<io::Error> for LinuxI2CError {
     }
 }
 
+/// Errno values are kept as the raw OS error of the `io::Error`
 impl From<LinuxI2CError> for io::Error {
+    #[allow(clippy::io_other_error)] // io::Error::other is newer than the MSRV
     fn from(e: LinuxI2CError) -> io::Error {
         match e {
             LinuxI2CError::Io(e) => e,
             LinuxI2CError::Errno(e) => io::Error::from_raw_os_error(e),
+            LinuxI2CError::PartialTransfer { .. } => io::Error::new(io::ErrorKind::Other, e),
+            LinuxI2CError::TooManyMessages { .. } => io::Error::new(io::ErrorKind::InvalidInput, e),
+            LinuxI2CError::AddressInUse(_) => io::Error::from_raw_os_error(libc::EBUSY),
         }
     }
 }
@@ -81,6 +172,22 @@ impl fmt::Display for LinuxI2CError {
                 fmt::Display::fmt(&error, f)
             }
             LinuxI2CError::Io(ref e) => fmt::Display::fmt(e, f),
+            LinuxI2CError::PartialTransfer { completed, total } => write!(
+                f,
+                "transfer stopped after {} of {} messages",
+                completed, total
+            ),
+            LinuxI2CError::TooManyMessages { count } => write!(
+                f,
+                "{} messages exceed the limit of {} per transfer",
+                count, MAX_TRANSFER_MESSAGES
+            ),
+            LinuxI2CError::AddressInUse(address) => write!(
+                f,
+                "address 0x{:02x} is in use by a kernel driver, \
+                 LinuxI2CDevice::force_new can access it anyway",
+                address
+            ),
         }
     }
 }
@@ -89,14 +196,155 @@ impl Error for LinuxI2CError {
     fn cause(&self) -> Option<&dyn Error> {
         match *self {
             LinuxI2CError::Io(ref e) => Some(e),
-            LinuxI2CError::Errno(_) => None,
+            LinuxI2CError::Errno(_)
+            | LinuxI2CError::PartialTransfer { .. }
+            | LinuxI2CError::TooManyMessages { .. }
+            | LinuxI2CError::AddressInUse(_) => None,
+        }
+    }
+}
+
+/// Convert the error from setting the slave address, telling a claimed
+/// address apart from other failures
+fn address_error(e: nix::Error, address: Address) -> LinuxI2CError {
+    match e {
+        nix::Error::EBUSY => LinuxI2CError::AddressInUse(address.value()),
+        e => e.into(),
+    }
+}
+
+/// The parts of a [`LinuxI2CError`] which are compared and hashed
+#[derive(PartialEq, Eq, Hash)]
+enum ErrorKey {
+    Errno(i32),
+    Io(Option<i32>, io::ErrorKind),
+    PartialTransfer(u32, u32),
+    TooManyMessages(usize),
+    AddressInUse(u16),
+}
+
+impl LinuxI2CError {
+    fn key(&self) -> ErrorKey {
+        match *self {
+            LinuxI2CError::Errno(e) => ErrorKey::Errno(e),
+            LinuxI2CError::Io(ref e) => ErrorKey::Io(e.raw_os_error(), e.kind()),
+            LinuxI2CError::PartialTransfer { completed, total } => {
+                ErrorKey::PartialTransfer(completed, total)
+            }
+            LinuxI2CError::TooManyMessages { count } => ErrorKey::TooManyMessages(count),
+            LinuxI2CError::AddressInUse(address) => ErrorKey::AddressInUse(address),
+        }
+    }
+}
+
+impl PartialEq for LinuxI2CError {
+    fn eq(&self, other: &LinuxI2CError) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for LinuxI2CError {}
+
+impl Hash for LinuxI2CError {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// Options applied when opening a [`LinuxI2CDevice`]
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+/// # fn main() -> Result<(), LinuxI2CError> {
+/// let device = LinuxI2CDevice::builder()
+///     .pec(true)
+///     .retries(3)
+///     .timeout(Duration::from_millis(100))
+///     .open("/dev/i2c-1", 0x48)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LinuxI2CDeviceBuilder {
+    pec: bool,
+    retries: Option<u32>,
+    timeout: Option<Duration>,
+    ten_bit: bool,
+}
+
+impl LinuxI2CDeviceBuilder {
+    /// Create a builder with PEC disabled and the adapter defaults for
+    /// retries and timeout
+    pub fn new() -> LinuxI2CDeviceBuilder {
+        LinuxI2CDeviceBuilder::default()
+    }
+
+    /// Enable or disable SMBus PEC, see [`LinuxI2CDevice::set_smbus_pec`]
+    pub fn pec(mut self, enable: bool) -> LinuxI2CDeviceBuilder {
+        self.pec = enable;
+        self
+    }
+
+    /// Set the adapter retries, see [`LinuxI2CBus::set_retries`]
+    pub fn retries(mut self, count: u32) -> LinuxI2CDeviceBuilder {
+        self.retries = Some(count);
+        self
+    }
+
+    /// Set the adapter timeout, see [`LinuxI2CBus::set_timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> LinuxI2CDeviceBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Treat the slave address as a 10-bit address even if it fits in 7
+    /// bits
+    ///
+    /// This only widens a 7-bit address; an address above 0x7F has to be
+    /// passed as `Address::TenBit` in any case.
+    pub fn ten_bit(mut self, enable: bool) -> LinuxI2CDeviceBuilder {
+        self.ten_bit = enable;
+        self
+    }
+
+    /// Open the device and apply the options
+    ///
+    /// The adapter options are applied first, then the slave address and
+    /// PEC.  If any step fails the device is closed again and the error is
+    /// returned.
+    pub fn open<P: AsRef<Path>, A: Into<Address>>(
+        &self,
+        path: P,
+        slave_address: A,
+    ) -> Result<LinuxI2CDevice, LinuxI2CError> {
+        let mut slave_address = slave_address.into();
+        if let (true, Address::SevenBit(address)) = (self.ten_bit, slave_address) {
+            if slave_address.is_valid() {
+                slave_address = Address::TenBit(u16::from(address));
+            }
+        }
+        let mut device = LinuxI2CDevice {
+            bus: LinuxI2CBus::new(path)?,
+            slave_address: Address::SevenBit(0), // will be set later
+            pec: false,
+            functions: None,
+        };
+        if let Some(count) = self.retries {
+            device.set_retries(count)?;
+        }
+        if let Some(timeout) = self.timeout {
+            device.set_timeout(timeout)?;
         }
+        device.set_slave_address(slave_address)?;
+        device.set_smbus_pec(self.pec)?;
+        Ok(device)
     }
 }
 
 impl AsRawFd for LinuxI2CDevice {
     fn as_raw_fd(&self) -> RawFd {
-        self.devfile.as_raw_fd()
+        self.bus.as_raw_fd()
     }
 }
 
@@ -106,40 +354,115 @@ impl AsRawFd for LinuxI2CBus {
     }
 }
 
+impl AsFd for LinuxI2CDevice {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.bus.as_fd()
+    }
+}
+
+impl AsFd for LinuxI2CBus {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.devfile.as_fd()
+    }
+}
+
+/// The bus takes ownership of the file descriptor and closes it when
+/// dropped.
+impl FromRawFd for LinuxI2CBus {
+    unsafe fn from_raw_fd(fd: RawFd) -> LinuxI2CBus {
+        LinuxI2CBus {
+            devfile: File::from_raw_fd(fd),
+        }
+    }
+}
+
 impl LinuxI2CDevice {
     /// Create a new I2CDevice for the specified path
-    pub fn new<P: AsRef<Path>>(
+    ///
+    /// The slave address may be given as a plain `u16` or as an
+    /// [`Address`]; 10-bit addresses enable ten bit addressing on the
+    /// device.  A plain address is the 7-bit one, without the read/write
+    /// bit; see [`addr_8bit_to_7bit`](../core/fn.addr_8bit_to_7bit.html)
+    /// for addresses given in the shifted 8-bit form.
+    pub fn new<P: AsRef<Path>, A: Into<Address>>(
         path: P,
-        slave_address: u16,
+        slave_address: A,
     ) -> Result<LinuxI2CDevice, LinuxI2CError> {
-        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        LinuxI2CDevice::builder().open(path, slave_address)
+    }
+
+    /// Create a new I2CDevice from a file descriptor of an i2c-dev bus
+    /// which is already open
+    ///
+    /// The slave address is set as in [`new`](#method.new), and ten bit
+    /// addressing is switched off first for a 7-bit address, in case the
+    /// previous user of the descriptor left it on.
+    ///
+    /// # Safety
+    /// The device takes ownership of `fd`, which must be an open file
+    /// descriptor that nothing else closes or uses as an owned file.  It is
+    /// closed when the device is dropped, or before returning if an error
+    /// occurs.
+    pub unsafe fn from_raw_fd<A: Into<Address>>(
+        fd: RawFd,
+        slave_address: A,
+    ) -> Result<LinuxI2CDevice, LinuxI2CError> {
+        LinuxI2CDevice::from_bus(LinuxI2CBus::from_raw_fd(fd), slave_address.into())
+    }
+
+    /// Bind a device to an open bus, resetting ten bit addressing and PEC
+    /// as they may have been left on
+    fn from_bus(bus: LinuxI2CBus, slave_address: Address) -> Result<LinuxI2CDevice, LinuxI2CError> {
         let mut device = LinuxI2CDevice {
-            devfile: file,
-            slave_address: 0, // will be set later
+            bus,
+            slave_address: Address::SevenBit(0), // will be set later
             pec: false,
+            functions: None,
         };
+        if !slave_address.is_ten_bit() {
+            ffi::i2c_set_tenbit(device.as_raw_fd(), false)?;
+        }
         device.set_slave_address(slave_address)?;
         device.set_smbus_pec(false)?;
         Ok(device)
     }
 
+    /// Start configuring a device which is opened with
+    /// [`LinuxI2CDeviceBuilder::open`]
+    pub fn builder() -> LinuxI2CDeviceBuilder {
+        LinuxI2CDeviceBuilder::new()
+    }
+
+    /// Create a new I2CDevice for a 10-bit slave address
+    ///
+    /// Ten bit addressing is enabled before the address is set, which fails
+    /// with an `Unsupported` error if the adapter does not advertise
+    /// `I2C_FUNC_10BIT_ADDR`.  Addresses above 0x3FF are rejected with
+    /// `EINVAL`.
+    pub fn new_10bit<P: AsRef<Path>>(
+        path: P,
+        slave_address: u16,
+    ) -> Result<LinuxI2CDevice, LinuxI2CError> {
+        LinuxI2CDevice::new(path, Address::TenBit(slave_address))
+    }
+
     /// Create a new I2CDevice for the specified path, without checking if the
     /// device is bound to a driver
     ///
     /// # Safety
     /// Using this can seriously confuse the original driver, and may cause all
     /// future communication to perform the wrong operations and/or return wrong results.
-    pub unsafe fn force_new<P: AsRef<Path>>(
+    pub unsafe fn force_new<P: AsRef<Path>, A: Into<Address>>(
         path: P,
-        slave_address: u16,
+        slave_address: A,
     ) -> Result<LinuxI2CDevice, LinuxI2CError> {
-        let file = OpenOptions::new().read(true).write(true).open(path)?;
         let mut device = LinuxI2CDevice {
-            devfile: file,
-            slave_address: 0, // will be set later
+            bus: LinuxI2CBus::new(path)?,
+            slave_address: Address::SevenBit(0), // will be set later
             pec: false,
+            functions: None,
         };
-        device.force_set_slave_address(slave_address)?;
+        device.force_set_slave_address(slave_address.into())?;
         device.set_smbus_pec(false)?;
         Ok(device)
     }
@@ -147,30 +470,124 @@ impl LinuxI2CDevice {
     /// Set the slave address for this device
     ///
     /// Typically the address is expected to be 7-bits but 10-bit addresses
-    /// may be supported by the kernel driver in some cases.  Little validation
-    /// is done in Rust as the kernel is good at making sure things are valid.
+    /// may be supported by the kernel driver in some cases.  Addresses out of
+    /// range for their kind are rejected with `EINVAL`; beyond that little
+    /// validation is done in Rust as the kernel is good at making sure
+    /// things are valid.
     ///
     /// Note that if you have created a device using
     /// `I2Device::new(...)` it is not necesasry to call this method
     /// (it is done internally).  Calling this method is only
     /// necessary if you need to change the slave device and you do
     /// not want to create a new device.
-    pub fn set_slave_address(&mut self, slave_address: u16) -> Result<(), LinuxI2CError> {
-        ffi::i2c_set_slave_address(self.as_raw_fd(), slave_address)?;
-        self.slave_address = slave_address;
-        Ok(())
+    ///
+    /// If a kernel driver has claimed the address, this fails with
+    /// `AddressInUse`.
+    pub fn set_slave_address<A: Into<Address>>(
+        &mut self,
+        slave_address: A,
+    ) -> Result<(), LinuxI2CError> {
+        let slave_address = slave_address.into();
+        self.prepare_slave_address(slave_address)?;
+        let result = ffi::i2c_set_slave_address(self.as_raw_fd(), slave_address.value())
+            .map_err(|e| address_error(e, slave_address));
+        self.finish_slave_address(slave_address, result)
+    }
+
+    /// The numeric slave address this device is bound to
+    ///
+    /// Use [`address`](#method.address) to also tell whether it is a 10-bit
+    /// address.
+    pub fn slave_address(&self) -> u16 {
+        self.slave_address.value()
+    }
+
+    /// The slave address this device is bound to, 7-bit or 10-bit
+    pub fn address(&self) -> Address {
+        self.slave_address
     }
 
     /// Set the slave address for this device, even if it is already in use
     /// by a driver
     ///
     /// This is private; use `force_new` instead.
-    unsafe fn force_set_slave_address(&mut self, slave_address: u16) -> Result<(), LinuxI2CError> {
-        ffi::i2c_set_slave_address_force(self.as_raw_fd(), slave_address)?;
+    unsafe fn force_set_slave_address(
+        &mut self,
+        slave_address: Address,
+    ) -> Result<(), LinuxI2CError> {
+        self.prepare_slave_address(slave_address)?;
+        let result = ffi::i2c_set_slave_address_force(self.as_raw_fd(), slave_address.value())
+            .map_err(From::from);
+        self.finish_slave_address(slave_address, result)
+    }
+
+    /// Validate the address and switch ten bit addressing on or off to
+    /// match it, which must happen before the kernel will accept it
+    fn prepare_slave_address(&mut self, slave_address: Address) -> Result<(), LinuxI2CError> {
+        if !slave_address.is_valid() {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        if slave_address.is_ten_bit() != self.slave_address.is_ten_bit() {
+            self.switch_ten_bit(slave_address.is_ten_bit())?;
+        }
+        Ok(())
+    }
+
+    /// Record the new address once the kernel accepted it
+    ///
+    /// If it was rejected, ten bit addressing is switched back to match the
+    /// old address, which stays in place, and the original error returned.
+    fn finish_slave_address(
+        &mut self,
+        slave_address: Address,
+        result: Result<(), LinuxI2CError>,
+    ) -> Result<(), LinuxI2CError> {
+        if let Err(e) = result {
+            let ten_bit = self.slave_address.is_ten_bit();
+            if slave_address.is_ten_bit() != ten_bit {
+                let _ = self.switch_ten_bit(ten_bit);
+            }
+            return Err(e);
+        }
+        self.slave_address = slave_address;
+        Ok(())
+    }
+
+    /// Switch ten bit addressing on or off for the current slave address
+    ///
+    /// Enabling it fails with an `Unsupported` error if the adapter does
+    /// not advertise `I2C_FUNC_10BIT_ADDR`.  Disabling it fails with
+    /// `EINVAL` if the current address does not fit in 7 bits.  10-bit
+    /// addresses above 0x3FF are never accepted.
+    pub fn set_ten_bit_addressing(&mut self, enable: bool) -> Result<(), LinuxI2CError> {
+        let value = self.slave_address.value();
+        let slave_address = if enable {
+            Address::TenBit(value)
+        } else {
+            Address::SevenBit(value as u8)
+        };
+        if u16::from(slave_address) != value || !slave_address.is_valid() {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        self.switch_ten_bit(enable)?;
         self.slave_address = slave_address;
         Ok(())
     }
 
+    fn switch_ten_bit(&mut self, enable: bool) -> Result<(), LinuxI2CError> {
+        if enable
+            && !self
+                .functionality()?
+                .contains(I2CFunctions::I2C_FUNC_10BIT_ADDR)
+        {
+            return Err(LinuxI2CError::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "adapter does not support 10-bit addresses",
+            )));
+        }
+        ffi::i2c_set_tenbit(self.as_raw_fd(), enable).map_err(From::from)
+    }
+
     /// Enable/Disable PEC support for this device
     ///
     /// Used only for SMBus transactions.  This request only has an effect if the
@@ -181,196 +598,1435 @@ impl LinuxI2CDevice {
         self.pec = enable;
         Ok(())
     }
-}
 
-impl I2CDevice for LinuxI2CDevice {
-    type Error = LinuxI2CError;
+    /// Whether PEC was last enabled with `set_smbus_pec`
+    ///
+    /// The kernel accepts the request even when the adapter cannot do PEC,
+    /// so check [`pec_supported`](#method.pec_supported) before relying on
+    /// checksums being verified.
+    pub fn pec_enabled(&self) -> bool {
+        self.pec
+    }
 
-    /// Read data from the device to fill the provided slice
-    fn read(&mut self, data: &mut [u8]) -> Result<(), LinuxI2CError> {
-        self.devfile.read_exact(data).map_err(From::from).map(drop)
+    /// Check whether the adapter advertises `I2C_FUNC_SMBUS_PEC`
+    pub fn pec_supported(&self) -> Result<bool, LinuxI2CError> {
+        Ok(self
+            .functionality()?
+            .contains(I2CFunctions::I2C_FUNC_SMBUS_PEC))
     }
 
-    /// Write the provided buffer to the device
-    fn write(&mut self, data: &[u8]) -> Result<(), LinuxI2CError> {
-        self.devfile.write(data).map_err(From::from).map(drop)
+    /// Check whether an SMBus operation has to be emulated with plain I2C
+    /// messages because the adapter only supports the latter
+    fn emulate_smbus(&mut self, operation: I2CFunctions) -> bool {
+        // assume everything is supported if the adapter cannot tell
+        let functions = self
+            .cached_functionality()
+            .unwrap_or_else(|_| I2CFunctions::all());
+        needs_emulation(functions, operation)
     }
 
-    /// This sends a single bit to the device, at the place of the Rd/Wr bit
-    fn smbus_write_quick(&mut self, bit: bool) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_quick(self.as_raw_fd(), bit).map_err(From::from)
+    /// The functionality of the adapter, which is only queried once
+    fn cached_functionality(&mut self) -> Result<I2CFunctions, LinuxI2CError> {
+        if let Some(functions) = self.functions {
+            return Ok(functions);
+        }
+        let functions = self.functionality()?;
+        self.functions = Some(functions);
+        Ok(functions)
     }
 
-    /// Read a single byte from a device, without specifying a device register
+    /// Check whether the adapter supports all of `functions`
     ///
-    /// Some devices are so simple that this interface is enough; for
-    /// others, it is a shorthand if you want to read the same register as in
-    /// the previous SMBus command.
-    fn smbus_read_byte(&mut self) -> Result<u8, LinuxI2CError> {
-        ffi::i2c_smbus_read_byte(self.as_raw_fd()).map_err(From::from)
+    /// The functionality is queried from the adapter the first time and
+    /// kept for the lifetime of the device, so checking before every
+    /// operation does not cost an ioctl each time.
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::core::I2CDevice;
+    /// # use i2cdev::linux::{I2CFunctions, LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut dev = LinuxI2CDevice::new("/dev/i2c-1", 0x0b)?;
+    /// if dev.supports(I2CFunctions::I2C_FUNC_SMBUS_PROC_CALL)? {
+    ///     dev.smbus_process_word(0x10, 0x1234)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn supports(&mut self, functions: I2CFunctions) -> Result<bool, LinuxI2CError> {
+        Ok(self.cached_functionality()?.contains(functions))
     }
 
-    /// Write a single byte to a sdevice, without specifying a device register
+    /// Check whether the adapter supports SMBus byte data reads and writes
+    pub fn supports_byte_data(&mut self) -> Result<bool, LinuxI2CError> {
+        self.supports(I2CFunctions::I2C_FUNC_SMBUS_BYTE_DATA)
+    }
+
+    /// Check whether the adapter supports SMBus word data reads and writes
     ///
-    /// This is the opposite operation as smbus_read_byte.  As with read_byte,
-    /// no register is specified.
-    fn smbus_write_byte(&mut self, value: u8) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_byte(self.as_raw_fd(), value).map_err(From::from)
+    /// The word data methods fall back to plain I2C messages on adapters
+    /// which only support those, so they may work even if this is `false`.
+    pub fn supports_word_data(&mut self) -> Result<bool, LinuxI2CError> {
+        self.supports(I2CFunctions::I2C_FUNC_SMBUS_WORD_DATA)
     }
 
-    /// Read a single byte from a device, from a designated register
+    /// Check whether the adapter supports SMBus block reads
     ///
-    /// The register is specified through the Comm byte.
-    fn smbus_read_byte_data(&mut self, register: u8) -> Result<u8, LinuxI2CError> {
-        ffi::i2c_smbus_read_byte_data(self.as_raw_fd(), register).map_err(From::from)
+    /// Many adapters do not, as the length of the read is only known once
+    /// the count byte has been received.
+    pub fn supports_block_read(&mut self) -> Result<bool, LinuxI2CError> {
+        self.supports(I2CFunctions::I2C_FUNC_SMBUS_READ_BLOCK_DATA)
     }
 
-    /// Write a single byte to a specific register on a device
+    /// Check whether the adapter supports SMBus block writes
+    pub fn supports_block_write(&mut self) -> Result<bool, LinuxI2CError> {
+        self.supports(I2CFunctions::I2C_FUNC_SMBUS_WRITE_BLOCK_DATA)
+    }
+
+    /// Check whether the adapter supports I2C block reads and writes,
+    /// which have no count byte
+    pub fn supports_i2c_block(&mut self) -> Result<bool, LinuxI2CError> {
+        self.supports(I2CFunctions::I2C_FUNC_SMBUS_I2C_BLOCK)
+    }
+
+    /// Query the functionality supported by the adapter of this device
     ///
-    /// The register is specified through the Comm byte.
-    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_byte_data(self.as_raw_fd(), register, value).map_err(From::from)
+    /// Drivers can use this to check for operations such as
+    /// `I2C_FUNC_SMBUS_READ_BLOCK_DATA` up-front rather than getting an
+    /// opaque error from the kernel when using them.
+    pub fn functionality(&self) -> Result<I2CFunctions, LinuxI2CError> {
+        self.bus.functionality()
     }
 
-    /// Read 2 bytes form a given register on a device
-    fn smbus_read_word_data(&mut self, register: u8) -> Result<u16, LinuxI2CError> {
-        ffi::i2c_smbus_read_word_data(self.as_raw_fd(), register).map_err(From::from)
+    /// Set the number of times the adapter retries a transfer
+    ///
+    /// See [`LinuxI2CBus::set_retries`].
+    pub fn set_retries(&mut self, count: u32) -> Result<(), LinuxI2CError> {
+        self.bus.set_retries(count)
     }
 
-    /// Write 2 bytes to a given register on a device
-    fn smbus_write_word_data(&mut self, register: u8, value: u16) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_word_data(self.as_raw_fd(), register, value).map_err(From::from)
+    /// Set how long the adapter waits for a transfer to complete
+    ///
+    /// See [`LinuxI2CBus::set_timeout`].
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), LinuxI2CError> {
+        self.bus.set_timeout(timeout)
     }
 
-    /// Select a register, send 16 bits of data to it, and read 16 bits of data
-    fn smbus_process_word(&mut self, register: u8, value: u16) -> Result<u16, LinuxI2CError> {
-        ffi::i2c_smbus_process_call(self.as_raw_fd(), register, value).map_err(From::from)
+    /// Create a second handle to this device sharing the same open file
+    ///
+    /// Like [`File::try_clone`](std::fs::File::try_clone), this duplicates
+    /// the file descriptor rather than reopening the device.  The kernel
+    /// keeps the slave address, PEC and ten bit addressing settings with
+    /// the open file, so changing them through one handle also changes them
+    /// for the other, even though the cached state in each handle is not
+    /// updated.
+    ///
+    /// Each call such as `transfer` or an SMBus method is a single atomic
+    /// operation, but a sequence of calls is not: plain `write` followed by
+    /// `read` from one thread can be interleaved with calls from another,
+    /// so use your own locking around such sequences.
+    pub fn try_clone(&self) -> Result<LinuxI2CDevice, LinuxI2CError> {
+        Ok(LinuxI2CDevice {
+            bus: self.bus.try_clone()?,
+            slave_address: self.slave_address,
+            pec: self.pec,
+            functions: self.functions,
+        })
     }
 
-    /// Read a block of up to 32 bytes from a device
+    /// Borrow the bus this device is on
     ///
-    /// The actual number of bytes available to read is returned in the count
-    /// byte.  This code returns a correctly sized vector containing the
-    /// count bytes read from the device.
-    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, LinuxI2CError> {
-        ffi::i2c_smbus_read_block_data(self.as_raw_fd(), register).map_err(From::from)
+    /// The bus shares the underlying file with the device, which avoids
+    /// reopening the path to use the multi-address `I2CTransfer` API.  The
+    /// bus ignores the slave address of the device; each message carries its
+    /// own address.
+    pub fn as_bus(&self) -> &LinuxI2CBus {
+        &self.bus
     }
 
-    /// Read a block of up to 32 bytes from a device via i2c_smbus_i2c_read_block_data
-    fn smbus_read_i2c_block_data(
-        &mut self,
-        register: u8,
-        len: u8,
-    ) -> Result<Vec<u8>, LinuxI2CError> {
-        ffi::i2c_smbus_read_i2c_block_data(self.as_raw_fd(), register, len).map_err(From::from)
+    /// Mutably borrow the bus this device is on
+    ///
+    /// See [`as_bus`](#method.as_bus).  Bus methods which set the slave
+    /// address themselves, such as scanning, leave the file pointing at
+    /// another address than the device expects; call `set_slave_address`
+    /// afterwards to restore it.
+    pub fn as_bus_mut(&mut self) -> &mut LinuxI2CBus {
+        &mut self.bus
     }
 
-    /// Write a block of up to 32 bytes to a device
+    /// Convert this device into the bus it is on, reusing the same file
     ///
-    /// The opposite of the Block Read command, this writes up to 32 bytes to
-    /// a device, to a designated register that is specified through the
-    /// Comm byte. The amount of data is specified in the Count byte.
-    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+    /// The slave address of the device is ignored by the bus; each message
+    /// carries its own address.
+    pub fn into_bus(self) -> LinuxI2CBus {
+        self.bus
     }
 
-    /// Write a block of up to 32 bytes from a device via i2c_smbus_i2c_write_block_data
-    fn smbus_write_i2c_block_data(
+    /// Write a buffer of any length to consecutive registers with I2C block
+    /// writes of up to 32 bytes
+    ///
+    /// Each chunk is written to `start_register` plus its offset in `data`,
+    /// so the device has to store block writes in consecutive registers;
+    /// devices like the PCA9956B only do so when their auto-increment bit
+    /// is set in `start_register`, which is up to the caller.  The write
+    /// fails with `EINVAL` before anything is sent if the registers would
+    /// run past 0xFF.  If a chunk fails, the chunks before it have already
+    /// been written.
+    pub fn smbus_write_block_chunked(
         &mut self,
-        register: u8,
-        values: &[u8],
+        start_register: u8,
+        data: &[u8],
     ) -> Result<(), LinuxI2CError> {
-        ffi::i2c_smbus_write_i2c_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+        if usize::from(start_register) + data.len() > 0x100 {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        write_i2c_block_chunks(self, start_register, data, SMBUS_BLOCK_MAX, Duration::ZERO)
     }
 
-    /// Select a register, send 1 to 31 bytes of data to it, and reads
-    /// 1 to 31 bytes of data from it.
-    fn smbus_process_block(
+    /// Write a buffer of any length to consecutive registers in pages of
+    /// `page_size` bytes, sleeping for `delay` between pages
+    ///
+    /// This suits EEPROMs with a one byte address, which need time for the
+    /// internal programming cycle of a page before they accept the next one
+    /// and usually ignore or NAK transfers during it.  There is no delay
+    /// after the last page, so wait for the cycle before reading back.
+    /// Pages are written to `start` plus their offset in `data`, like
+    /// [`smbus_write_block_chunked`](#method.smbus_write_block_chunked)
+    /// does, so `start` should be aligned to the page size of the device;
+    /// otherwise a page wraps around within the page of the device.
+    ///
+    /// The write fails with `EINVAL` before anything is sent if
+    /// `page_size` is not between 1 and 32 or the registers would run past
+    /// 0xFF.
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// // a 24C02 EEPROM has 8 byte pages and a 5ms write cycle
+    /// let mut eeprom = LinuxI2CDevice::new("/dev/i2c-1", 0x50)?;
+    /// eeprom.smbus_write_paged(0x00, b"hello, world", 8, Duration::from_millis(5))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn smbus_write_paged(
         &mut self,
-        register: u8,
-        values: &[u8],
-    ) -> Result<Vec<u8>, LinuxI2CError> {
-        ffi::i2c_smbus_process_call_block(self.as_raw_fd(), register, values).map_err(From::from)
+        start: u8,
+        data: &[u8],
+        page_size: usize,
+        delay: Duration,
+    ) -> Result<(), LinuxI2CError> {
+        if page_size == 0 || page_size > SMBUS_BLOCK_MAX || usize::from(start) + data.len() > 0x100
+        {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        write_i2c_block_chunks(self, start, data, page_size, delay)
     }
-}
-
-impl<'a> I2CTransfer<'a> for LinuxI2CDevice {
-    type Error = LinuxI2CError;
-    type Message = LinuxI2CMessage<'a>;
-
-    /// Issue the provided sequence of I2C transactions
-    fn transfer(&mut self, messages: &mut [Self::Message]) -> Result<u32, LinuxI2CError> {
-        let msg_type = |flag: u16| flag & I2CMessageFlags::READ.bits();
-        let mut prev_msg_type = None;
-        for msg in messages.iter_mut() {
-            msg.addr = self.slave_address;
 
-            let cur_msg_type = msg_type(msg.flags);
-            if prev_msg_type
-                .map(|prev| prev == cur_msg_type)
-                .unwrap_or_default()
-            {
-                msg.flags |= I2CMessageFlags::NO_START.bits();
-            } else {
-                prev_msg_type = Some(cur_msg_type);
-            }
+    /// Read up to `N` bytes starting at `register` into `buf`, without
+    /// allocating
+    ///
+    /// This is the same I2C block read as `smbus_read_i2c_block_data` with
+    /// a length of `N`, returning the number of bytes the adapter reported.
+    /// Like all SMBus blocks, `N` is limited to 32, and larger buffers are
+    /// rejected with `EINVAL` before anything is sent.
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut dev = LinuxI2CDevice::new("/dev/i2c-1", 0x6b)?;
+    /// let mut sample = [0; 6];
+    /// loop {
+    ///     let count = dev.smbus_read_i2c_block_data_into(0xA8, &mut sample)?;
+    ///     println!("{:?}", &sample[..count]);
+    /// }
+    /// # }
+    /// ```
+    pub fn smbus_read_i2c_block_data_into<const N: usize>(
+        &mut self,
+        register: u8,
+        buf: &mut [u8; N],
+    ) -> Result<usize, LinuxI2CError> {
+        if N > SMBUS_BLOCK_MAX {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
         }
-        ffi::i2c_rdwr(self.as_raw_fd(), messages).map_err(From::from)
+        ffi::i2c_smbus_read_i2c_block_data_into(self.as_raw_fd(), register, buf).map_err(From::from)
     }
-}
 
-impl LinuxI2CBus {
-    /// Create a new LinuxI2CBus for the specified path
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<LinuxI2CBus, LinuxI2CError> {
-        let file = OpenOptions::new().read(true).write(true).open(path)?;
-        let bus = LinuxI2CBus { devfile: file };
-        Ok(bus)
+    /// Read a block of data from the register, returning the count byte
+    /// as the device sent it along with the data
+    ///
+    /// `smbus_read_block_data` only returns the data.  This also returns the
+    /// count, which is useful when debugging a device, or with protocols
+    /// giving meaning to it.  Unlike the other block reads, a count larger
+    /// than 32 is not an error here; the count is returned as the adapter
+    /// reported it and the data is cut to 32 bytes, so the caller can tell
+    /// how far the device over-reported.  Most adapters reject such a
+    /// block with `EPROTO` or `EIO` themselves, though.
+    pub fn smbus_read_block_data_full(
+        &mut self,
+        register: u8,
+    ) -> Result<(u8, Vec<u8>), LinuxI2CError> {
+        let mut buf = [0; SMBUS_BLOCK_MAX];
+        let count = ffi::i2c_smbus_read_block_data_raw(self.as_raw_fd(), register, &mut buf)?;
+        Ok((count, reported_block(count, &buf).to_vec()))
     }
-}
 
-pub use ffi::i2c_msg as LinuxI2CMessage;
+    /// Read the byte registers in `registers`, returning each register with
+    /// its value in the same order
+    ///
+    /// This is a convenience for dumping registers which are not
+    /// consecutive: every register is read with its own
+    /// `smbus_read_byte_data`, not in a single bus transaction, so the
+    /// values may come from different moments.  The first failing read
+    /// ends the dump with its error.
+    pub fn smbus_read_registers(
+        &mut self,
+        registers: &[u8],
+    ) -> Result<Vec<(u8, u8)>, LinuxI2CError> {
+        read_registers_with(registers, |register| self.smbus_read_byte_data(register))
+    }
 
-impl<'a> I2CTransfer<'a> for LinuxI2CBus {
-    type Error = LinuxI2CError;
+    /// Enable/Disable PEC support for the lifetime of the returned guard
+    ///
+    /// This is useful for devices where only some commands use PEC.  The
+    /// guard dereferences to the device, so a single command can be issued
+    /// with `dev.with_smbus_pec(true)?.smbus_read_byte_data(reg)`.  The
+    /// previous setting is restored when the guard is dropped, including
+    /// when unwinding from a panic.  A failure to restore it cannot be
+    /// reported; `pec_enabled` returns the previous setting regardless.
+    pub fn with_smbus_pec(&mut self, enable: bool) -> Result<SmbusPecGuard<'_>, LinuxI2CError> {
+        let previous = self.pec;
+        if enable != previous {
+            self.set_smbus_pec(enable)?;
+        }
+        Ok(SmbusPecGuard {
+            device: self,
+            previous,
+        })
+    }
+
+    /// Run `f` with the device pointed at another slave address
+    ///
+    /// The previous address is restored when `f` returns, and also when it
+    /// panics.  If setting the temporary address fails, `f` is not run and
+    /// the error is returned; a failure to restore the previous address
+    /// cannot be reported, and leaves `slave_address` at the temporary one.
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::core::I2CDevice;
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut dev = LinuxI2CDevice::new("/dev/i2c-1", 0x50)?;
+    /// // the second half of a 16 Kbit EEPROM answers at the next address
+    /// let byte = dev.with_temporary_address(0x51, |dev| dev.smbus_read_byte_data(0x00))??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_temporary_address<A, F, R>(
+        &mut self,
+        slave_address: A,
+        f: F,
+    ) -> Result<R, LinuxI2CError>
+    where
+        A: Into<Address>,
+        F: FnOnce(&mut LinuxI2CDevice) -> R,
+    {
+        let previous = self.slave_address;
+        self.set_slave_address(slave_address)?;
+        let guard = SlaveAddressGuard {
+            device: self,
+            previous,
+        };
+        Ok(f(&mut *guard.device))
+    }
+
+    /// Issue a raw SMBus transaction through the `I2C_SMBUS` ioctl
+    ///
+    /// This is an escape hatch for transactions the SMBus methods do not
+    /// cover, using the same ioctl as they do.  `command` is the register or
+    /// command byte, ignored for `Quick` and `Byte`.  `data` is the kernel's
+    /// `union i2c_smbus_data`, used according to `size`:
+    ///
+    /// - `Byte` and `ByteData`: the byte in `data[0]`
+    /// - `WordData` and `ProcCall`: the word in `data[0..2]`, in native byte
+    ///   order; the kernel puts its low byte on the bus first
+    /// - `BlockData`, `BlockProcCall` and `I2CBlockData`: the count in
+    ///   `data[0]`, at most 32, followed by the bytes in `data[1..=count]`.
+    ///   For `I2CBlockData` reads, the count is set by the caller to the
+    ///   number of bytes wanted.
+    ///
+    /// Reads and the second half of process calls store their result in
+    /// `data`.  The kernel rejects combinations it does not support, and
+    /// checks the adapter functionality, so no validation is done here.
+    pub fn smbus_transfer(
+        &mut self,
+        read_write: SmbusReadWrite,
+        command: u8,
+        size: SmbusSize,
+        data: &mut [u8; 34],
+    ) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_transfer(
+            self.as_raw_fd(),
+            read_write as u8,
+            command,
+            size as u32,
+            data,
+        )
+        .map_err(From::from)
+    }
+}
+
+/// The data of a block read whose device reported `count` bytes, cut to the
+/// bytes the adapter could return
+fn reported_block(count: u8, buf: &[u8; SMBUS_BLOCK_MAX]) -> &[u8] {
+    &buf[..usize::from(count).min(SMBUS_BLOCK_MAX)]
+}
+
+/// Pair each register in `registers` with the value `read` returns for it,
+/// stopping at the first failure
+fn read_registers_with<F, E>(registers: &[u8], mut read: F) -> Result<Vec<(u8, u8)>, E>
+where
+    F: FnMut(u8) -> Result<u8, E>,
+{
+    registers
+        .iter()
+        .map(|&register| Ok((register, read(register)?)))
+        .collect()
+}
+
+/// Whether an SMBus operation missing from the adapter functionality can be
+/// emulated with plain I2C messages instead
+fn needs_emulation(functions: I2CFunctions, operation: I2CFunctions) -> bool {
+    !functions.contains(operation) && functions.contains(I2CFunctions::I2C_FUNC_I2C)
+}
+
+/// Read a word register with a write-read transfer, the way the kernel
+/// emulates SMBus on adapters which only support plain I2C
+fn transfer_read_word_data<T, E>(dev: &mut T, register: u8) -> Result<u16, E>
+where
+    T: for<'b> I2CTransfer<'b, Error = E>,
+{
+    let command = [register];
+    let mut buf = [0; 2];
+    dev.transfer(&mut [I2CMessage::write(&command), I2CMessage::read(&mut buf)])?;
+    Ok(LittleEndian::read_u16(&buf))
+}
+
+/// Write a word register with a single write message, the way the kernel
+/// emulates SMBus on adapters which only support plain I2C
+fn transfer_write_word_data<T, E>(dev: &mut T, register: u8, value: u16) -> Result<(), E>
+where
+    T: for<'b> I2CTransfer<'b, Error = E>,
+{
+    let mut buf = [register, 0, 0];
+    LittleEndian::write_u16(&mut buf[1..], value);
+    dev.transfer(&mut [I2CMessage::write(&buf)])?;
+    Ok(())
+}
+
+/// Write `data` in I2C blocks of up to `chunk_size` bytes to successive
+/// registers, sleeping for `delay` between blocks; the caller checks that
+/// `chunk_size` is between 1 and 32 and that the registers do not run past
+/// 0xFF
+fn write_i2c_block_chunks<D: I2CDevice>(
+    dev: &mut D,
+    start_register: u8,
+    data: &[u8],
+    chunk_size: usize,
+    delay: Duration,
+) -> Result<(), D::Error> {
+    for (i, chunk) in data.chunks(chunk_size).enumerate() {
+        if i > 0 && delay > Duration::ZERO {
+            thread::sleep(delay);
+        }
+        let register = usize::from(start_register) + i * chunk_size;
+        dev.smbus_write_i2c_block_data(register as u8, chunk)?;
+    }
+    Ok(())
+}
+
+/// Guard restoring the PEC setting of a `LinuxI2CDevice` when dropped
+///
+/// Created by [`LinuxI2CDevice::with_smbus_pec`].
+pub struct SmbusPecGuard<'a> {
+    device: &'a mut LinuxI2CDevice,
+    previous: bool,
+}
+
+impl<'a> Deref for SmbusPecGuard<'a> {
+    type Target = LinuxI2CDevice;
+
+    fn deref(&self) -> &LinuxI2CDevice {
+        self.device
+    }
+}
+
+impl<'a> DerefMut for SmbusPecGuard<'a> {
+    fn deref_mut(&mut self) -> &mut LinuxI2CDevice {
+        self.device
+    }
+}
+
+impl<'a> Drop for SmbusPecGuard<'a> {
+    fn drop(&mut self) {
+        if self.device.pec != self.previous {
+            // errors cannot be reported from drop, so the previous setting
+            // is recorded even if the ioctl fails
+            let _ = ffi::i2c_set_smbus_pec(self.device.as_raw_fd(), self.previous);
+            self.device.pec = self.previous;
+        }
+    }
+}
+
+/// Guard restoring the slave address of a `LinuxI2CDevice` when dropped,
+/// see [`LinuxI2CDevice::with_temporary_address`]
+struct SlaveAddressGuard<'a> {
+    device: &'a mut LinuxI2CDevice,
+    previous: Address,
+}
+
+impl<'a> Drop for SlaveAddressGuard<'a> {
+    fn drop(&mut self) {
+        if self.device.slave_address != self.previous {
+            // as for the PEC guard, errors cannot be reported from drop
+            let _ = self.device.set_slave_address(self.previous);
+        }
+    }
+}
+
+impl I2CDevice for LinuxI2CDevice {
+    type Error = LinuxI2CError;
+
+    /// Read data from the device to fill the provided slice
+    fn read(&mut self, data: &mut [u8]) -> Result<(), LinuxI2CError> {
+        self.bus.devfile.read_exact(data)?;
+        trace_traffic!("read {:?}: {}", self.slave_address, HexDump(data));
+        Ok(())
+    }
+
+    /// Read data from the device, returning the number of bytes the kernel
+    /// actually transferred
+    fn read_len(&mut self, data: &mut [u8]) -> Result<usize, LinuxI2CError> {
+        let len = self.bus.devfile.read(data)?;
+        trace_traffic!("read {:?}: {}", self.slave_address, HexDump(&data[..len]));
+        Ok(len)
+    }
+
+    /// Write the provided buffer to the device
+    fn write(&mut self, data: &[u8]) -> Result<(), LinuxI2CError> {
+        trace_traffic!("write {:?}: {}", self.slave_address, HexDump(data));
+        self.bus.devfile.write(data).map_err(From::from).map(drop)
+    }
+
+    /// Flush the device file
+    ///
+    /// Writes to i2c-dev are not buffered, as the kernel completes the
+    /// transfer before `write` returns, so this only matters for files
+    /// wrapped with `from_raw_fd` which are not i2c-dev devices.
+    fn flush(&mut self) -> Result<(), LinuxI2CError> {
+        self.bus.devfile.flush().map_err(From::from)
+    }
+
+    /// This sends a single bit to the device, at the place of the Rd/Wr bit
+    fn smbus_write_quick(&mut self, bit: bool) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_write_quick(self.as_raw_fd(), bit).map_err(From::from)
+    }
+
+    /// Read a single byte from a device, without specifying a device register
+    ///
+    /// Some devices are so simple that this interface is enough; for
+    /// others, it is a shorthand if you want to read the same register as in
+    /// the previous SMBus command.
+    fn smbus_read_byte(&mut self) -> Result<u8, LinuxI2CError> {
+        ffi::i2c_smbus_read_byte(self.as_raw_fd()).map_err(From::from)
+    }
+
+    /// Write a single byte to a sdevice, without specifying a device register
+    ///
+    /// This is the opposite operation as smbus_read_byte.  As with read_byte,
+    /// no register is specified.
+    fn smbus_write_byte(&mut self, value: u8) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_write_byte(self.as_raw_fd(), value).map_err(From::from)
+    }
+
+    /// Read a single byte from a device, from a designated register
+    ///
+    /// The register is specified through the Comm byte.
+    fn smbus_read_byte_data(&mut self, register: u8) -> Result<u8, LinuxI2CError> {
+        ffi::i2c_smbus_read_byte_data(self.as_raw_fd(), register).map_err(From::from)
+    }
+
+    /// Write a single byte to a specific register on a device
+    ///
+    /// The register is specified through the Comm byte.
+    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_write_byte_data(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    /// Read 2 bytes form a given register on a device
+    ///
+    /// Falls back to a plain I2C write-read transfer if the adapter lacks
+    /// SMBus word support.
+    fn smbus_read_word_data(&mut self, register: u8) -> Result<u16, LinuxI2CError> {
+        if self.emulate_smbus(I2CFunctions::I2C_FUNC_SMBUS_READ_WORD_DATA) {
+            return transfer_read_word_data(self, register);
+        }
+        ffi::i2c_smbus_read_word_data(self.as_raw_fd(), register).map_err(From::from)
+    }
+
+    /// Write 2 bytes to a given register on a device
+    ///
+    /// Falls back to a plain I2C write if the adapter lacks SMBus word
+    /// support.
+    fn smbus_write_word_data(&mut self, register: u8, value: u16) -> Result<(), LinuxI2CError> {
+        if self.emulate_smbus(I2CFunctions::I2C_FUNC_SMBUS_WRITE_WORD_DATA) {
+            return transfer_write_word_data(self, register, value);
+        }
+        ffi::i2c_smbus_write_word_data(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    /// Select a register, send 16 bits of data to it, and read 16 bits of data
+    fn smbus_process_word(&mut self, register: u8, value: u16) -> Result<u16, LinuxI2CError> {
+        ffi::i2c_smbus_process_call(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    /// Read a block of up to 32 bytes from a device into `buf` without
+    /// allocating, returning the count byte
+    fn smbus_read_block_data_into(
+        &mut self,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize, LinuxI2CError> {
+        ffi::i2c_smbus_read_block_data_into(self.as_raw_fd(), register, buf).map_err(From::from)
+    }
+
+    /// Read a block into `buf`, which may have any length, straight from
+    /// the kernel buffer
+    ///
+    /// A block which does not fit in `buf` fails with `EOVERFLOW`.
+    fn smbus_read_block_data_into_slice(
+        &mut self,
+        register: u8,
+        buf: &mut [u8],
+    ) -> Result<usize, LinuxI2CError> {
+        ffi::i2c_smbus_read_block_data_into_slice(self.as_raw_fd(), register, buf)
+            .map_err(From::from)
+    }
+
+    /// Write a block of up to 32 bytes to a device
+    ///
+    /// The opposite of the Block Read command, this writes up to 32 bytes to
+    /// a device, to a designated register that is specified through the
+    /// Comm byte. The amount of data is specified in the Count byte.
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_write_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+    }
+
+    /// Write a block of up to 32 bytes from a device via i2c_smbus_i2c_write_block_data
+    fn smbus_write_i2c_block_data(
+        &mut self,
+        register: u8,
+        values: &[u8],
+    ) -> Result<(), LinuxI2CError> {
+        ffi::i2c_smbus_write_i2c_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+    }
+}
+
+impl I2CDeviceExt for LinuxI2CDevice {
+    /// Read a block of up to 32 bytes from a device
+    ///
+    /// The actual number of bytes available to read is returned in the count
+    /// byte.  This code returns a correctly sized vector containing the
+    /// count bytes read from the device.
+    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, LinuxI2CError> {
+        ffi::i2c_smbus_read_block_data(self.as_raw_fd(), register).map_err(From::from)
+    }
+
+    /// Read a block of up to 32 bytes from a device via i2c_smbus_i2c_read_block_data
+    fn smbus_read_i2c_block_data(
+        &mut self,
+        register: u8,
+        len: u8,
+    ) -> Result<Vec<u8>, LinuxI2CError> {
+        ffi::i2c_smbus_read_i2c_block_data(self.as_raw_fd(), register, len).map_err(From::from)
+    }
+
+    /// Select a register, send 1 to 31 bytes of data to it, and reads
+    /// 1 to 31 bytes of data from it.
+    fn smbus_process_block(
+        &mut self,
+        register: u8,
+        values: &[u8],
+    ) -> Result<Vec<u8>, LinuxI2CError> {
+        ffi::i2c_smbus_process_call_block(self.as_raw_fd(), register, values).map_err(From::from)
+    }
+}
+
+impl<'a> I2CTransfer<'a> for LinuxI2CDevice {
+    type Error = LinuxI2CError;
     type Message = LinuxI2CMessage<'a>;
 
     /// Issue the provided sequence of I2C transactions
-    fn transfer(&mut self, msgs: &mut [Self::Message]) -> Result<u32, LinuxI2CError> {
-        ffi::i2c_rdwr(self.as_raw_fd(), msgs).map_err(From::from)
+    ///
+    /// At most [`MAX_TRANSFER_MESSAGES`] messages can be issued at once;
+    /// longer sequences fail with `TooManyMessages` without touching the
+    /// bus.
+    fn transfer(&mut self, messages: &mut [Self::Message]) -> Result<u32, LinuxI2CError> {
+        let slave_address = self.slave_address;
+        self.transfer_to(slave_address, messages)
     }
 }
 
-bitflags! {
-    /// Various flags used by the i2c_rdwr ioctl on Linux. For details, see
-    /// https://www.kernel.org/doc/Documentation/i2c/i2c-protocol
+impl LinuxI2CDevice {
+    /// Write `write` and then read into `read` in one transaction
     ///
-    /// In general, these are for special cases and should not be needed
-    pub struct I2CMessageFlags: u16 {
-        /// Use ten bit addressing on this message
-        const TEN_BIT_ADDRESS = 0x0010;
-        /// Read data, from slave to master
-        const READ = 0x0001;
-        /// Force an I2C stop condition on this message
-        const STOP = 0x8000;
-        /// Avoid sending an I2C start condition on this message
-        const NO_START = 0x4000;
-        /// If you need to invert a 'read' command bit to a 'write'
-        const INVERT_COMMAND = 0x2000;
-        /// Force this message to ignore I2C negative acknowlegements
-        const IGNORE_NACK = 0x1000;
-        /// Force message to ignore acknowledgement
-        const IGNORE_ACK = 0x0800;
-        /// Allow the client to specify how many bytes it will send
-        const USE_RECEIVE_LENGTH = 0x0400;
+    /// The read follows the write with a repeated start and no stop in
+    /// between, so no other master can take the bus and the device keeps
+    /// any state set by the write, such as a register pointer.  This is the
+    /// usual way to read a register of devices which reset the pointer on a
+    /// stop condition.  It fails with [`LinuxI2CError::PartialTransfer`]
+    /// unless the kernel reports both messages as executed, so `read` is
+    /// never left with stale data.
+    pub fn write_read(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), LinuxI2CError> {
+        let mut msgs = [LinuxI2CMessage::write(write), LinuxI2CMessage::read(read)];
+        self.transfer_all(&mut msgs)
     }
+
+    /// Issue the provided sequence of I2C transactions, failing unless all
+    /// of them were executed
+    ///
+    /// `transfer` returns the number of messages the kernel executed, which
+    /// some adapter drivers report as less than the number submitted
+    /// without an error.  This turns that case into a
+    /// [`LinuxI2CError::PartialTransfer`], so the data of the remaining
+    /// messages is not silently lost.
+    pub fn transfer_all(&mut self, messages: &mut [LinuxI2CMessage]) -> Result<(), LinuxI2CError> {
+        let completed = self.transfer(messages)?;
+        check_transfer(completed, messages.len())
+    }
+
+    /// Issue the provided sequence of I2C transactions with the addresses
+    /// and flags of the messages as they are
+    ///
+    /// `transfer` sends every message to the address of the device and adds
+    /// `NO_START` between messages in the same direction.  This does
+    /// neither, so a single transaction can address several devices with
+    /// [`LinuxI2CMessage::with_address`], as with a [`LinuxI2CBus`].
+    /// Messages built without `with_address` go to address 0.
+    pub fn transfer_raw(&mut self, messages: &mut [LinuxI2CMessage]) -> Result<u32, LinuxI2CError> {
+        self.bus.transfer(messages)
+    }
+
+    /// Issue the provided sequence of I2C transactions to `slave_address`
+    /// instead of the address this device was opened for
+    ///
+    /// Consecutive messages in the same direction are sent without a
+    /// repeated start, as for [`I2CTransfer::transfer`].
+    pub(crate) fn transfer_to(
+        &mut self,
+        slave_address: Address,
+        messages: &mut [LinuxI2CMessage],
+    ) -> Result<u32, LinuxI2CError> {
+        let msg_type = |flag: u16| flag & I2CMessageFlags::READ.bits();
+        let mut prev_msg_type = None;
+        for msg in messages.iter_mut() {
+            msg.addr = slave_address.value();
+            if slave_address.is_ten_bit() {
+                msg.flags |= I2CMessageFlags::TEN_BIT_ADDRESS.bits();
+            }
+
+            let cur_msg_type = msg_type(msg.flags);
+            if prev_msg_type
+                .map(|prev| prev == cur_msg_type)
+                .unwrap_or_default()
+            {
+                msg.flags |= I2CMessageFlags::NO_START.bits();
+            } else {
+                prev_msg_type = Some(cur_msg_type);
+            }
+        }
+        rdwr(self.as_raw_fd(), messages)
+    }
+}
+
+impl LinuxI2CBus {
+    /// Create a new LinuxI2CBus for the specified path
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<LinuxI2CBus, LinuxI2CError> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let bus = LinuxI2CBus { devfile: file };
+        Ok(bus)
+    }
+
+    /// List the I2C bus device files on the system, `/dev/i2c-N`, sorted by
+    /// bus number
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::linux::{LinuxI2CBus, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// for path in LinuxI2CBus::list()? {
+    ///     let name = LinuxI2CBus::adapter_name(&path)?;
+    ///     println!("{}: {}", path.display(), name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn list() -> Result<Vec<PathBuf>, LinuxI2CError> {
+        list_buses(Path::new("/dev"))
+    }
+
+    /// The name of the adapter behind an I2C bus device file, as reported
+    /// in `/sys/class/i2c-dev/i2c-N/name`
+    pub fn adapter_name<P: AsRef<Path>>(path: P) -> Result<String, LinuxI2CError> {
+        let file_name = path
+            .as_ref()
+            .file_name()
+            .ok_or(LinuxI2CError::Errno(libc::EINVAL))?;
+        read_adapter_name(file_name)
+    }
+
+    /// The name of the adapter of this bus, such as "bcm2835 (i2c@7e804000)"
+    ///
+    /// The bus number is taken from the device number of the open file, so
+    /// this works however the bus was opened.  The name is read from sysfs,
+    /// so it fails if sysfs is not mounted.
+    pub fn name(&self) -> Result<String, LinuxI2CError> {
+        let rdev = self.devfile.metadata()?.rdev();
+        let (major, minor) = dev_major_minor(rdev);
+        if major != I2C_DEV_MAJOR {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an i2c-dev character device",
+            )
+            .into());
+        }
+        read_adapter_name(format!("i2c-{}", minor).as_ref())
+    }
+
+    /// Open a device at `slave_address` on this bus
+    ///
+    /// The device file is opened again, through `/proc/self/fd`, rather
+    /// than duplicated as `try_clone` does: the kernel keeps the slave
+    /// address with the open file, so devices sharing one would change each
+    /// other's address.  Each device thus has its own open file, and can be
+    /// used alongside the bus and the other devices created from it.  The
+    /// device is set up as by [`LinuxI2CDevice::new`].
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::core::I2CDevice;
+    /// # use i2cdev::linux::{LinuxI2CBus, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut bus = LinuxI2CBus::new("/dev/i2c-1")?;
+    /// for address in bus.scan()? {
+    ///     let mut dev = bus.device(address)?;
+    ///     println!("0x{:02x}: 0x{:02x}", address, dev.smbus_read_byte()?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn device<A: Into<Address>>(
+        &self,
+        slave_address: A,
+    ) -> Result<LinuxI2CDevice, LinuxI2CError> {
+        let path = format!("/proc/self/fd/{}", self.as_raw_fd());
+        LinuxI2CDevice::from_bus(LinuxI2CBus::new(path)?, slave_address.into())
+    }
+
+    /// Create a second handle to this bus sharing the same open file
+    ///
+    /// See [`LinuxI2CDevice::try_clone`] for what is shared.
+    pub fn try_clone(&self) -> Result<LinuxI2CBus, LinuxI2CError> {
+        Ok(LinuxI2CBus {
+            devfile: self.devfile.try_clone()?,
+        })
+    }
+
+    /// Query the functionality supported by the adapter of this bus
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::linux::{I2CFunctions, LinuxI2CBus, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let bus = LinuxI2CBus::new("/dev/i2c-1")?;
+    /// if bus
+    ///     .functionality()?
+    ///     .contains(I2CFunctions::I2C_FUNC_SMBUS_READ_BLOCK_DATA)
+    /// {
+    ///     println!("SMBus block reads are supported");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn functionality(&self) -> Result<I2CFunctions, LinuxI2CError> {
+        ffi::i2c_get_functionality(self.as_raw_fd()).map_err(From::from)
+    }
+
+    /// Check whether the adapter supports all of `functions`
+    ///
+    /// Unlike [`LinuxI2CDevice::supports`], the bus keeps no state, so this
+    /// queries the adapter every time.
+    pub fn supports(&self, functions: I2CFunctions) -> Result<bool, LinuxI2CError> {
+        Ok(self.functionality()?.contains(functions))
+    }
+
+    /// Set the number of times the adapter retries a transfer
+    ///
+    /// The kernel only retries transfers which fail because arbitration was
+    /// lost to another master; NAKs and other I/O errors are reported
+    /// straight away.  The setting applies to the adapter, so it affects all
+    /// users of the bus.
+    pub fn set_retries(&mut self, count: u32) -> Result<(), LinuxI2CError> {
+        ffi::i2c_set_retries(self.as_raw_fd(), count).map_err(From::from)
+    }
+
+    /// Set how long the adapter waits for a transfer to complete
+    ///
+    /// The kernel takes the timeout in units of 10ms, so it is rounded up to
+    /// the next multiple of 10ms and clamped to the largest value the kernel
+    /// accepts.  A zero timeout is rejected with `EINVAL`.  The setting
+    /// applies to the adapter, so it affects all users of the bus.
+    ///
+    /// This is the only bound on how long a read or write, including a
+    /// device stretching the clock, blocks the caller.  The i2c-dev
+    /// character device does not implement `poll`, so `poll` and `select`
+    /// report it as ready straight away and cannot be used to time out a
+    /// read; an expired transfer fails with `ETIMEDOUT`, classified as
+    /// [`LinuxI2CErrorKind::Timeout`].
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), LinuxI2CError> {
+        let timeout = timeout_to_ticks(timeout).ok_or(LinuxI2CError::Errno(libc::EINVAL))?;
+        ffi::i2c_set_timeout(self.as_raw_fd(), timeout).map_err(From::from)
+    }
+
+    /// Enable/Disable PEC support for the SMBus transactions on this bus
+    ///
+    /// The setting belongs to this open file rather than to an address, so
+    /// it applies to every address the transactions are sent to, including
+    /// after `set_slave_address`.  As with
+    /// [`LinuxI2CDevice::set_smbus_pec`], it only has an effect if the
+    /// adapter has `I2C_FUNC_SMBUS_PEC`.
+    pub fn set_smbus_pec(&mut self, enable: bool) -> Result<(), LinuxI2CError> {
+        ffi::i2c_set_smbus_pec(self.as_raw_fd(), enable).map_err(From::from)
+    }
+
+    /// Attempt to recover a bus where a slave is holding SDA low
+    ///
+    /// The standard recovery is to clock SCL up to 9 times until the slave
+    /// releases SDA.  Adapters providing recovery support already have the
+    /// kernel run `i2c_recover_bus` when a transfer times out, but the i2c-dev
+    /// interface does not expose a way to trigger it from userspace, so this
+    /// returns an `Unsupported` error unless a future kernel interface becomes
+    /// available.  Bit-banging the recovery sequence over GPIOs is out of
+    /// scope for this crate.
+    pub fn recover_bus(&mut self) -> Result<(), LinuxI2CError> {
+        Err(LinuxI2CError::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "bus recovery is not exposed by the i2c-dev interface",
+        )))
+    }
+
+    /// Probe every address in the standard 0x03 to 0x77 range for a device
+    ///
+    /// Like `i2cdetect`, addresses in the 0x30 to 0x37 and 0x50 to 0x5F
+    /// ranges are probed by reading a byte, as a quick write can corrupt the
+    /// contents of EEPROMs living there, and all other addresses with a quick
+    /// write.  Addresses claimed by a kernel driver (shown as `UU` by
+    /// `i2cdetect`) are reported as busy rather than probed.
+    ///
+    /// An error is only returned if the adapter functionality cannot be
+    /// queried; failures for individual addresses are part of the result.
+    pub fn scan_detailed(&mut self) -> Result<ScanResult, LinuxI2CError> {
+        self.functionality()?;
+        let mut result = ScanResult::default();
+        for address in 0x03..=0x77 {
+            let probe = self.probe_address(address);
+            result.record(address, probe);
+        }
+        Ok(result)
+    }
+
+    /// Find the 7-bit addresses of the devices responding on this bus
+    ///
+    /// This probes the same addresses in the same way as
+    /// [`scan_detailed`](#method.scan_detailed), returning only those where
+    /// a device acknowledged.  Addresses in use by a kernel driver are left
+    /// out, as are addresses where the probe failed for a reason other than
+    /// a missing acknowledgement.
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::linux::{LinuxI2CBus, LinuxI2CError};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut bus = LinuxI2CBus::new("/dev/i2c-1")?;
+    /// for address in bus.scan()? {
+    ///     println!("found device at 0x{:02x}", address);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan(&mut self) -> Result<Vec<u16>, LinuxI2CError> {
+        Ok(self.scan_detailed()?.present)
+    }
+
+    /// Check whether a device acknowledges the 7-bit `address`
+    ///
+    /// The address is probed like [`scan_detailed`](#method.scan_detailed)
+    /// does, with a one byte read in the EEPROM ranges and a quick write
+    /// elsewhere.  A missing acknowledgement gives `Ok(false)`, while any
+    /// other failure is returned as an error, including `AddressInUse` for
+    /// an address in use by a kernel driver and `EINVAL` for an address
+    /// above 0x7F.
+    pub fn probe(&mut self, address: u16) -> Result<bool, LinuxI2CError> {
+        probe_ack(self.probe_address(address))
+    }
+
+    /// Check whether a device acknowledges `address`, with a transfer of a
+    /// single zero-length write message
+    ///
+    /// The adapter sends just a start condition, the address and a stop,
+    /// which some devices handle better than the SMBus quick write used by
+    /// [`probe`](#method.probe).  A 10-bit address is given as
+    /// `Address::TenBit`, while a `u16` is a 7-bit address.  The device not acknowledging, reported as `ENXIO`,
+    /// `ENODEV` or `EREMOTEIO`, gives `Ok(false)`; any other failure is
+    /// returned as an error.  Unlike `probe`, this includes a plain `EIO`,
+    /// so a broken bus is not mistaken for an empty one.  Some adapters
+    /// cannot send zero-length messages and fail with `EOPNOTSUPP`.
+    pub fn address_ack<A: Into<Address>>(&mut self, address: A) -> Result<bool, LinuxI2CError> {
+        let address = address.into();
+        if !address.is_valid() {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        let mut msgs = [LinuxI2CMessage::write(&[]).with_address(address)];
+        transfer_ack(self.transfer(&mut msgs))
+    }
+
+    /// Read the SMBus Alert Response Address to find a device asserting
+    /// SMBALERT#
+    ///
+    /// Returns the 7-bit address of the device which won the arbitration
+    /// for the response along with the status bit it sent, the least
+    /// significant bit of the response, or `None` if no device responded.
+    /// A device stops asserting SMBALERT# once its address has been read, so
+    /// when several devices raise an alert this is called until it returns
+    /// `None`.
+    ///
+    /// If the `smbus_alert` kernel driver handles alerts for this adapter,
+    /// the address is in use and this fails with `AddressInUse`.
+    pub fn smbus_read_alert(&mut self) -> Result<Option<(u16, u8)>, LinuxI2CError> {
+        self.select_seven_bit(SMBUS_ALERT_RESPONSE_ADDR)?;
+        alert_response(ffi::i2c_smbus_read_byte(self.as_raw_fd()).map_err(From::from))
+    }
+
+    /// Issue the provided sequence of I2C transactions, failing unless all
+    /// of them were executed
+    ///
+    /// See [`LinuxI2CDevice::transfer_all`].
+    pub fn transfer_all(&mut self, messages: &mut [LinuxI2CMessage]) -> Result<(), LinuxI2CError> {
+        let completed = self.transfer(messages)?;
+        check_transfer(completed, messages.len())
+    }
+
+    /// Point the open file at `address` for the SMBus ioctls, with ten bit
+    /// addressing switched to match
+    fn select_address(&mut self, address: Address) -> Result<(), LinuxI2CError> {
+        if !address.is_valid() {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        ffi::i2c_set_tenbit(self.as_raw_fd(), address.is_ten_bit())?;
+        ffi::i2c_set_slave_address(self.as_raw_fd(), address.value())
+            .map_err(|e| address_error(e, address))
+    }
+
+    /// Point the file at the 7-bit `address`, switching ten bit addressing
+    /// off in case an earlier 10-bit address left it on
+    fn select_seven_bit(&mut self, address: u16) -> Result<(), LinuxI2CError> {
+        let address = u8::try_from(address)
+            .ok()
+            .and_then(Address::seven_bit)
+            .ok_or(LinuxI2CError::Errno(libc::EINVAL))?;
+        self.select_address(address)
+    }
+
+    fn probe_address(&mut self, address: u16) -> Result<(), LinuxI2CError> {
+        self.select_seven_bit(address)?;
+        if (0x30..=0x37).contains(&address) || (0x50..=0x5F).contains(&address) {
+            ffi::i2c_smbus_read_byte(self.as_raw_fd())?;
+        } else {
+            ffi::i2c_smbus_write_quick(self.as_raw_fd(), false)?;
+        }
+        Ok(())
+    }
+}
+
+/// Major device number of the i2c-dev character devices
+const I2C_DEV_MAJOR: u32 = 89;
+
+/// The SMBus Alert Response Address, read by the host to find which device
+/// asserts SMBALERT#
+const SMBUS_ALERT_RESPONSE_ADDR: u16 = 0x0C;
+
+/// Decode the byte read from the SMBus Alert Response Address into the
+/// address of the alerting device and its status bit, with a missing
+/// acknowledgement meaning that no device is alerting
+fn alert_response(response: Result<u8, LinuxI2CError>) -> Result<Option<(u16, u8)>, LinuxI2CError> {
+    match response {
+        Ok(response) => Ok(Some((u16::from(response >> 1), response & 1))),
+        Err(ref e) if is_missing_ack(e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Split a Linux device number into its major and minor numbers, as the
+/// `major` and `minor` macros of glibc do
+fn dev_major_minor(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
+
+/// Read the adapter name of the bus device file named `file_name` from sysfs
+fn read_adapter_name(file_name: &OsStr) -> Result<String, LinuxI2CError> {
+    let path = Path::new("/sys/class/i2c-dev").join(file_name).join("name");
+    match fs::read_to_string(&path) {
+        Ok(name) => Ok(name.trim_end().to_string()),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("cannot read adapter name from {}: {}", path.display(), e),
+        )
+        .into()),
+    }
+}
+
+/// The `i2c-N` entries of `dir`, sorted by bus number
+fn list_buses(dir: &Path) -> Result<Vec<PathBuf>, LinuxI2CError> {
+    let mut buses = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let number = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("i2c-"))
+            .and_then(|number| number.parse::<u32>().ok());
+        if let Some(number) = number {
+            buses.push((number, entry.path()));
+        }
+    }
+    buses.sort();
+    Ok(buses.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Issue messages with the `I2C_RDWR` ioctl, tracing them with the `log`
+/// feature
+fn rdwr(fd: RawFd, messages: &mut [LinuxI2CMessage]) -> Result<u32, LinuxI2CError> {
+    // the kernel rejects these with a bare EINVAL
+    if messages.len() > MAX_TRANSFER_MESSAGES {
+        return Err(LinuxI2CError::TooManyMessages {
+            count: messages.len(),
+        });
+    }
+    let completed = ffi::i2c_rdwr(fd, messages)?;
+    for msg in messages.iter().take(completed as usize) {
+        let direction = if msg.flags & I2CMessageFlags::READ.bits() != 0 {
+            "read"
+        } else {
+            "write"
+        };
+        // the message still borrows its buffer, and the kernel is done with it
+        let data = unsafe { std::slice::from_raw_parts(msg.buf, msg.len as usize) };
+        trace_traffic!(
+            "transfer {} 0x{:02x} (flags 0x{:04x}): {}",
+            direction,
+            msg.addr,
+            msg.flags,
+            HexDump(data)
+        );
+    }
+    Ok(completed)
+}
+
+/// Check the number of messages a transfer executed against the number
+/// submitted
+pub(crate) fn check_transfer(completed: u32, total: usize) -> Result<(), LinuxI2CError> {
+    if completed as usize == total {
+        Ok(())
+    } else {
+        Err(LinuxI2CError::PartialTransfer {
+            completed,
+            total: total as u32,
+        })
+    }
+}
+
+/// Convert a timeout into the 10ms units of the `I2C_TIMEOUT` ioctl,
+/// rounding up and clamping, or `None` for a zero timeout
+fn timeout_to_ticks(timeout: Duration) -> Option<i32> {
+    if timeout == Duration::from_secs(0) {
+        return None;
+    }
+    const TICK_NANOS: u128 = 10_000_000;
+    let nanos = timeout.as_nanos();
+    let mut ticks = nanos / TICK_NANOS;
+    if ticks * TICK_NANOS < nanos {
+        ticks += 1;
+    }
+    Some(i32::try_from(ticks).unwrap_or(i32::MAX))
+}
+
+/// Outcome of scanning a bus with [`LinuxI2CBus::scan_detailed`]
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Addresses where a device acknowledged the probe
+    pub present: Vec<u16>,
+    /// Addresses which are in use by a kernel driver
+    pub busy: Vec<u16>,
+    /// Addresses where probing failed for a reason other than a NAK
+    pub errors: Vec<(u16, LinuxI2CError)>,
+}
+
+impl ScanResult {
+    fn record(&mut self, address: u16, probe: Result<(), LinuxI2CError>) {
+        match probe {
+            Ok(()) => self.present.push(address),
+            Err(LinuxI2CError::AddressInUse(_)) | Err(LinuxI2CError::Errno(libc::EBUSY)) => {
+                self.busy.push(address)
+            }
+            Err(ref e) if is_missing_ack(e) => {}
+            Err(e) => self.errors.push((address, e)),
+        }
+    }
+}
+
+/// Turn the outcome of probing an address into whether a device
+/// acknowledged, keeping failures other than a missing ACK as errors
+fn probe_ack(probe: Result<(), LinuxI2CError>) -> Result<bool, LinuxI2CError> {
+    match probe {
+        Ok(()) => Ok(true),
+        Err(ref e) if is_missing_ack(e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Turn the outcome of a zero-length write into whether the device
+/// acknowledged, where only `NoDevice` and `Nack` errors mean it did not
+fn transfer_ack(transfer: Result<u32, LinuxI2CError>) -> Result<bool, LinuxI2CError> {
+    match transfer {
+        Ok(_) => Ok(true),
+        Err(ref e)
+            if matches!(
+                e.kind(),
+                LinuxI2CErrorKind::NoDevice | LinuxI2CErrorKind::Nack
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether an error is how the adapter reports that no device acknowledged
+fn is_missing_ack(error: &LinuxI2CError) -> bool {
+    // adapters report a missing ACK with any of these
+    matches!(
+        *error,
+        LinuxI2CError::Errno(libc::ENXIO)
+            | LinuxI2CError::Errno(libc::EREMOTEIO)
+            | LinuxI2CError::Errno(libc::EIO)
+    )
+}
+
+/// Direction of a raw SMBus transaction, see
+/// [`LinuxI2CDevice::smbus_transfer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SmbusReadWrite {
+    /// Read from the device
+    Read = 1,
+    /// Write to the device
+    Write = 0,
+}
+
+/// Transaction type of a raw SMBus transaction, with the values of the
+/// kernel's `I2C_SMBUS_*` sizes
+///
+/// See [`LinuxI2CDevice::smbus_transfer`] for the data each one uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum SmbusSize {
+    /// Quick command; the direction is the data bit
+    Quick = 0,
+    /// Send or receive byte, without a command
+    Byte = 1,
+    /// Read or write byte data
+    ByteData = 2,
+    /// Read or write word data
+    WordData = 3,
+    /// Process call, writing a word and reading one back
+    ProcCall = 4,
+    /// Block read or write, with a count byte
+    BlockData = 5,
+    /// I2C block read or write with the count passed to the kernel, as used
+    /// by old kernels
+    I2CBlockBroken = 6,
+    /// Block process call, writing a block and reading one back
+    BlockProcCall = 7,
+    /// I2C block read or write, without a count byte on the bus
+    I2CBlockData = 8,
+}
+
+pub use crate::ffi::i2c_msg as LinuxI2CMessage;
+
+pub use crate::ffi::I2CFunctions;
+
+impl<'a> I2CTransfer<'a> for LinuxI2CBus {
+    type Error = LinuxI2CError;
+    type Message = LinuxI2CMessage<'a>;
+
+    /// Issue the provided sequence of I2C transactions
+    ///
+    /// As for [`LinuxI2CDevice`], at most [`MAX_TRANSFER_MESSAGES`] messages
+    /// can be issued at once.
+    fn transfer(&mut self, msgs: &mut [Self::Message]) -> Result<u32, LinuxI2CError> {
+        rdwr(self.as_raw_fd(), msgs)
+    }
+}
+
+/// SMBus commands to any address on the bus
+///
+/// Each call points the open file at `address`, switching ten bit
+/// addressing to match, and then issues the same ioctl as the
+/// [`LinuxI2CDevice`] methods.  The address stays selected afterwards, so
+/// devices sharing the file through `as_bus_mut` need their address set
+/// again.  Addresses claimed by a kernel driver fail with `AddressInUse`.
+///
+/// ```rust,no_run
+/// # use i2cdev::core::I2CSmbus;
+/// # use i2cdev::linux::{LinuxI2CBus, LinuxI2CError};
+/// # fn main() -> Result<(), LinuxI2CError> {
+/// let mut bus = LinuxI2CBus::new("/dev/i2c-1")?;
+/// let id = bus.smbus_read_byte_data(0x76, 0xD0)?;
+/// # Ok(())
+/// # }
+/// ```
+impl I2CSmbus for LinuxI2CBus {
+    type Error = LinuxI2CError;
+
+    fn smbus_write_quick<A: Into<Address>>(
+        &mut self,
+        address: A,
+        bit: bool,
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_quick(self.as_raw_fd(), bit).map_err(From::from)
+    }
+
+    fn smbus_read_byte<A: Into<Address>>(&mut self, address: A) -> Result<u8, LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_read_byte(self.as_raw_fd()).map_err(From::from)
+    }
+
+    fn smbus_write_byte<A: Into<Address>>(
+        &mut self,
+        address: A,
+        value: u8,
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_byte(self.as_raw_fd(), value).map_err(From::from)
+    }
+
+    fn smbus_read_byte_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+    ) -> Result<u8, LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_read_byte_data(self.as_raw_fd(), register).map_err(From::from)
+    }
+
+    fn smbus_write_byte_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u8,
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_byte_data(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    fn smbus_read_word_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+    ) -> Result<u16, LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_read_word_data(self.as_raw_fd(), register).map_err(From::from)
+    }
+
+    fn smbus_write_word_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u16,
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_word_data(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    fn smbus_process_word<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        value: u16,
+    ) -> Result<u16, LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_process_call(self.as_raw_fd(), register, value).map_err(From::from)
+    }
+
+    fn smbus_read_block_data_into<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize, LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_read_block_data_into(self.as_raw_fd(), register, buf).map_err(From::from)
+    }
+
+    fn smbus_write_block_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        values: &[u8],
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+    }
+
+    fn smbus_write_i2c_block_data<A: Into<Address>>(
+        &mut self,
+        address: A,
+        register: u8,
+        values: &[u8],
+    ) -> Result<(), LinuxI2CError> {
+        self.select_address(address.into())?;
+        ffi::i2c_smbus_write_i2c_block_data(self.as_raw_fd(), register, values).map_err(From::from)
+    }
+}
+
+/// The length of a message of `len` bytes as the kernel takes it
+///
+/// # Panics
+///
+/// Panics if `len` is more than 65535, rather than truncating it.
+fn message_len(len: usize) -> u16 {
+    u16::try_from(len).expect("message longer than 65535 bytes")
 }
 
+/// # Panics
+///
+/// The kernel takes the length of a message as 16 bits, so creating a
+/// message with more than 65535 bytes panics.
 impl<'a> I2CMessage<'a> for LinuxI2CMessage<'a> {
     fn read(data: &'a mut [u8]) -> LinuxI2CMessage<'a> {
         Self {
             addr: 0, // will be filled later
             flags: I2CMessageFlags::READ.bits(),
-            len: data.len() as u16,
+            len: message_len(data.len()),
             buf: data.as_ptr(),
             _p: PhantomData,
         }
@@ -380,7 +2036,7 @@ impl<'a> I2CMessage<'a> for LinuxI2CMessage<'a> {
         Self {
             addr: 0, // will be filled later
             flags: I2CMessageFlags::empty().bits(),
-            len: data.len() as u16,
+            len: message_len(data.len()),
             buf: data.as_ptr(),
             _p: PhantomData,
         }
@@ -388,11 +2044,112 @@ impl<'a> I2CMessage<'a> for LinuxI2CMessage<'a> {
 }
 
 impl<'a> LinuxI2CMessage<'a> {
+    /// Create a read whose length is given by the first byte the device
+    /// sends, as for SMBus block reads (`I2C_M_RECV_LEN`)
+    ///
+    /// The kernel reads the count byte, then that many data bytes, up to
+    /// 32, so `data` must have room for the count byte and 32 bytes of
+    /// data, i.e. at least 33 bytes; shorter buffers are rejected with
+    /// `EINVAL`.  The first byte of `data` is initialized as the kernel
+    /// requires.
+    ///
+    /// The [length](#method.len) of the message is not updated by
+    /// `transfer`.  Instead, after the transfer `data[0]` holds the count
+    /// sent by the device and the data follows in `data[1..=count]`.
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::core::{I2CMessage, I2CTransfer};
+    /// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError, LinuxI2CMessage};
+    /// # fn main() -> Result<(), LinuxI2CError> {
+    /// let mut dev = LinuxI2CDevice::new("/dev/i2c-1", 0x0b)?;
+    /// let mut data = [0; 33];
+    /// dev.transfer(&mut [
+    ///     LinuxI2CMessage::write(&[0x20]),
+    ///     LinuxI2CMessage::read_with_recv_len(&mut data)?,
+    /// ])?;
+    /// let count = data[0] as usize;
+    /// println!("manufacturer: {:?}", &data[1..=count]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_with_recv_len(data: &'a mut [u8]) -> Result<LinuxI2CMessage<'a>, LinuxI2CError> {
+        LinuxI2CMessage::recv_len(data, 1)
+    }
+
+    /// Create a read like [`read_with_recv_len`](#method.read_with_recv_len)
+    /// which also receives the PEC byte following the data
+    ///
+    /// `data` must have room for the PEC byte as well, i.e. at least 34
+    /// bytes.  After the transfer the PEC byte is `data[count + 1]`; it is
+    /// not checked, as for any other I2C message.
+    pub fn read_with_recv_len_pec(
+        data: &'a mut [u8],
+    ) -> Result<LinuxI2CMessage<'a>, LinuxI2CError> {
+        LinuxI2CMessage::recv_len(data, 2)
+    }
+
+    /// Build an `I2C_M_RECV_LEN` read where the kernel reads `extra` bytes
+    /// beyond the received count: the count byte itself and the PEC byte
+    fn recv_len(data: &'a mut [u8], extra: u8) -> Result<LinuxI2CMessage<'a>, LinuxI2CError> {
+        if data.len() < usize::from(extra) + SMBUS_BLOCK_MAX {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        data[0] = extra;
+        let len = data.len().min(u16::MAX as usize);
+        let data = &mut data[..len];
+        let flags = I2CMessageFlags::READ | I2CMessageFlags::USE_RECEIVE_LENGTH;
+        Ok(LinuxI2CMessage::read(data).with_flags(flags))
+    }
+
+    /// The length of the message buffer in bytes
+    ///
+    /// This is the length the message was created with.  For a
+    /// [`read_with_recv_len`](#method.read_with_recv_len) message it does
+    /// not reflect the count received: the kernel updates the length of its
+    /// own copy of the message during `I2C_RDWR`, but only copies the data
+    /// back to userspace.  Read the count from the first byte of the buffer
+    /// instead.
+    pub fn len(&self) -> u16 {
+        self.len
+    }
+
+    /// Whether the message buffer is empty, as for a zero-length write
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The target device address of the message
+    ///
+    /// This is a 10-bit address if the message has the `TEN_BIT_ADDRESS`
+    /// flag, as set by [`with_address`](#method.with_address) for 10-bit
+    /// addresses, and a 7-bit address otherwise.  Messages transferred
+    /// through a [`LinuxI2CDevice`] get the address of the device during the
+    /// transfer, so until then they are addressed to 0.
+    pub fn address(&self) -> Address {
+        if self.is_ten_bit() {
+            Address::TenBit(self.addr)
+        } else {
+            Address::SevenBit(self.addr as u8)
+        }
+    }
+
+    /// Whether the message has the `TEN_BIT_ADDRESS` flag
+    pub fn is_ten_bit(&self) -> bool {
+        I2CMessageFlags::from_bits_retain(self.flags).contains(I2CMessageFlags::TEN_BIT_ADDRESS)
+    }
+
     /// Set the target device address for the message
-    pub fn with_address(self, slave_address: u16) -> Self {
+    ///
+    /// Passing a 10-bit [`Address`] sets the
+    /// `TEN_BIT_ADDRESS` flag on the message, while a 7-bit address clears
+    /// it.  Note that a later call to `with_flags` replaces all flags.
+    pub fn with_address<A: Into<Address>>(self, slave_address: A) -> Self {
+        let slave_address = slave_address.into();
+        let mut flags = I2CMessageFlags::from_bits_retain(self.flags);
+        flags.set(I2CMessageFlags::TEN_BIT_ADDRESS, slave_address.is_ten_bit());
         Self {
-            addr: slave_address,
-            flags: self.flags,
+            addr: slave_address.value(),
+            flags: flags.bits(),
             len: self.len,
             buf: self.buf,
             _p: PhantomData,
@@ -409,4 +2166,1060 @@ impl<'a> LinuxI2CMessage<'a> {
             _p: PhantomData,
         }
     }
+
+    /// Send this message without a start condition and address, so that it
+    /// continues the previous message
+    ///
+    /// This sets the `NO_START` flag and keeps the other flags.  It needs
+    /// an adapter with `I2C_FUNC_NOSTART`.  Consecutive messages in the same
+    /// direction to a `LinuxI2CDevice` already get this flag.
+    pub fn with_no_start(self) -> Self {
+        let flags = I2CMessageFlags::from_bits_retain(self.flags) | I2CMessageFlags::NO_START;
+        self.with_flags(flags)
+    }
+
+    /// Send a stop condition after this message, even if more messages
+    /// follow in the same transfer
+    ///
+    /// This sets the `STOP` flag and keeps the other flags.  Without it,
+    /// messages in a transfer are separated by repeated starts.
+    pub fn with_stop(self) -> Self {
+        let flags = I2CMessageFlags::from_bits_retain(self.flags) | I2CMessageFlags::STOP;
+        self.with_flags(flags)
+    }
+}
+
+/// A sequence of messages, with their buffers, which can be issued on a bus
+/// any number of times
+///
+/// Building an array of [`LinuxI2CMessage`]s for every transfer is cheap,
+/// but a polling loop issuing the same transaction over and over repeats
+/// that work, along with allocating or borrowing the buffers.  A batch owns
+/// the buffers and the messages pointing into them, so `execute` only has
+/// to issue the `I2C_RDWR` ioctl.
+///
+/// ```rust,no_run
+/// # use i2cdev::linux::{LinuxI2CBus, LinuxI2CError, TransferBatch};
+/// # fn main() -> Result<(), LinuxI2CError> {
+/// let mut bus = LinuxI2CBus::new("/dev/i2c-1")?;
+/// let mut batch = TransferBatch::new();
+/// batch.push_write(0x48, &[0x00]);
+/// let reading = batch.push_read(0x48, 2);
+/// loop {
+///     batch.execute(&mut bus)?;
+///     println!("temperature: {:?}", batch.data(reading));
+/// }
+/// # }
+/// ```
+#[derive(Default)]
+pub struct TransferBatch {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+    // the buffers of these point into `data`, and are only valid while
+    // `execute` holds a mutable borrow of it
+    msgs: Vec<LinuxI2CMessage<'static>>,
+}
+
+// the raw pointers in `msgs` only ever point into `data`, which is owned
+unsafe impl Send for TransferBatch {}
+
+impl TransferBatch {
+    /// Create an empty batch
+    pub fn new() -> TransferBatch {
+        TransferBatch::default()
+    }
+
+    /// Append a message writing `data` to `slave_address`, returning its
+    /// index
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is longer than 65535 bytes.
+    pub fn push_write<A: Into<Address>>(&mut self, slave_address: A, data: &[u8]) -> usize {
+        let index = self.push(slave_address.into(), I2CMessageFlags::empty(), data.len());
+        let offset = self.offsets[index];
+        self.data[offset..].copy_from_slice(data);
+        index
+    }
+
+    /// Append a message reading `len` bytes from `slave_address`, returning
+    /// its index
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is more than 65535.
+    pub fn push_read<A: Into<Address>>(&mut self, slave_address: A, len: usize) -> usize {
+        self.push(slave_address.into(), I2CMessageFlags::READ, len)
+    }
+
+    fn push(&mut self, slave_address: Address, flags: I2CMessageFlags, len: usize) -> usize {
+        let msg_len = message_len(len);
+        let mut flags = flags;
+        flags.set(I2CMessageFlags::TEN_BIT_ADDRESS, slave_address.is_ten_bit());
+        self.offsets.push(self.data.len());
+        self.data.resize(self.data.len() + len, 0);
+        self.msgs.push(LinuxI2CMessage {
+            addr: slave_address.value(),
+            flags: flags.bits(),
+            len: msg_len,
+            buf: ptr::null(),
+            _p: PhantomData,
+        });
+        self.msgs.len() - 1
+    }
+
+    /// The buffer of the message at `index`
+    ///
+    /// After `execute`, the buffers of read messages hold the data read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no message at `index`.
+    pub fn data(&self, index: usize) -> &[u8] {
+        let offset = self.offsets[index];
+        &self.data[offset..offset + self.msgs[index].len as usize]
+    }
+
+    /// The buffer of the message at `index`, to update the data of a write
+    /// message between calls to `execute`
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no message at `index`.
+    pub fn data_mut(&mut self, index: usize) -> &mut [u8] {
+        let offset = self.offsets[index];
+        let len = self.msgs[index].len as usize;
+        &mut self.data[offset..offset + len]
+    }
+
+    /// The number of messages in the batch
+    pub fn len(&self) -> usize {
+        self.msgs.len()
+    }
+
+    /// Whether the batch has no messages
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    /// Remove all messages, keeping the allocated memory for reuse
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.offsets.clear();
+        self.msgs.clear();
+    }
+
+    /// Issue the messages on `bus` as one transaction
+    ///
+    /// A [`LinuxI2CDevice`] can issue a batch with
+    /// [`as_bus_mut`](struct.LinuxI2CDevice.html#method.as_bus_mut); the
+    /// addresses of the messages are used, not the address of the device.
+    /// On success the return code is the number of successfully executed
+    /// messages.
+    pub fn execute(&mut self, bus: &mut LinuxI2CBus) -> Result<u32, LinuxI2CError> {
+        let base = self.data.as_mut_ptr();
+        for (msg, &offset) in self.msgs.iter_mut().zip(&self.offsets) {
+            msg.buf = base.wrapping_add(offset);
+        }
+        rdwr(bus.as_raw_fd(), &mut self.msgs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockI2CDevice;
+    use std::env;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Instant;
+
+    /// A device backed by `/dev/null`, on which every ioctl fails
+    fn null_device() -> LinuxI2CDevice {
+        LinuxI2CDevice {
+            bus: LinuxI2CBus::new("/dev/null").unwrap(),
+            slave_address: Address::SevenBit(0),
+            pec: false,
+            functions: None,
+        }
+    }
+
+    /// Tests which need real hardware only run when `I2CDEV_TEST_BUS` and
+    /// `I2CDEV_TEST_ADDR` (in hex) name a bus and a device present on it
+    fn test_device() -> Option<LinuxI2CDevice> {
+        let bus = env::var("I2CDEV_TEST_BUS").ok()?;
+        let addr = env::var("I2CDEV_TEST_ADDR").ok()?;
+        let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16).ok()?;
+        Some(LinuxI2CDevice::new(bus, addr).unwrap())
+    }
+
+    #[test]
+    fn test_as_fd() {
+        let dev = null_device();
+        assert_eq!(dev.as_fd().as_raw_fd(), dev.as_raw_fd());
+        let bus = dev.into_bus();
+        assert_eq!(bus.as_fd().as_raw_fd(), bus.as_raw_fd());
+    }
+
+    #[test]
+    fn test_from_raw_fd() {
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        let bus = unsafe { LinuxI2CBus::from_raw_fd(fd) };
+        assert_eq!(bus.as_raw_fd(), fd);
+
+        let fd = File::open("/dev/null").unwrap().into_raw_fd();
+        match unsafe { LinuxI2CDevice::from_raw_fd(fd, 0x50) } {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_transfer_batch() {
+        let mut batch = TransferBatch::new();
+        assert!(batch.is_empty());
+        let write = batch.push_write(0x48, &[0x01, 0x02]);
+        let read = batch.push_read(Address::TenBit(0x150), 3);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.data(write), &[0x01, 0x02]);
+        assert_eq!(batch.data(read), &[0, 0, 0]);
+        assert_eq!(
+            batch.msgs[read].flags,
+            (I2CMessageFlags::READ | I2CMessageFlags::TEN_BIT_ADDRESS).bits()
+        );
+
+        batch.data_mut(write)[1] = 0x03;
+        assert_eq!(batch.data(write), &[0x01, 0x03]);
+
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        match batch.execute(&mut bus) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.push_read(0x48, 1), 0);
+    }
+
+    #[test]
+    fn test_smbus_transfer_values() {
+        // passed to the kernel as is, so they must match linux/i2c.h
+        assert_eq!(SmbusReadWrite::Write as u8, 0);
+        assert_eq!(SmbusReadWrite::Read as u8, 1);
+        let sizes = [
+            SmbusSize::Quick,
+            SmbusSize::Byte,
+            SmbusSize::ByteData,
+            SmbusSize::WordData,
+            SmbusSize::ProcCall,
+            SmbusSize::BlockData,
+            SmbusSize::I2CBlockBroken,
+            SmbusSize::BlockProcCall,
+            SmbusSize::I2CBlockData,
+        ];
+        for (value, size) in sizes.iter().enumerate() {
+            assert_eq!(*size as u32, value as u32);
+        }
+    }
+
+    #[test]
+    fn test_errno_round_trip() {
+        let err = LinuxI2CError::from(nix::Error::ENXIO);
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.raw_os_error(), Some(libc::ENXIO));
+
+        let err = LinuxI2CError::from(io_err);
+        assert_eq!(err.kind(), LinuxI2CErrorKind::NoDevice);
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.raw_os_error(), Some(libc::ENXIO));
+        assert_eq!(
+            nix::Error::from_i32(io_err.raw_os_error().unwrap()),
+            nix::Error::ENXIO
+        );
+    }
+
+    #[test]
+    fn test_transfer_raw_keeps_addresses() {
+        let mut dev = null_device();
+        dev.slave_address = Address::SevenBit(0x50);
+        let mut data = [0; 2];
+        let mut msgs = [
+            LinuxI2CMessage::write(&[0x01]).with_address(0x20),
+            LinuxI2CMessage::read(&mut data).with_address(0x21),
+        ];
+        assert!(dev.transfer_raw(&mut msgs).is_err());
+        assert_eq!((msgs[0].addr, msgs[1].addr), (0x20, 0x21));
+        assert_eq!(msgs[1].flags, I2CMessageFlags::READ.bits());
+    }
+
+    #[test]
+    fn test_read_i2c_block_data_into() {
+        let mut dev = null_device();
+        match dev.smbus_read_i2c_block_data_into(0x00, &mut [0; 33]) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match dev.smbus_read_i2c_block_data_into(0x00, &mut [0; 32]) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut dev = null_device();
+        dev.write(&[0x01]).unwrap();
+        dev.flush().unwrap();
+    }
+
+    #[test]
+    fn test_reported_block() {
+        let mut buf = [0; SMBUS_BLOCK_MAX];
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        assert!(reported_block(0, &buf).is_empty());
+        assert_eq!(reported_block(3, &buf), &[0, 1, 2]);
+        assert_eq!(reported_block(32, &buf), &buf[..]);
+        // an over-reported count keeps the 32 bytes the adapter returned
+        assert_eq!(reported_block(33, &buf), &buf[..]);
+        assert_eq!(reported_block(0xFF, &buf), &buf[..]);
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let registers = [0x10, 0x11, 0x12, 0x13];
+        let read = |register: u8| -> Result<u8, u8> {
+            registers
+                .get(usize::from(register))
+                .copied()
+                .ok_or(register)
+        };
+        assert_eq!(read_registers_with(&[], read), Ok(vec![]));
+        assert_eq!(
+            read_registers_with(&[0x03, 0x00, 0x03], read),
+            Ok(vec![(0x03, 0x13), (0x00, 0x10), (0x03, 0x13)])
+        );
+
+        let mut reads = Vec::new();
+        let result = read_registers_with(&[0x01, 0x0F, 0x02], |register| {
+            reads.push(register);
+            read(register)
+        });
+        assert_eq!(result, Err(0x0F));
+        assert_eq!(reads, vec![0x01, 0x0F]);
+    }
+
+    #[test]
+    fn test_too_many_messages() {
+        let data = [0; MAX_TRANSFER_MESSAGES + 1];
+        let mut msgs: Vec<_> = data.chunks(1).map(LinuxI2CMessage::write).collect();
+        let mut dev = null_device();
+        match dev.transfer(&mut msgs) {
+            Err(LinuxI2CError::TooManyMessages { count: 43 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        let err = bus.transfer(&mut msgs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "43 messages exceed the limit of 42 per transfer"
+        );
+        assert_eq!(err.kind(), LinuxI2CErrorKind::Other);
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidInput);
+
+        // at the limit, the messages reach /dev/null, which has no ioctls
+        match bus.transfer(&mut msgs[..MAX_TRANSFER_MESSAGES]) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_eq() {
+        use std::collections::HashSet;
+
+        assert_eq!(
+            LinuxI2CError::Errno(libc::ENXIO),
+            LinuxI2CError::Errno(libc::ENXIO)
+        );
+        assert_ne!(
+            LinuxI2CError::Errno(libc::ENXIO),
+            LinuxI2CError::Errno(libc::EIO)
+        );
+        assert_ne!(
+            LinuxI2CError::Errno(libc::EIO),
+            LinuxI2CError::Io(io::Error::from_raw_os_error(libc::EIO))
+        );
+        assert_eq!(
+            LinuxI2CError::Io(io::Error::from_raw_os_error(libc::EIO)),
+            LinuxI2CError::Io(io::Error::from_raw_os_error(libc::EIO))
+        );
+        // messages are not compared
+        assert_eq!(
+            LinuxI2CError::Io(io::Error::new(io::ErrorKind::InvalidInput, "a")),
+            LinuxI2CError::Io(io::Error::new(io::ErrorKind::InvalidInput, "b"))
+        );
+        assert_ne!(
+            LinuxI2CError::Io(io::Error::new(io::ErrorKind::InvalidInput, "a")),
+            LinuxI2CError::Io(io::Error::new(io::ErrorKind::InvalidData, "a"))
+        );
+        assert_ne!(
+            LinuxI2CError::PartialTransfer {
+                completed: 1,
+                total: 2
+            },
+            LinuxI2CError::PartialTransfer {
+                completed: 0,
+                total: 2
+            }
+        );
+
+        let errors: HashSet<_> = vec![
+            LinuxI2CError::Errno(libc::ENXIO),
+            LinuxI2CError::Errno(libc::ENXIO),
+            LinuxI2CError::TooManyMessages { count: 43 },
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_address_in_use() {
+        let err = address_error(nix::Error::EBUSY, Address::SevenBit(0x1d));
+        assert_eq!(err, LinuxI2CError::AddressInUse(0x1d));
+        assert_eq!(
+            err.to_string(),
+            "address 0x1d is in use by a kernel driver, \
+             LinuxI2CDevice::force_new can access it anyway"
+        );
+        assert_eq!(err.kind(), LinuxI2CErrorKind::Other);
+        assert_eq!(io::Error::from(err).raw_os_error(), Some(libc::EBUSY));
+
+        let err = address_error(nix::Error::ENOTTY, Address::SevenBit(0x1d));
+        assert_eq!(err, LinuxI2CError::Errno(libc::ENOTTY));
+    }
+
+    #[test]
+    fn test_check_transfer() {
+        assert!(check_transfer(2, 2).is_ok());
+        let err = check_transfer(1, 3).unwrap_err();
+        assert_eq!(err.to_string(), "transfer stopped after 1 of 3 messages");
+        assert_eq!(err.kind(), LinuxI2CErrorKind::Io);
+        match err {
+            LinuxI2CError::PartialTransfer {
+                completed: 1,
+                total: 3,
+            } => {}
+            other => panic!("unexpected error {:?}", other),
+        }
+        // a write-read whose read was not executed, as write_read checks
+        assert!(check_transfer(1, 2).is_err());
+    }
+
+    #[test]
+    fn test_list_buses() {
+        let dir = env::temp_dir().join(format!("i2cdev-list-{}", std::process::id()));
+        fs::create_dir(&dir).unwrap();
+        for name in &["i2c-10", "i2c-2", "i2c-x", "spidev0.0", "i2c-0"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let buses = list_buses(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            buses.unwrap(),
+            vec![dir.join("i2c-0"), dir.join("i2c-2"), dir.join("i2c-10")]
+        );
+        assert!(LinuxI2CBus::adapter_name("/").is_err());
+    }
+
+    #[test]
+    fn test_bus_name() {
+        assert_eq!(dev_major_minor(0x5901), (89, 1));
+        assert_eq!(
+            dev_major_minor(0x1234_5678_9abc_def0),
+            (0x1234_5cde, 0x6789_abf0)
+        );
+        // /dev/null is a character device, but not an I2C bus
+        let err = LinuxI2CBus::new("/dev/null").unwrap().name().unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let mut dev = null_device();
+        dev.slave_address = Address::TenBit(0x150);
+        dev.pec = true;
+        let clone = dev.try_clone().unwrap();
+        assert_eq!(clone.address(), Address::TenBit(0x150));
+        assert!(clone.pec_enabled());
+        assert_ne!(clone.as_raw_fd(), dev.as_raw_fd());
+    }
+
+    #[test]
+    fn test_write_block_chunked() {
+        let data: Vec<u8> = (1..=70).collect();
+        let mut dev = MockI2CDevice::new();
+        write_i2c_block_chunks(&mut dev, 0x10, &data, SMBUS_BLOCK_MAX, Duration::ZERO).unwrap();
+        let mut read = vec![0; 70];
+        dev.write(&[0x10]).unwrap();
+        dev.read(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        let mut dev = null_device();
+        match dev.smbus_write_block_chunked(0xF0, &[0; 17]) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(dev.smbus_write_block_chunked(0xF0, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_write_paged() {
+        let data: Vec<u8> = (1..=20).collect();
+        let mut dev = MockI2CDevice::new();
+        let delay = Duration::from_millis(1);
+        let start = Instant::now();
+        write_i2c_block_chunks(&mut dev, 0x08, &data, 8, delay).unwrap();
+        // three pages, with a delay between each
+        assert!(start.elapsed() >= 2 * delay);
+        let mut read = vec![0; 20];
+        dev.write(&[0x08]).unwrap();
+        dev.read(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        let mut dev = null_device();
+        for &(start, page_size) in &[(0x00, 0), (0x00, 33), (0xF8, 8)] {
+            match dev.smbus_write_paged(start, &data, page_size, delay) {
+                Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_word_data_emulation() {
+        let i2c_only = I2CFunctions::I2C_FUNC_I2C;
+        let smbus = i2c_only | I2CFunctions::I2C_FUNC_SMBUS_WORD_DATA;
+        let word = I2CFunctions::I2C_FUNC_SMBUS_READ_WORD_DATA;
+        assert!(needs_emulation(i2c_only, word));
+        assert!(!needs_emulation(smbus, word));
+        assert!(!needs_emulation(I2CFunctions::I2C_FUNC_SMBUS_QUICK, word));
+
+        let mut smbus_dev = MockI2CDevice::new();
+        let mut i2c_dev = MockI2CDevice::new();
+        smbus_dev.smbus_write_word_data(0x10, 0xBEEF).unwrap();
+        transfer_write_word_data(&mut i2c_dev, 0x10, 0xBEEF).unwrap();
+        for register in 0x10..0x12 {
+            assert_eq!(
+                smbus_dev.smbus_read_byte_data(register).unwrap(),
+                i2c_dev.smbus_read_byte_data(register).unwrap()
+            );
+        }
+
+        assert_eq!(
+            smbus_dev.smbus_read_word_data(0x10).unwrap(),
+            transfer_read_word_data(&mut i2c_dev, 0x10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_device_into_bus() {
+        let dev = match test_device() {
+            Some(dev) => dev,
+            None => return,
+        };
+        let address = dev.slave_address;
+        let mut bus = dev.into_bus();
+        let mut data = [0; 1];
+        let mut msgs = [LinuxI2CMessage::read(&mut data).with_address(address)];
+        assert_eq!(bus.transfer(&mut msgs).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_functionality() {
+        assert_eq!(
+            null_device().functionality().unwrap_err().to_string(),
+            LinuxI2CError::Errno(libc::ENOTTY).to_string()
+        );
+        if let Some(dev) = test_device() {
+            assert!(dev.functionality().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_supports() {
+        let mut dev = null_device();
+        match dev.supports_block_read() {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(dev.as_bus().supports(I2CFunctions::I2C_FUNC_I2C).is_err());
+
+        // the cached functionality is used without an ioctl
+        dev.functions = Some(I2CFunctions::I2C_FUNC_I2C | I2CFunctions::I2C_FUNC_SMBUS_WORD_DATA);
+        assert!(dev.supports_word_data().unwrap());
+        assert!(!dev.supports_byte_data().unwrap());
+        assert!(!dev.supports_block_write().unwrap());
+        assert!(dev
+            .supports(I2CFunctions::I2C_FUNC_I2C | I2CFunctions::I2C_FUNC_SMBUS_READ_WORD_DATA)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_ten_bit_address_range() {
+        match LinuxI2CDevice::new_10bit("/dev/null", 0x400) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        let mut device = null_device();
+        device.slave_address = Address::TenBit(0x150);
+        match device.set_ten_bit_addressing(false) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(device.slave_address, Address::TenBit(0x150));
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let builder = LinuxI2CDevice::builder()
+            .pec(true)
+            .retries(2)
+            .timeout(Duration::from_secs(0));
+        assert!(builder.pec);
+        assert_eq!(builder.retries, Some(2));
+        assert!(LinuxI2CDevice::builder()
+            .timeout(Duration::from_secs(0))
+            .open("/dev/null", 0x50)
+            .is_err());
+        match LinuxI2CDevice::builder()
+            .ten_bit(true)
+            .open("/dev/null", 0x400)
+        {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        match LinuxI2CDevice::builder()
+            .ten_bit(true)
+            .open("/dev/null", Address::TenBit(0x400))
+        {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_read_len_short_read() {
+        let mut device = null_device();
+        let mut buf = [0; 4];
+        assert_eq!(device.read_len(&mut buf).unwrap(), 0);
+        assert!(device.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_read_block_data_into_error() {
+        let mut device = null_device();
+        let mut buf = [0; 32];
+        match device.smbus_read_block_data_into(0x10, &mut buf) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut buf = [0; 4];
+        match device.smbus_read_block_data_into_slice(0x10, &mut buf) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_timeout_to_ticks() {
+        assert_eq!(timeout_to_ticks(Duration::from_secs(0)), None);
+        assert_eq!(timeout_to_ticks(Duration::from_nanos(1)), Some(1));
+        assert_eq!(timeout_to_ticks(Duration::from_millis(10)), Some(1));
+        assert_eq!(timeout_to_ticks(Duration::from_micros(10_001)), Some(2));
+        assert_eq!(timeout_to_ticks(Duration::from_secs(1)), Some(100));
+        assert_eq!(
+            timeout_to_ticks(Duration::from_secs(u64::MAX)),
+            Some(i32::MAX)
+        );
+    }
+
+    #[test]
+    fn test_describe_functionality() {
+        let funcs = I2CFunctions::from_bits_truncate(0x0fff_8009);
+        assert_eq!(
+            funcs.describe(),
+            vec![
+                "I2C",
+                "SMBUS_PEC",
+                "SMBUS_QUICK",
+                "SMBUS_READ_BYTE",
+                "SMBUS_WRITE_BYTE",
+                "SMBUS_READ_BYTE_DATA",
+                "SMBUS_WRITE_BYTE_DATA",
+                "SMBUS_READ_WORD_DATA",
+                "SMBUS_WRITE_WORD_DATA",
+                "SMBUS_PROC_CALL",
+                "SMBUS_READ_BLOCK_DATA",
+                "SMBUS_WRITE_BLOCK_DATA",
+                "SMBUS_BLOCK_PROC_CALL",
+                "SMBUS_READ_I2C_BLOCK",
+                "SMBUS_WRITE_I2C_BLOCK",
+            ]
+        );
+        assert!(I2CFunctions::empty().describe().is_empty());
+    }
+
+    #[test]
+    fn test_read_with_recv_len() {
+        let mut short = [0; 32];
+        assert!(LinuxI2CMessage::read_with_recv_len(&mut short).is_err());
+
+        let mut data = [0xFF; 33];
+        {
+            let msg = LinuxI2CMessage::read_with_recv_len(&mut data).unwrap();
+            let expected = I2CMessageFlags::READ | I2CMessageFlags::USE_RECEIVE_LENGTH;
+            assert_eq!(msg.flags, expected.bits());
+            assert_eq!(msg.len(), 33);
+            assert!(!msg.is_empty());
+        }
+        assert_eq!(data[0], 1);
+
+        // room for the PEC byte is needed as well
+        let mut short = [0; 33];
+        assert!(LinuxI2CMessage::read_with_recv_len_pec(&mut short).is_err());
+        let mut data = [0xFF; 34];
+        {
+            let msg = LinuxI2CMessage::read_with_recv_len_pec(&mut data).unwrap();
+            assert_eq!(msg.len(), 34);
+        }
+        assert_eq!(data[0], 2);
+
+        let mut long = [0; 0x1_0001];
+        let msg = LinuxI2CMessage::read_with_recv_len(&mut long).unwrap();
+        assert_eq!(msg.len(), u16::MAX);
+    }
+
+    #[test]
+    fn test_message_len() {
+        assert_eq!(LinuxI2CMessage::write(&[1, 2, 3]).len(), 3);
+        assert!(LinuxI2CMessage::write(&[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "message longer than 65535 bytes")]
+    fn test_message_len_overflow() {
+        let data = vec![0; 0x1_0000];
+        LinuxI2CMessage::write(&data);
+    }
+
+    #[test]
+    fn test_message_address() {
+        let msg = LinuxI2CMessage::write(&[0x01]);
+        assert_eq!(msg.address(), Address::SevenBit(0));
+        assert!(!msg.is_ten_bit());
+
+        let msg = msg.with_address(Address::TenBit(0x150));
+        assert_eq!(msg.address(), Address::TenBit(0x150));
+        assert!(msg.is_ten_bit());
+        // a 10-bit address below 0x80 keeps its kind
+        let msg = msg.with_address(Address::TenBit(0x50));
+        assert_eq!(msg.address(), Address::TenBit(0x50));
+
+        let msg = msg.with_address(0x50);
+        assert_eq!(msg.address(), Address::SevenBit(0x50));
+        assert!(!msg.is_ten_bit());
+    }
+
+    #[test]
+    fn test_message_flag_shortcuts() {
+        let mut data = [0; 2];
+        let msg = LinuxI2CMessage::read(&mut data)
+            .with_address(Address::TenBit(0x150))
+            .with_no_start()
+            .with_stop();
+        let expected = I2CMessageFlags::READ
+            | I2CMessageFlags::TEN_BIT_ADDRESS
+            | I2CMessageFlags::NO_START
+            | I2CMessageFlags::STOP;
+        assert_eq!(msg.flags, expected.bits());
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let kind = |errno| LinuxI2CError::Errno(errno).kind();
+        assert_eq!(kind(libc::ENXIO), LinuxI2CErrorKind::NoDevice);
+        assert_eq!(kind(libc::ENODEV), LinuxI2CErrorKind::NoDevice);
+        assert_eq!(kind(libc::EREMOTEIO), LinuxI2CErrorKind::Nack);
+        assert_eq!(kind(libc::ETIMEDOUT), LinuxI2CErrorKind::Timeout);
+        assert_eq!(kind(libc::EAGAIN), LinuxI2CErrorKind::Arbitration);
+        assert_eq!(kind(libc::EIO), LinuxI2CErrorKind::Io);
+        assert_eq!(kind(libc::EINVAL), LinuxI2CErrorKind::Other);
+
+        let io_error = io::Error::from_raw_os_error(libc::EAGAIN);
+        assert_eq!(
+            LinuxI2CError::Io(io_error).kind(),
+            LinuxI2CErrorKind::Arbitration
+        );
+        let io_error = io::Error::new(io::ErrorKind::UnexpectedEof, "short read");
+        assert_eq!(LinuxI2CError::Io(io_error).kind(), LinuxI2CErrorKind::Io);
+    }
+
+    #[test]
+    fn test_scan_result_classification() {
+        let mut result = ScanResult::default();
+        result.record(0x20, Ok(()));
+        result.record(0x21, Err(LinuxI2CError::Errno(libc::ENXIO)));
+        result.record(0x22, Err(LinuxI2CError::Errno(libc::EBUSY)));
+        result.record(0x23, Err(LinuxI2CError::Errno(libc::EACCES)));
+        result.record(0x24, Err(LinuxI2CError::AddressInUse(0x24)));
+        assert_eq!(result.present, vec![0x20]);
+        assert_eq!(result.busy, vec![0x22, 0x24]);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].0, 0x23);
+
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        assert!(bus.scan_detailed().is_err());
+        assert!(bus.scan().is_err());
+    }
+
+    #[test]
+    fn test_probe_ack() {
+        assert!(probe_ack(Ok(())).unwrap());
+        for &errno in &[libc::ENXIO, libc::EREMOTEIO, libc::EIO] {
+            assert!(!probe_ack(Err(LinuxI2CError::Errno(errno))).unwrap());
+        }
+        match probe_ack(Err(LinuxI2CError::AddressInUse(0x48))) {
+            Err(LinuxI2CError::AddressInUse(0x48)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match probe_ack(Err(LinuxI2CError::Errno(libc::ETIMEDOUT))) {
+            Err(LinuxI2CError::Errno(libc::ETIMEDOUT)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_finish_slave_address() {
+        let mut dev = null_device();
+        dev.slave_address = Address::SevenBit(0x50);
+        // restoring ten bit addressing fails on /dev/null as well, but the
+        // error of the rejected address is the one returned
+        match dev.finish_slave_address(
+            Address::TenBit(0x150),
+            Err(LinuxI2CError::AddressInUse(0x150)),
+        ) {
+            Err(LinuxI2CError::AddressInUse(0x150)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(dev.address(), Address::SevenBit(0x50));
+
+        dev.finish_slave_address(Address::TenBit(0x150), Ok(()))
+            .unwrap();
+        assert_eq!(dev.address(), Address::TenBit(0x150));
+    }
+
+    #[test]
+    fn test_address_ack_invalid() {
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        // an 8-bit datasheet address is not sent as a 10-bit one
+        match bus.address_ack(0xA0) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match bus.address_ack(Address::TenBit(0x400)) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_alert_response() {
+        assert_eq!(alert_response(Ok(0x90)).unwrap(), Some((0x48, 0)));
+        assert_eq!(alert_response(Ok(0x91)).unwrap(), Some((0x48, 1)));
+        assert_eq!(alert_response(Ok(0xFF)).unwrap(), Some((0x7F, 1)));
+        for &errno in &[libc::ENXIO, libc::EREMOTEIO, libc::EIO] {
+            assert_eq!(
+                alert_response(Err(LinuxI2CError::Errno(errno))).unwrap(),
+                None
+            );
+        }
+        // anything else is a real failure rather than no device alerting
+        match alert_response(Err(LinuxI2CError::Errno(libc::ENOTTY))) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bus_device() {
+        let bus = LinuxI2CBus::new("/dev/null").unwrap();
+        // the file is opened again, but /dev/null has no ioctls
+        match bus.device(0x50) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("unexpected success"),
+        }
+
+        let bus = match test_device() {
+            Some(dev) => dev.into_bus(),
+            None => return,
+        };
+        let dev = bus.device(0x08).unwrap();
+        assert_ne!(dev.as_raw_fd(), bus.as_raw_fd());
+        assert_eq!(dev.address(), Address::SevenBit(0x08));
+    }
+
+    #[test]
+    fn test_probe_selects_seven_bit() {
+        // probing selects the address like the SMBus methods of the bus,
+        // validating it before touching the file
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        match bus.probe(0x80) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // a 10-bit address selected before must not leave ten bit
+        // addressing on for the probe
+        let (mut bus, address) = match test_device() {
+            Some(dev) => {
+                let address = dev.slave_address();
+                (dev.into_bus(), address)
+            }
+            None => return,
+        };
+        assert!(bus.probe(address).unwrap());
+        let _ = bus.smbus_read_byte_data(Address::TenBit(0x3FF), 0x00);
+        assert!(bus.probe(address).unwrap());
+        let _ = bus.smbus_read_byte_data(Address::TenBit(0x3FF), 0x00);
+        match bus.smbus_read_alert() {
+            Ok(_) | Err(LinuxI2CError::AddressInUse(_)) => {}
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_bus_smbus_invalid_address() {
+        // rejected before any ioctl
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        match bus.smbus_write_byte_data(Address::TenBit(0x400), 0x00, 0x00) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_transfer_ack() {
+        assert!(transfer_ack(Ok(1)).unwrap());
+        for &errno in &[libc::ENXIO, libc::ENODEV, libc::EREMOTEIO] {
+            assert!(!transfer_ack(Err(LinuxI2CError::Errno(errno))).unwrap());
+        }
+        // unlike probe, a plain EIO is a failure rather than an empty address
+        for &errno in &[libc::EIO, libc::EOPNOTSUPP, libc::ETIMEDOUT] {
+            match transfer_ack(Err(LinuxI2CError::Errno(errno))) {
+                Err(LinuxI2CError::Errno(e)) if e == errno => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bus_set_smbus_pec() {
+        let mut bus = match test_device() {
+            Some(dev) => dev.into_bus(),
+            None => return,
+        };
+        bus.set_smbus_pec(false).unwrap();
+    }
+
+    #[test]
+    fn test_recover_bus_unsupported() {
+        let mut bus = LinuxI2CBus::new("/dev/null").unwrap();
+        match bus.recover_bus() {
+            Err(LinuxI2CError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pec_guard_without_change() {
+        let mut dev = null_device();
+        assert!(!dev.with_smbus_pec(false).unwrap().pec_enabled());
+        assert!(dev.pec_supported().is_err());
+        assert!(dev.with_smbus_pec(true).is_err());
+        assert!(!dev.pec);
+    }
+
+    #[test]
+    fn test_pec_guard_restores() {
+        // a guard as with_smbus_pec(true) leaves it; restoring the setting
+        // on /dev/null fails, but the previous setting is recorded anyway
+        let mut dev = null_device();
+        dev.pec = true;
+        {
+            let guard = SmbusPecGuard {
+                device: &mut dev,
+                previous: false,
+            };
+            assert!(guard.pec_enabled());
+        }
+        assert!(!dev.pec_enabled());
+
+        dev.pec = true;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = SmbusPecGuard {
+                device: &mut dev,
+                previous: false,
+            };
+            panic!("restore on unwind");
+        }));
+        assert!(result.is_err());
+        assert!(!dev.pec_enabled());
+
+        let mut dev = match test_device() {
+            Some(dev) => dev,
+            None => return,
+        };
+        {
+            let guard = dev.with_smbus_pec(true).unwrap();
+            assert!(guard.pec_enabled());
+        }
+        assert!(!dev.pec_enabled());
+    }
+
+    #[test]
+    fn test_temporary_address_error() {
+        let mut dev = null_device();
+        let result = dev.with_temporary_address(0x51, |_| panic!("not run"));
+        match result {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(dev.address(), Address::SevenBit(0));
+    }
+
+    #[test]
+    fn test_temporary_address_restores() {
+        let mut dev = match test_device() {
+            Some(dev) => dev,
+            None => return,
+        };
+        let address = dev.address();
+        let temporary = dev
+            .with_temporary_address(0x08, |dev| dev.address())
+            .unwrap();
+        assert_eq!(temporary, Address::SevenBit(0x08));
+        assert_eq!(dev.slave_address(), address.value());
+        assert_eq!(dev.address(), address);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = dev.with_temporary_address(0x08, |_| panic!("restore on unwind"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(dev.address(), address);
+    }
 }
@@ -0,0 +1,233 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The i2c-dev character device does not support `poll`, so it cannot be
+//! registered with the reactor through `tokio::io::unix::AsyncFd`; every
+//! read, write and ioctl on it blocks until the transfer completes.  Instead,
+//! each operation of [`AsyncLinuxI2CDevice`] runs on tokio's blocking thread
+//! pool with `spawn_blocking`, which keeps the reactor free while the bus is
+//! busy.
+//!
+//! This module is available with the `tokio` feature.
+
+use crate::core::{I2CDevice, I2CMessage, I2CTransfer};
+use crate::linux::{LinuxI2CDevice, LinuxI2CError, LinuxI2CMessage};
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::task::{self, JoinHandle};
+
+/// A message for [`AsyncLinuxI2CDevice::transfer`] which owns its buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsyncI2CMessage {
+    /// Read from the device to fill the buffer
+    Read(Vec<u8>),
+    /// Write the buffer to the device
+    Write(Vec<u8>),
+}
+
+/// A [`LinuxI2CDevice`] whose operations run on tokio's blocking thread pool
+///
+/// Operations take `&self` and lock the device for their whole duration, so
+/// operations started concurrently, for example by sharing the device
+/// between tasks in an `Arc`, are serialized rather than interleaved on the
+/// bus.  Note that this only holds for single operations: an exchange of
+/// several messages which must not be interrupted by another task has to be
+/// issued as one [`transfer`](#method.transfer) or
+/// [`with_device`](#method.with_device) call.
+///
+/// Operations are started when the method is called, which must happen
+/// within a tokio runtime.  Dropping a returned future does not cancel the
+/// operation; it still runs to completion on the blocking pool.
+pub struct AsyncLinuxI2CDevice {
+    device: Arc<Mutex<LinuxI2CDevice>>,
+}
+
+impl AsyncLinuxI2CDevice {
+    /// Wrap an opened device
+    pub fn new(device: LinuxI2CDevice) -> AsyncLinuxI2CDevice {
+        AsyncLinuxI2CDevice {
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    /// Open a device for the specified path and 7-bit slave address
+    ///
+    /// Opening the device issues ioctls, so this is done on the blocking
+    /// pool as well.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        slave_address: u16,
+    ) -> AsyncOperation<AsyncLinuxI2CDevice> {
+        let path = path.as_ref().to_path_buf();
+        spawn(move || LinuxI2CDevice::new(path, slave_address).map(AsyncLinuxI2CDevice::new))
+    }
+
+    /// Run a closure with exclusive access to the device on the blocking
+    /// pool
+    ///
+    /// This is how the SMBus methods and any other blocking API of
+    /// [`LinuxI2CDevice`] are used:
+    ///
+    /// ```rust,no_run
+    /// # use i2cdev::core::I2CDevice;
+    /// # use i2cdev::linux_async::AsyncLinuxI2CDevice;
+    /// # async fn example(device: AsyncLinuxI2CDevice) -> Result<u16, i2cdev::linux::LinuxI2CError> {
+    /// let temperature = device
+    ///     .with_device(|dev| dev.smbus_read_word_data(0x05))
+    ///     .await?;
+    /// # Ok(temperature)
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn with_device<F, T>(&self, f: F) -> AsyncOperation<T>
+    where
+        F: FnOnce(&mut LinuxI2CDevice) -> Result<T, LinuxI2CError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let device = Arc::clone(&self.device);
+        spawn(move || {
+            // a panic in an earlier closure does not leave the device in
+            // an inconsistent state
+            let mut device = device.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut device)
+        })
+    }
+
+    /// Read `len` bytes from the device
+    pub fn read(&self, len: usize) -> AsyncOperation<Vec<u8>> {
+        self.with_device(move |dev| {
+            let mut data = vec![0; len];
+            dev.read(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    /// Write the provided buffer to the device
+    pub fn write(&self, data: Vec<u8>) -> AsyncOperation<()> {
+        self.with_device(move |dev| dev.write(&data))
+    }
+
+    /// Issue the provided sequence of I2C messages as one transaction
+    ///
+    /// The messages are returned with their read buffers filled in.
+    pub fn transfer(&self, messages: Vec<AsyncI2CMessage>) -> AsyncOperation<Vec<AsyncI2CMessage>> {
+        self.with_device(move |dev| {
+            let mut messages = messages;
+            {
+                let mut msgs: Vec<LinuxI2CMessage> = messages
+                    .iter_mut()
+                    .map(|message| match *message {
+                        AsyncI2CMessage::Read(ref mut data) => LinuxI2CMessage::read(data),
+                        AsyncI2CMessage::Write(ref data) => LinuxI2CMessage::write(data),
+                    })
+                    .collect();
+                dev.transfer(&mut msgs)?;
+            }
+            Ok(messages)
+        })
+    }
+}
+
+impl From<LinuxI2CDevice> for AsyncLinuxI2CDevice {
+    fn from(device: LinuxI2CDevice) -> AsyncLinuxI2CDevice {
+        AsyncLinuxI2CDevice::new(device)
+    }
+}
+
+/// Future for an operation running on tokio's blocking thread pool
+///
+/// If the operation panics, the future resolves to an `Io` error.
+#[derive(Debug)]
+pub struct AsyncOperation<T> {
+    handle: JoinHandle<Result<T, LinuxI2CError>>,
+}
+
+impl<T> Future for AsyncOperation<T> {
+    type Output = Result<T, LinuxI2CError>;
+
+    #[allow(clippy::io_other_error)] // io::Error::other is newer than the MSRV
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match Pin::new(&mut self.handle).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(LinuxI2CError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                e,
+            )))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+fn spawn<F, T>(f: F) -> AsyncOperation<T>
+where
+    F: FnOnce() -> Result<T, LinuxI2CError> + Send + 'static,
+    T: Send + 'static,
+{
+    AsyncOperation {
+        handle: task::spawn_blocking(f),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use tokio::runtime::{Builder, Runtime};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn test_panic_is_error() {
+        let runtime = runtime();
+        let _guard = runtime.enter();
+        let result = runtime.block_on(spawn(|| -> Result<(), LinuxI2CError> {
+            panic!("operation failed")
+        }));
+        match result {
+            Err(LinuxI2CError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::Other),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_open_error() {
+        let runtime = runtime();
+        let _guard = runtime.enter();
+        let result = runtime.block_on(AsyncLinuxI2CDevice::open("/dev/null", 0x50));
+        assert!(result.is_err());
+    }
+
+    /// Only runs when `I2CDEV_TEST_BUS` and `I2CDEV_TEST_ADDR` (in hex) name
+    /// a bus and a device present on it
+    #[test]
+    fn test_transfer() {
+        let (bus, addr) = match (env::var("I2CDEV_TEST_BUS"), env::var("I2CDEV_TEST_ADDR")) {
+            (Ok(bus), Ok(addr)) => (bus, addr),
+            _ => return,
+        };
+        let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16).unwrap();
+        let runtime = runtime();
+        let _guard = runtime.enter();
+        let device = runtime
+            .block_on(AsyncLinuxI2CDevice::open(bus, addr))
+            .unwrap();
+        let messages = runtime
+            .block_on(device.transfer(vec![AsyncI2CMessage::Read(vec![0; 2])]))
+            .unwrap();
+        match messages[0] {
+            AsyncI2CMessage::Read(ref data) => assert_eq!(data.len(), 2),
+            ref other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
@@ -5,8 +5,11 @@
 // <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
 // option.  This file may not be copied, modified, or distributed
 // except according to those terms.
-use core::{I2CDevice, I2CMessage, I2CTransfer};
-use std::convert::TryFrom;
+use crate::core::{
+    Address, I2CDevice, I2CDeviceExt, I2CMessage, I2CMessageFlags, I2CTransfer, SMBUS_BLOCK_MAX,
+};
+use crate::crc8::crc8;
+use std::collections::VecDeque;
 use std::io;
 
 /// I2C mock result type
@@ -14,8 +17,12 @@ pub type I2CResult<T> = io::Result<T>;
 
 /// Mock I2C device register map
 pub struct I2CRegisterMap {
-    registers: [u8; 0xFF],
+    registers: Vec<u8>,
     offset: usize,
+    logging: bool,
+    auto_increment_bit: bool,
+    wide_addresses: bool,
+    valid_range: Option<(usize, usize)>,
 }
 
 impl Default for I2CRegisterMap {
@@ -25,17 +32,85 @@ impl Default for I2CRegisterMap {
 }
 
 impl I2CRegisterMap {
-    /// Create new mock I2C register map
+    /// Create new mock I2C register map with 256 registers
     pub fn new() -> I2CRegisterMap {
+        I2CRegisterMap::with_capacity(0x100)
+    }
+
+    /// Create a mock I2C register map with `len` registers
+    ///
+    /// Registers above 0xFF can only be reached through `write` with
+    /// 16-bit addresses, see
+    /// [`set_16bit_addresses`](#method.set_16bit_addresses), or by reading
+    /// on from a lower register.
+    pub fn with_capacity(len: usize) -> I2CRegisterMap {
         I2CRegisterMap {
-            registers: [0x00; 0xFF],
+            registers: vec![0x00; len],
             offset: 0,
+            logging: false,
+            auto_increment_bit: false,
+            wide_addresses: false,
+            valid_range: None,
         }
     }
 
+    /// Take the register address of writes from their first two bytes,
+    /// most significant first
+    ///
+    /// This models devices with 16-bit register addresses, like EEPROMs of
+    /// the 24C32 to 24C512 class.  The SMBus methods only send a one byte
+    /// register, so use `read` and `write` in this mode.
+    pub fn set_16bit_addresses(&mut self, enable: bool) {
+        self.wide_addresses = enable;
+    }
+
+    /// Log every register read and write
+    ///
+    /// With the `log` feature the accesses are logged at trace level with
+    /// target `i2cdev::mock`, otherwise they are printed to stdout.
+    pub fn set_logging(&mut self, logging: bool) {
+        self.logging = logging;
+    }
+
+    /// Ignore the top bit of the register address in writes
+    ///
+    /// Many devices, such as the L3G gyros and the LSM accelerometers, only
+    /// advance through consecutive registers in a multi-byte access when the
+    /// top bit of the register address is set, and do not decode it as part
+    /// of the address.  The mock always advances, so this just makes it
+    /// accept such register addresses.
+    pub fn set_auto_increment_bit(&mut self, enable: bool) {
+        self.auto_increment_bit = enable;
+    }
+
+    /// Only acknowledge accesses to the registers `start` to `end`,
+    /// inclusive
+    ///
+    /// Reads and writes touching a register outside the range fail with
+    /// `NotConnected`, as a device which does not acknowledge them would
+    /// make them fail, and leave the map as it was.  This includes a write
+    /// just selecting such a register.  `write_regs` is not restricted, so
+    /// tests can still set up the whole map.
+    pub fn set_valid_range(&mut self, start: usize, end: usize) {
+        self.valid_range = Some((start, end));
+    }
+
+    /// The contents of all registers
+    ///
+    /// This is a snapshot for tests to check what a driver wrote, which
+    /// unlike reading through the device does not move the current offset.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// The contents of the register `reg`
+    pub fn register(&self, reg: u8) -> u8 {
+        self.registers[usize::from(reg)]
+    }
+
     /// Set several registers starting at the given offset
     pub fn write_regs(&mut self, offset: usize, data: &[u8]) {
-        println!("WRITE | 0x{:X} : {:?}", offset, data);
+        self.log_access("WRITE", offset, data);
         self.registers[offset..(data.len() + offset)].clone_from_slice(data);
     }
 }
@@ -44,33 +119,99 @@ impl I2CRegisterMap {
     /// Read data from the device to fill the provided slice
     fn read(&mut self, data: &mut [u8]) -> I2CResult<()> {
         let len = data.len();
+        self.check_range(self.offset, len.max(1))?;
         data.clone_from_slice(&self.registers[self.offset..(self.offset + len)]);
-        println!(
-            "READ  | 0x{:X} : {:?}",
-            isize::try_from(self.offset).unwrap_or(0xBAD)
-                - isize::try_from(data.len()).unwrap_or(0xBAD),
-            data
-        );
+        self.log_access("READ ", self.offset, data);
         Ok(())
     }
 
+    /// Read the registers from the current offset into `data`, up to the
+    /// end of the map, returning the number of bytes read
+    fn read_len(&mut self, data: &mut [u8]) -> I2CResult<usize> {
+        let len = data
+            .len()
+            .min(self.registers.len().saturating_sub(self.offset));
+        self.read(&mut data[..len])?;
+        Ok(len)
+    }
+
+    fn log_access(&self, op: &str, offset: usize, data: &[u8]) {
+        if !self.logging {
+            return;
+        }
+        #[cfg(feature = "log")]
+        log::trace!(target: "i2cdev::mock", "{} | 0x{:X} : {:?}", op, offset, data);
+        #[cfg(not(feature = "log"))]
+        println!("{} | 0x{:X} : {:?}", op, offset, data);
+    }
+
     /// Write the provided buffer to the device
     fn write(&mut self, data: &[u8]) -> I2CResult<()> {
-        // ASSUMPTION: first byte sets the offset
-        // ASSUMPTION: write has length of at least one (will panic)
-        let offset = data[0] as usize;
-        let remdata = &data[1..];
+        // ASSUMPTION: write has length of at least the register address
+        // (will panic)
+        let (offset, remdata) = self.split_write(data);
+        self.check_range(offset, remdata.len().max(1))?;
         self.write_regs(offset, remdata);
         self.offset = offset + remdata.len();
         Ok(())
     }
+
+    /// Fail if any of the `len` registers from `offset` is outside the
+    /// valid range
+    fn check_range(&self, offset: usize, len: usize) -> I2CResult<()> {
+        match self.valid_range {
+            Some((start, end)) if offset < start || offset + len - 1 > end => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "register not acknowledged",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Split a write into the register address and the data written there
+    fn split_write<'a>(&self, data: &'a [u8]) -> (usize, &'a [u8]) {
+        // ASSUMPTION: first byte sets the offset, or the first two for maps
+        // with 16-bit addresses
+        if self.wide_addresses {
+            (usize::from(data[0]) << 8 | usize::from(data[1]), &data[2..])
+        } else if self.auto_increment_bit {
+            ((data[0] & 0x7F) as usize, &data[1..])
+        } else {
+            (data[0] as usize, &data[1..])
+        }
+    }
+}
+
+/// A read or write expected by a scripted [`MockI2CDevice`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockTransaction {
+    /// Expect a write of exactly these bytes
+    Write(Vec<u8>),
+    /// Expect a read of this many bytes and answer it with these bytes
+    Read(Vec<u8>),
+    /// Expect a message of a `transfer` writing exactly these bytes, with
+    /// exactly these flags
+    TransferWrite(Vec<u8>, I2CMessageFlags),
+    /// Expect a message of a `transfer` reading this many bytes, with
+    /// exactly these flags, and answer it with these bytes
+    ///
+    /// The flags of read messages include `READ`.
+    TransferRead(Vec<u8>, I2CMessageFlags),
 }
 
 /// Mock I2C device exposing a register map
+///
+/// Devices which cannot be modelled by a register map can be scripted with
+/// [`expect`](#method.expect) instead.
 #[derive(Default)]
 pub struct MockI2CDevice {
     /// I2C register map
     pub regmap: I2CRegisterMap,
+    expectations: Option<VecDeque<MockTransaction>>,
+    failures: VecDeque<io::Error>,
+    register_failures: Vec<(u8, io::Error)>,
+    pec: bool,
+    address: u8,
 }
 
 impl MockI2CDevice {
@@ -78,46 +219,374 @@ impl MockI2CDevice {
     pub fn new() -> MockI2CDevice {
         MockI2CDevice {
             regmap: I2CRegisterMap::new(),
+            expectations: None,
+            failures: VecDeque::new(),
+            register_failures: Vec::new(),
+            pec: false,
+            address: 0,
         }
     }
+
+    /// Enable or disable SMBus PEC for the block methods
+    ///
+    /// With PEC enabled, block writes store the PEC the host sends in the
+    /// register after the block, and block reads expect the device's PEC
+    /// there and fail with `InvalidData` if it does not match, as the kernel
+    /// does.  The PEC byte is never part of the data returned.  The PEC
+    /// covers the address bytes, see [`with_slave_address`].
+    ///
+    /// [`with_slave_address`]: #method.with_slave_address
+    pub fn set_pec(&mut self, enable: bool) {
+        self.pec = enable;
+    }
+
+    /// Set the 7-bit address the device answers to, which is only used to
+    /// compute the PEC
+    pub fn with_slave_address(mut self, address: u8) -> MockI2CDevice {
+        self.address = address;
+        self
+    }
+
+    /// Script the reads and writes the device expects, in order
+    ///
+    /// Once set, the register map is bypassed: every read and write,
+    /// including those made by the SMBus methods and by `transfer`, must
+    /// match the next expectation or the call panics.  `Read` and `Write`
+    /// match the messages of a `transfer` whatever their flags, while
+    /// `TransferRead` and `TransferWrite` only match those messages, and
+    /// check their flags as well.
+    pub fn expect(&mut self, transactions: Vec<MockTransaction>) {
+        self.expectations = Some(transactions.into_iter().collect());
+    }
+
+    /// Assert that every scripted expectation was consumed
+    pub fn done(&mut self) {
+        if let Some(ref expectations) = self.expectations {
+            assert!(
+                expectations.is_empty(),
+                "mock I2C device has unmet expectations: {:?}",
+                expectations
+            );
+        }
+    }
+
+    /// Fail the next read or write with `error`
+    ///
+    /// The SMBus methods and `transfer` are made of reads and writes, so
+    /// this fails the first of those in the next operation.  Errors are
+    /// queued, so calling this twice fails the next two.  The failing read
+    /// or write does not touch the register map or consume an expectation.
+    ///
+    /// ```rust
+    /// use std::io;
+    /// use i2cdev::core::I2CDevice;
+    /// use i2cdev::mock::MockI2CDevice;
+    ///
+    /// let mut dev = MockI2CDevice::new();
+    /// dev.fail_next(io::Error::new(io::ErrorKind::Other, "NAK"));
+    /// assert!(dev.smbus_read_byte_data(0x10).is_err());
+    /// assert_eq!(dev.smbus_read_byte_data(0x10).unwrap(), 0x00);
+    /// ```
+    pub fn fail_next(&mut self, error: io::Error) {
+        self.failures.push_back(error);
+    }
+
+    /// Fail the next access to `register` with `error`
+    ///
+    /// A write accesses the register its first byte selects, or the first
+    /// two with 16-bit addresses, and a read the register it starts at; so
+    /// an SMBus read of the register fails when selecting it.  As with
+    /// `fail_next`, the error is only returned once, and several can be
+    /// queued for one register.
+    pub fn fail_at_register(&mut self, register: u8, error: io::Error) {
+        self.register_failures.push((register, error));
+    }
+
+    /// Return the error queued for the next read or write accessing
+    /// `register`, if any
+    fn take_failure(&mut self, register: Option<usize>) -> I2CResult<()> {
+        if let Some(e) = self.failures.pop_front() {
+            return Err(e);
+        }
+        let position = self
+            .register_failures
+            .iter()
+            .position(|&(r, _)| Some(usize::from(r)) == register);
+        match position {
+            Some(i) => Err(self.register_failures.remove(i).1),
+            None => Ok(()),
+        }
+    }
+
+    /// The register a write selects, if it is long enough to select one
+    fn write_register(&self, data: &[u8]) -> Option<usize> {
+        let len = if self.regmap.wide_addresses { 2 } else { 1 };
+        if data.len() < len {
+            return None;
+        }
+        Some(self.regmap.split_write(data).0)
+    }
+
+    fn next_expectation(&mut self, actual: &str) -> Option<MockTransaction> {
+        self.expectations.as_mut().map(|expectations| {
+            expectations
+                .pop_front()
+                .unwrap_or_else(|| panic!("unexpected {} on mock I2C device", actual))
+        })
+    }
+
+    /// Log register accesses, which helps when debugging a driver
+    ///
+    /// See [`I2CRegisterMap::set_logging`] for where they go.
+    pub fn with_logging(mut self, logging: bool) -> MockI2CDevice {
+        self.regmap.set_logging(logging);
+        self
+    }
+
+    /// Model a device with 16-bit register addresses and 65536 registers,
+    /// see [`I2CRegisterMap::set_16bit_addresses`]
+    ///
+    /// ```rust
+    /// use i2cdev::core::I2CDevice;
+    /// use i2cdev::mock::MockI2CDevice;
+    ///
+    /// let mut eeprom = MockI2CDevice::new().with_16bit_addresses();
+    /// eeprom.write(&[0x7F, 0xFF, 0xAA]).unwrap();
+    /// let mut data = [0];
+    /// eeprom.write(&[0x7F, 0xFF]).unwrap();
+    /// eeprom.read(&mut data).unwrap();
+    /// assert_eq!(data, [0xAA]);
+    /// ```
+    pub fn with_16bit_addresses(mut self) -> MockI2CDevice {
+        if self.regmap.registers.len() < 0x10000 {
+            self.regmap.registers.resize(0x10000, 0x00);
+        }
+        self.regmap.set_16bit_addresses(true);
+        self
+    }
+
+    /// The contents of all registers, see [`I2CRegisterMap::registers`]
+    pub fn registers(&self) -> &[u8] {
+        self.regmap.registers()
+    }
+
+    /// The contents of the register `reg`, without going through the
+    /// device
+    ///
+    /// ```rust
+    /// use i2cdev::core::I2CDevice;
+    /// use i2cdev::mock::MockI2CDevice;
+    ///
+    /// let mut dev = MockI2CDevice::new();
+    /// dev.smbus_write_byte_data(0x2D, 0x08).unwrap();
+    /// assert_eq!(dev.register(0x2D), 0x08);
+    /// ```
+    pub fn register(&self, reg: u8) -> u8 {
+        self.regmap.register(reg)
+    }
+
+    /// Only acknowledge the registers `start` to `end`, see
+    /// [`I2CRegisterMap::set_valid_range`]
+    ///
+    /// This lets a driver reading registers until the device stops
+    /// acknowledging be tested.
+    ///
+    /// ```rust
+    /// use std::io;
+    /// use i2cdev::core::I2CDevice;
+    /// use i2cdev::mock::MockI2CDevice;
+    ///
+    /// let mut dev = MockI2CDevice::new().with_valid_range(0x00, 0x0F);
+    /// assert!(dev.smbus_read_byte_data(0x0F).is_ok());
+    /// let e = dev.smbus_read_byte_data(0x10).unwrap_err();
+    /// assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+    /// ```
+    pub fn with_valid_range(mut self, start: usize, end: usize) -> MockI2CDevice {
+        self.regmap.set_valid_range(start, end);
+        self
+    }
+
+    /// Treat the top bit of register addresses as an auto-increment flag,
+    /// see [`I2CRegisterMap::set_auto_increment_bit`]
+    pub fn with_auto_increment_bit(mut self, enable: bool) -> MockI2CDevice {
+        self.regmap.set_auto_increment_bit(enable);
+        self
+    }
 }
 
 impl I2CDevice for MockI2CDevice {
     type Error = io::Error;
 
     fn read(&mut self, data: &mut [u8]) -> I2CResult<()> {
-        self.regmap.read(data)
+        self.take_failure(Some(self.regmap.offset))?;
+        let actual = format!("read of {} bytes", data.len());
+        match self.next_expectation(&actual) {
+            None => self.regmap.read(data),
+            Some(MockTransaction::Read(ref response)) if response.len() == data.len() => {
+                data.copy_from_slice(response);
+                Ok(())
+            }
+            Some(expected) => panic!("expected {:?} but got {}", expected, actual),
+        }
+    }
+
+    /// Read up to `data.len()` bytes, stopping at the end of the register
+    /// map
+    ///
+    /// A scripted `Read` may also answer with fewer bytes than were asked
+    /// for.
+    fn read_len(&mut self, data: &mut [u8]) -> I2CResult<usize> {
+        self.take_failure(Some(self.regmap.offset))?;
+        let actual = format!("read of up to {} bytes", data.len());
+        match self.next_expectation(&actual) {
+            None => self.regmap.read_len(data),
+            Some(MockTransaction::Read(ref response)) if response.len() <= data.len() => {
+                data[..response.len()].copy_from_slice(response);
+                Ok(response.len())
+            }
+            Some(expected) => panic!("expected {:?} but got {}", expected, actual),
+        }
     }
 
     fn write(&mut self, data: &[u8]) -> I2CResult<()> {
-        self.regmap.write(data)
+        self.take_failure(self.write_register(data))?;
+        let actual = format!("write of {:?}", data);
+        match self.next_expectation(&actual) {
+            None => self.regmap.write(data),
+            Some(MockTransaction::Write(ref expected)) if expected[..] == *data => Ok(()),
+            Some(expected) => panic!("expected {:?} but got {}", expected, actual),
+        }
+    }
+
+    /// Acknowledge the quick command, which carries no data and leaves the
+    /// register map as it is
+    ///
+    /// Errors queued with `fail_next` are returned, so a device not
+    /// acknowledging a probe can be modelled.
+    fn smbus_write_quick(&mut self, bit: bool) -> I2CResult<()> {
+        self.take_failure(None)?;
+        self.regmap
+            .log_access("QUICK", self.regmap.offset, &[u8::from(bit)]);
+        Ok(())
+    }
+
+    /// Read the count byte stored at the register, then that many bytes
+    /// from the registers which follow it
+    fn smbus_read_block_data_into(
+        &mut self,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> I2CResult<usize> {
+        let data = self.smbus_read_block_data(register)?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    /// Store the count byte at the register, followed by the values
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> I2CResult<()> {
+        let pec = self.pec;
+        self.write_block(register, values, pec)
     }
 
-    fn smbus_write_quick(&mut self, _bit: bool) -> I2CResult<()> {
-        unimplemented!()
+    /// Store the values starting at the register, without a count byte
+    fn smbus_write_i2c_block_data(&mut self, register: u8, values: &[u8]) -> I2CResult<()> {
+        check_block_len(values.len())?;
+        let mut buf = vec![register];
+        buf.extend_from_slice(values);
+        self.write(&buf)
     }
+}
 
-    fn smbus_read_block_data(&mut self, _register: u8) -> I2CResult<Vec<u8>> {
-        unimplemented!()
+impl I2CDeviceExt for MockI2CDevice {
+    /// Read the count byte stored at the register, then that many bytes
+    /// from the registers which follow it
+    fn smbus_read_block_data(&mut self, register: u8) -> I2CResult<Vec<u8>> {
+        self.write(&[register])?;
+        let (write, read) = self.address_bytes();
+        self.read_block(&[write, register, read])
     }
 
-    fn smbus_write_block_data(&mut self, _register: u8, _values: &[u8]) -> I2CResult<()> {
-        unimplemented!()
+    /// Read `len` bytes starting at the register
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> I2CResult<Vec<u8>> {
+        check_block_len(len as usize)?;
+        let mut data = vec![0; len as usize];
+        self.write(&[register])?;
+        self.read(&mut data)?;
+        Ok(data)
     }
 
-    fn smbus_process_block(&mut self, _register: u8, _values: &[u8]) -> I2CResult<Vec<u8>> {
-        unimplemented!()
+    /// Write a block with its count byte to the register, then read back a
+    /// block of data from the registers which follow it.
+    ///
+    /// The response starts with its own count byte, as on the wire.
+    fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> I2CResult<Vec<u8>> {
+        // only the device sends a PEC, at the very end
+        self.write_block(register, values, false)?;
+        let (write, read) = self.address_bytes();
+        let mut transaction = vec![write, register, values.len() as u8];
+        transaction.extend_from_slice(values);
+        transaction.push(read);
+        self.read_block(&transaction)
     }
+}
 
-    fn smbus_read_i2c_block_data(&mut self, _register: u8, _len: u8) -> I2CResult<Vec<u8>> {
-        unimplemented!()
+impl MockI2CDevice {
+    /// The address byte of a write and of a read
+    fn address_bytes(&self) -> (u8, u8) {
+        (self.address << 1, self.address << 1 | 1)
     }
 
-    fn smbus_write_i2c_block_data(&mut self, _register: u8, _values: &[u8]) -> I2CResult<()> {
-        unimplemented!()
+    /// Write the count byte and the values to the register, followed by the
+    /// PEC of the transaction if `pec` is set
+    fn write_block(&mut self, register: u8, values: &[u8], pec: bool) -> I2CResult<()> {
+        check_block_len(values.len())?;
+        let mut buf = vec![register, values.len() as u8];
+        buf.extend_from_slice(values);
+        if pec {
+            let mut transaction = vec![self.address_bytes().0];
+            transaction.extend_from_slice(&buf);
+            buf.push(crc8(&transaction));
+        }
+        self.write(&buf)
+    }
+
+    /// Read a count byte at the current offset followed by that many bytes,
+    /// and a PEC byte if enabled which is checked against the bytes of the
+    /// transaction so far, given in `transaction`
+    fn read_block(&mut self, transaction: &[u8]) -> I2CResult<Vec<u8>> {
+        let mut count = [0];
+        self.read(&mut count)?;
+        check_block_len(count[0] as usize)?;
+        let pec_len = if self.pec { 1 } else { 0 };
+        let mut data = vec![0; count[0] as usize + 1 + pec_len];
+        self.read(&mut data)?;
+        if self.pec {
+            let pec = data.pop();
+            let mut transaction = transaction.to_vec();
+            transaction.extend_from_slice(&data);
+            if pec != Some(crc8(&transaction)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "SMBus PEC mismatch",
+                ));
+            }
+        }
+        data.remove(0);
+        Ok(data)
     }
 }
 
+/// SMBus blocks carry at most 32 bytes
+fn check_block_len(len: usize) -> I2CResult<()> {
+    if len > SMBUS_BLOCK_MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SMBus blocks are limited to 32 bytes",
+        ));
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 enum MessageType<'a> {
     Write(&'a [u8]),
@@ -125,14 +594,52 @@ enum MessageType<'a> {
 }
 
 /// Mock I2C message
+///
+/// Like [`LinuxI2CMessage`](../linux/type.LinuxI2CMessage.html), the message
+/// carries flags and an optional address, which scripted expectations can
+/// check.
 pub struct MockI2CMessage<'a> {
     msg_type: MessageType<'a>,
+    address: Option<Address>,
+    flags: I2CMessageFlags,
+}
+
+impl<'a> MockI2CMessage<'a> {
+    /// Set the target device address for the message
+    ///
+    /// As for Linux messages, a 10-bit address sets the `TEN_BIT_ADDRESS`
+    /// flag and a 7-bit address clears it.
+    pub fn with_address<A: Into<Address>>(mut self, slave_address: A) -> Self {
+        let slave_address = slave_address.into();
+        self.flags
+            .set(I2CMessageFlags::TEN_BIT_ADDRESS, slave_address.is_ten_bit());
+        self.address = Some(slave_address);
+        self
+    }
+
+    /// Set optional message flags, replacing all flags
+    pub fn with_flags(mut self, flags: I2CMessageFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// The address set with `with_address`, if any
+    pub fn address(&self) -> Option<Address> {
+        self.address
+    }
+
+    /// The flags of the message
+    pub fn flags(&self) -> I2CMessageFlags {
+        self.flags
+    }
 }
 
 impl<'a> I2CMessage<'a> for MockI2CMessage<'a> {
     fn read(data: &'a mut [u8]) -> Self {
         Self {
             msg_type: MessageType::Read(data),
+            address: None,
+            flags: I2CMessageFlags::READ,
         }
     }
 
@@ -140,6 +647,8 @@ impl<'a> I2CMessage<'a> for MockI2CMessage<'a> {
     fn write(data: &'a [u8]) -> Self {
         Self {
             msg_type: MessageType::Write(data),
+            address: None,
+            flags: I2CMessageFlags::empty(),
         }
     }
 }
@@ -154,15 +663,63 @@ where
     /// Issue the provided sequence of I2C transactions
     fn transfer(&mut self, messages: &mut [Self::Message]) -> Result<u32, Self::Error> {
         for msg in messages.iter_mut() {
-            match &mut msg.msg_type {
-                MessageType::Read(data) => self.read(data)?,
-                MessageType::Write(data) => self.write(data)?,
-            }
+            self.transfer_message(msg)?;
         }
         Ok(messages.len() as u32)
     }
 }
 
+impl MockI2CDevice {
+    /// Write `write` and then read into `read` in one transaction, like
+    /// `LinuxI2CDevice::write_read`
+    pub fn write_read(&mut self, write: &[u8], read: &mut [u8]) -> I2CResult<()> {
+        let mut msgs = [MockI2CMessage::write(write), MockI2CMessage::read(read)];
+        self.transfer(&mut msgs)?;
+        Ok(())
+    }
+
+    /// Execute a message of a transfer, checking its flags if the next
+    /// expectation is one of the `Transfer` ones
+    fn transfer_message(&mut self, msg: &mut MockI2CMessage) -> I2CResult<()> {
+        let checks_flags = matches!(
+            self.expectations.as_ref().and_then(|e| e.front()),
+            Some(MockTransaction::TransferWrite(..)) | Some(MockTransaction::TransferRead(..))
+        );
+        if !checks_flags {
+            return match msg.msg_type {
+                MessageType::Read(ref mut data) => self.read(data),
+                MessageType::Write(data) => self.write(data),
+            };
+        }
+
+        let flags = msg.flags;
+        match msg.msg_type {
+            MessageType::Read(ref mut data) => {
+                self.take_failure(Some(self.regmap.offset))?;
+                let actual = format!("read of {} bytes with {:?}", data.len(), flags);
+                match self.next_expectation(&actual) {
+                    Some(MockTransaction::TransferRead(ref response, expected_flags))
+                        if response.len() == data.len() && expected_flags == flags =>
+                    {
+                        data.copy_from_slice(response);
+                    }
+                    expected => panic!("expected {:?} but got {}", expected, actual),
+                }
+            }
+            MessageType::Write(data) => {
+                self.take_failure(self.write_register(data))?;
+                let actual = format!("write of {:?} with {:?}", data, flags);
+                match self.next_expectation(&actual) {
+                    Some(MockTransaction::TransferWrite(ref expected, expected_flags))
+                        if expected[..] == *data && expected_flags == flags => {}
+                    expected => panic!("expected {:?} but got {}", expected, actual),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -171,6 +728,441 @@ mod test {
     fn test_can_read_at_zero_offset() {
         let mut mock_device = MockI2CDevice::new();
         mock_device.regmap.write_regs(0x0, &[0x1u8; 4]);
-        mock_device.read(&mut [0x0u8; 4]).unwrap();
+        let mut buf = [0x0u8; 4];
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x1u8; 4]);
+    }
+
+    #[test]
+    fn test_read_len() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device
+            .regmap
+            .write_regs(0x10, &[0x01, 0x02, 0x03, 0x04]);
+        mock_device.write(&[0x10]).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(mock_device.read_len(&mut buf).unwrap(), 4);
+        assert_eq!(buf, [0x01, 0x02, 0x03, 0x04]);
+
+        // the map ends after two bytes
+        mock_device.regmap.write_regs(0xFE, &[0xAA, 0xBB]);
+        mock_device.write(&[0xFE]).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(mock_device.read_len(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [0xAA, 0xBB, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_last_register() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.smbus_write_byte_data(0xFF, 0xAB).unwrap();
+        assert_eq!(mock_device.smbus_read_byte_data(0xFF).unwrap(), 0xAB);
+        assert_eq!(mock_device.smbus_read_byte_data(0xFE).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn test_register_map_capacity() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.regmap = I2CRegisterMap::with_capacity(0x200);
+        mock_device.regmap.write_regs(0x100, &[0x12]);
+        mock_device.write(&[0xFF]).unwrap();
+        let mut buf = [0; 2];
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x00, 0x12]);
+    }
+
+    #[test]
+    fn test_16bit_register_addresses() {
+        let mut mock_device = MockI2CDevice::new().with_16bit_addresses();
+        mock_device.write(&[0x1F, 0xFE, 0x12, 0x34]).unwrap();
+        let mut buf = [0; 2];
+        mock_device.write(&[0x1F, 0xFE]).unwrap();
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x12, 0x34]);
+        // the low byte alone addresses the first page
+        mock_device.write(&[0x00, 0xFE]).unwrap();
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_logging_read_at_zero_offset() {
+        let mut mock_device = MockI2CDevice::new().with_logging(true);
+        let mut buf = [0xFFu8; 8];
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x0u8; 8]);
+    }
+
+    #[test]
+    fn test_auto_increment_block_read() {
+        // gyro output registers as on the L3G family, X/Y/Z little endian
+        const OUT_X_L: u8 = 0x28;
+        let mut mock_device = MockI2CDevice::new().with_auto_increment_bit(true);
+        mock_device
+            .regmap
+            .write_regs(OUT_X_L as usize, &[0x34, 0x12, 0xFE, 0xFF, 0x00, 0x80]);
+
+        let buf = mock_device
+            .smbus_read_i2c_block_data(0x80 | OUT_X_L, 6)
+            .unwrap();
+        let x = i16::from_le_bytes([buf[0], buf[1]]);
+        let y = i16::from_le_bytes([buf[2], buf[3]]);
+        let z = i16::from_le_bytes([buf[4], buf[5]]);
+        assert_eq!((x, y, z), (0x1234, -2, i16::MIN));
+        assert_eq!(i16::from_be_bytes([buf[1], buf[0]]), x);
+
+        mock_device
+            .smbus_write_byte_data(0x80 | OUT_X_L, 0x56)
+            .unwrap();
+        assert_eq!(mock_device.smbus_read_byte_data(OUT_X_L).unwrap(), 0x56);
+    }
+
+    #[test]
+    fn test_scripted_transactions() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![
+            MockTransaction::Write(vec![0x01, 0x02]),
+            MockTransaction::Read(vec![0xAA, 0xBB]),
+            MockTransaction::Write(vec![0x10]),
+            MockTransaction::Read(vec![0x34, 0x12]),
+        ]);
+        mock_device.write(&[0x01, 0x02]).unwrap();
+        let mut buf = [0; 2];
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0xAA, 0xBB]);
+        assert_eq!(mock_device.smbus_read_word_data(0x10).unwrap(), 0x1234);
+        mock_device.done();
+    }
+
+    #[test]
+    fn test_scripted_transfer() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![
+            MockTransaction::Write(vec![0x20]),
+            MockTransaction::Read(vec![0x55]),
+        ]);
+        let mut buf = [0];
+        let mut msgs = [
+            MockI2CMessage::write(&[0x20]),
+            MockI2CMessage::read(&mut buf),
+        ];
+        mock_device.transfer(&mut msgs).unwrap();
+        assert_eq!(buf, [0x55]);
+        mock_device.done();
+    }
+
+    #[test]
+    fn test_scripted_transfer_flags() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![
+            MockTransaction::TransferWrite(vec![0x20], I2CMessageFlags::TEN_BIT_ADDRESS),
+            MockTransaction::TransferRead(
+                vec![0x55, 0x66],
+                I2CMessageFlags::READ | I2CMessageFlags::NO_START,
+            ),
+            // plain expectations accept any flags
+            MockTransaction::Write(vec![0x21]),
+        ]);
+        let mut buf = [0; 2];
+        let mut msgs = [
+            MockI2CMessage::write(&[0x20]).with_address(Address::TenBit(0x150)),
+            MockI2CMessage::read(&mut buf)
+                .with_flags(I2CMessageFlags::READ | I2CMessageFlags::NO_START),
+            MockI2CMessage::write(&[0x21]).with_flags(I2CMessageFlags::STOP),
+        ];
+        assert_eq!(msgs[0].address(), Some(Address::TenBit(0x150)));
+        assert_eq!(msgs[2].flags(), I2CMessageFlags::STOP);
+        mock_device.transfer(&mut msgs).unwrap();
+        assert_eq!(buf, [0x55, 0x66]);
+        mock_device.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "but got read of 1 bytes with I2CMessageFlags(READ)")]
+    fn test_scripted_transfer_flags_mismatch() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![MockTransaction::TransferRead(
+            vec![0x55],
+            I2CMessageFlags::READ | I2CMessageFlags::NO_START,
+        )]);
+        let mut buf = [0];
+        let mut msgs = [MockI2CMessage::read(&mut buf)];
+        let _ = mock_device.transfer(&mut msgs);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![
+            MockTransaction::Write(vec![0x0F]),
+            MockTransaction::Read(vec![0xD4]),
+        ]);
+        let mut buf = [0];
+        mock_device.write_read(&[0x0F], &mut buf).unwrap();
+        assert_eq!(buf, [0xD4]);
+        mock_device.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected Write([1]) but got write of [2]")]
+    fn test_scripted_mismatch() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![MockTransaction::Write(vec![1])]);
+        mock_device.write(&[2]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "unmet expectations")]
+    fn test_scripted_unmet() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![MockTransaction::Read(vec![1])]);
+        mock_device.done();
+    }
+
+    #[test]
+    fn test_smbus_process_block() {
+        let mut mock_device = MockI2CDevice::new();
+        // the response follows the count byte and the three request bytes
+        mock_device.regmap.write_regs(0x14, &[2, 0xAB, 0xCD]);
+        let response = mock_device.smbus_process_block(0x10, &[1, 2, 3]).unwrap();
+        assert_eq!(response, vec![0xAB, 0xCD]);
+
+        let mut request = [0; 4];
+        mock_device.write(&[0x10]).unwrap();
+        mock_device.read(&mut request).unwrap();
+        assert_eq!(request, [3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_smbus_block_round_trip() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device
+            .smbus_write_block_data(0x20, &[0xDE, 0xAD, 0xBE, 0xEF])
+            .unwrap();
+        assert_eq!(
+            mock_device.smbus_read_block_data(0x20).unwrap(),
+            vec![0xDE, 0xAD, 0xBE, 0xEF]
+        );
+        assert_eq!(mock_device.smbus_read_byte_data(0x20).unwrap(), 4);
+
+        mock_device.smbus_write_block_data(0x30, &[]).unwrap();
+        assert!(mock_device.smbus_read_block_data(0x30).unwrap().is_empty());
+
+        assert!(mock_device.smbus_write_block_data(0x40, &[0; 33]).is_err());
+
+        let mut buf = [0; 32];
+        assert_eq!(
+            mock_device
+                .smbus_read_block_data_into(0x20, &mut buf)
+                .unwrap(),
+            4
+        );
+        assert_eq!(buf[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(
+            mock_device
+                .smbus_read_block_data_into(0x30, &mut buf)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_smbus_block_into_slice() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device
+            .smbus_write_block_data(0x20, &[0xDE, 0xAD, 0xBE])
+            .unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(
+            mock_device
+                .smbus_read_block_data_into_slice(0x20, &mut buf)
+                .unwrap(),
+            3
+        );
+        assert_eq!(buf, [0xDE, 0xAD, 0xBE, 0x00]);
+
+        let mut buf = [0; 2];
+        let e = mock_device
+            .smbus_read_block_data_into_slice(0x20, &mut buf)
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_smbus_block_pec() {
+        let mut mock_device = MockI2CDevice::new().with_slave_address(0x0B);
+        mock_device.set_pec(true);
+        mock_device
+            .smbus_write_block_data(0x20, &[1, 2, 3])
+            .unwrap();
+        let pec = crc8(&[0x16, 0x20, 3, 1, 2, 3]);
+        assert_eq!(mock_device.smbus_read_byte_data(0x24).unwrap(), pec);
+
+        // the device answers a read with the PEC of the read transaction
+        let read_pec = crc8(&[0x16, 0x20, 0x17, 3, 1, 2, 3]);
+        mock_device.regmap.write_regs(0x24, &[read_pec]);
+        assert_eq!(
+            mock_device.smbus_read_block_data(0x20).unwrap(),
+            vec![1, 2, 3]
+        );
+
+        mock_device.regmap.write_regs(0x24, &[read_pec ^ 0xFF]);
+        let err = mock_device.smbus_read_block_data(0x20).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        mock_device.set_pec(false);
+        assert_eq!(
+            mock_device.smbus_read_block_data(0x20).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_smbus_i2c_block_round_trip() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device
+            .smbus_write_i2c_block_data(0x20, &[1, 2, 3, 4, 5])
+            .unwrap();
+        assert_eq!(
+            mock_device.smbus_read_i2c_block_data(0x20, 5).unwrap(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            mock_device.smbus_read_i2c_block_data(0x21, 2).unwrap(),
+            vec![2, 3]
+        );
+        assert!(mock_device
+            .smbus_read_i2c_block_data(0x20, 0)
+            .unwrap()
+            .is_empty());
+        assert!(mock_device.smbus_read_i2c_block_data(0x20, 33).is_err());
+    }
+
+    #[test]
+    fn test_word_data_byte_order() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.regmap.write_regs(0x10, &[0x12, 0x34]);
+        assert_eq!(mock_device.smbus_read_word_data(0x10).unwrap(), 0x3412);
+        assert_eq!(mock_device.smbus_read_word_data_le(0x10).unwrap(), 0x3412);
+        assert_eq!(mock_device.smbus_read_word_data_be(0x10).unwrap(), 0x1234);
+
+        mock_device.smbus_write_word_data_be(0x20, 0xABCD).unwrap();
+        mock_device.smbus_write_word_data_le(0x22, 0xABCD).unwrap();
+        let mut buf = [0; 4];
+        mock_device.write(&[0x20]).unwrap();
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0xAB, 0xCD, 0xCD, 0xAB]);
+    }
+
+    #[test]
+    fn test_fail_next() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.fail_next(io::Error::from(io::ErrorKind::TimedOut));
+        mock_device.fail_next(io::Error::from(io::ErrorKind::WouldBlock));
+        let e = mock_device.smbus_write_byte_data(0x10, 0xAB).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        let mut buf = [0];
+        let e = mock_device.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        mock_device.smbus_write_byte_data(0x10, 0xAB).unwrap();
+        assert_eq!(mock_device.smbus_read_byte_data(0x10).unwrap(), 0xAB);
+    }
+
+    #[test]
+    fn test_smbus_quick() {
+        use crate::core::QuickCommand;
+
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.regmap.write_regs(0x00, &[0x12]);
+        mock_device.smbus_quick(QuickCommand::Write).unwrap();
+        mock_device.smbus_quick(QuickCommand::Read).unwrap();
+        assert_eq!(mock_device.regmap.offset, 0);
+        assert_eq!(mock_device.register(0x00), 0x12);
+
+        mock_device.fail_next(io::Error::from(io::ErrorKind::NotConnected));
+        let e = mock_device.smbus_quick(QuickCommand::Read).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+        mock_device.smbus_quick(QuickCommand::Write).unwrap();
+    }
+
+    #[test]
+    fn test_fail_at_register() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.regmap.write_regs(0x10, &[0x12, 0x34]);
+        mock_device.fail_at_register(0x11, io::Error::from(io::ErrorKind::TimedOut));
+        assert_eq!(mock_device.smbus_read_byte_data(0x10).unwrap(), 0x12);
+        let e = mock_device.smbus_read_byte_data(0x11).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(mock_device.smbus_read_byte_data(0x11).unwrap(), 0x34);
+
+        // reads fail at the register they start at
+        mock_device.write(&[0x11]).unwrap();
+        mock_device.fail_at_register(0x11, io::Error::from(io::ErrorKind::TimedOut));
+        let mut buf = [0];
+        assert!(mock_device.read(&mut buf).is_err());
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x34]);
+    }
+
+    #[test]
+    fn test_fail_transfer_message() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.expect(vec![MockTransaction::TransferWrite(
+            vec![0x10],
+            I2CMessageFlags::empty(),
+        )]);
+        mock_device.fail_at_register(0x10, io::Error::from(io::ErrorKind::TimedOut));
+        let mut msgs = [MockI2CMessage::write(&[0x10])];
+        assert!(mock_device.transfer(&mut msgs).is_err());
+        mock_device.transfer(&mut msgs).unwrap();
+        mock_device.done();
+    }
+
+    #[test]
+    fn test_valid_range() {
+        let mut mock_device = MockI2CDevice::new().with_valid_range(0x10, 0x13);
+        mock_device.regmap.write_regs(0x10, &[1, 2, 3, 4, 5]);
+
+        // probing until the device stops acknowledging
+        let mut read = Vec::new();
+        let mut register = 0x10;
+        while let Ok(value) = mock_device.smbus_read_byte_data(register) {
+            read.push(value);
+            register += 1;
+        }
+        assert_eq!(read, [1, 2, 3, 4]);
+
+        // a read running past the end fails as a whole
+        mock_device.write(&[0x12]).unwrap();
+        let mut buf = [0; 3];
+        let e = mock_device.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+
+        // writes outside the range leave the map untouched
+        assert!(mock_device.write(&[0x0F]).is_err());
+        assert!(mock_device.smbus_write_byte_data(0x0F, 0xAA).is_err());
+        assert!(mock_device.write(&[0x13, 0xAA, 0xBB]).is_err());
+        mock_device.smbus_write_byte_data(0x13, 0xAA).unwrap();
+        assert_eq!(mock_device.smbus_read_byte_data(0x13).unwrap(), 0xAA);
+        assert_eq!(mock_device.register(0x14), 5);
+    }
+
+    #[test]
+    fn test_register_snapshot() {
+        let mut mock_device = MockI2CDevice::new();
+        mock_device.write(&[0x20, 0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(mock_device.register(0x21), 0x02);
+        assert_eq!(mock_device.registers().len(), 0x100);
+        assert_eq!(
+            mock_device.registers()[0x20..0x24],
+            [0x01, 0x02, 0x03, 0x00]
+        );
+        // the snapshot does not move the offset of the next read
+        let mut buf = [0];
+        mock_device.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x00]);
+
+        let eeprom = MockI2CDevice::new().with_16bit_addresses();
+        assert_eq!(eeprom.registers().len(), 0x10000);
     }
 }
@@ -0,0 +1,122 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::core::{I2CMessage, I2CTransfer};
+use crate::linux::{LinuxI2CBus, LinuxI2CError, LinuxI2CMessage};
+
+/// Default address of a TCA9548A with its address pins tied low
+pub const TCA9548A_DEFAULT_ADDR: u16 = 0x70;
+
+/// Number of downstream channels of a TCA9548A
+pub const TCA9548A_CHANNELS: u8 = 8;
+
+/// A TCA9548A (or compatible) eight channel I2C multiplexer on a bus
+///
+/// The multiplexer has a single control register holding one enable bit per
+/// downstream channel.  After `select_channel`, transfers on the bus reach
+/// the devices behind that channel as well as those on the upstream bus, so
+/// downstream devices are addressed through [`bus`](#method.bus) as usual.
+///
+/// ```rust,no_run
+/// use i2cdev::core::*;
+/// use i2cdev::linux::{LinuxI2CBus, LinuxI2CError, LinuxI2CMessage};
+/// use i2cdev::mux::{Tca9548a, TCA9548A_DEFAULT_ADDR};
+///
+/// # fn main() -> Result<(), LinuxI2CError> {
+/// let bus = LinuxI2CBus::new("/dev/i2c-1")?;
+/// let mut mux = Tca9548a::new(bus, TCA9548A_DEFAULT_ADDR);
+/// mux.select_channel(3)?;
+///
+/// let mut data = [0; 2];
+/// let mut msgs = [
+///     LinuxI2CMessage::write(&[0x00]).with_address(0x48),
+///     LinuxI2CMessage::read(&mut data).with_address(0x48),
+/// ];
+/// mux.bus().transfer(&mut msgs)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Tca9548a {
+    bus: LinuxI2CBus,
+    address: u16,
+}
+
+impl Tca9548a {
+    /// Wrap a bus with a multiplexer at the specified 7-bit address
+    ///
+    /// Nothing is written to the multiplexer, so whichever channels were
+    /// enabled before stay enabled.
+    pub fn new(bus: LinuxI2CBus, address: u16) -> Tca9548a {
+        Tca9548a { bus, address }
+    }
+
+    /// The address of the multiplexer
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Enable `channel`, in the range 0 to 7, and disable all others
+    ///
+    /// Channels out of range are rejected with `EINVAL` without touching the
+    /// bus.
+    pub fn select_channel(&mut self, channel: u8) -> Result<(), LinuxI2CError> {
+        if channel >= TCA9548A_CHANNELS {
+            return Err(LinuxI2CError::Errno(libc::EINVAL));
+        }
+        self.write_control(1 << channel)
+    }
+
+    /// Disable all downstream channels
+    pub fn disable_all(&mut self) -> Result<(), LinuxI2CError> {
+        self.write_control(0)
+    }
+
+    /// The bus, for transfers to the devices behind the selected channel
+    pub fn bus(&mut self) -> &mut LinuxI2CBus {
+        &mut self.bus
+    }
+
+    /// Unwrap the bus, leaving the multiplexer channels as they are
+    pub fn into_bus(self) -> LinuxI2CBus {
+        self.bus
+    }
+
+    fn write_control(&mut self, value: u8) -> Result<(), LinuxI2CError> {
+        let data = [value];
+        let mut msgs = [LinuxI2CMessage::write(&data).with_address(self.address)];
+        self.bus.transfer(&mut msgs)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn null_mux() -> Tca9548a {
+        Tca9548a::new(
+            LinuxI2CBus::new("/dev/null").unwrap(),
+            TCA9548A_DEFAULT_ADDR,
+        )
+    }
+
+    #[test]
+    fn test_select_channel_range() {
+        let mut mux = null_mux();
+        match mux.select_channel(8) {
+            Err(LinuxI2CError::Errno(libc::EINVAL)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // in range, so the write reaches /dev/null, which has no ioctls
+        match mux.select_channel(7) {
+            Err(LinuxI2CError::Errno(libc::ENOTTY)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(mux.disable_all().is_err());
+    }
+}
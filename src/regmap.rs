@@ -0,0 +1,542 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::core::I2CDevice;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Not};
+
+/// A value stored in a device register, read and written with the SMBus
+/// method matching its width
+///
+/// Bytes use the byte data methods and words the word data methods, so
+/// words are least significant byte first as SMBus defines.
+pub trait RegisterValue: Sized {
+    /// Read the register
+    fn read_register<D: I2CDevice + ?Sized>(dev: &mut D, register: u8) -> Result<Self, D::Error>;
+
+    /// Write the register
+    fn write_register<D: I2CDevice + ?Sized>(
+        dev: &mut D,
+        register: u8,
+        value: Self,
+    ) -> Result<(), D::Error>;
+}
+
+impl RegisterValue for u8 {
+    fn read_register<D: I2CDevice + ?Sized>(dev: &mut D, register: u8) -> Result<u8, D::Error> {
+        dev.smbus_read_byte_data(register)
+    }
+
+    fn write_register<D: I2CDevice + ?Sized>(
+        dev: &mut D,
+        register: u8,
+        value: u8,
+    ) -> Result<(), D::Error> {
+        dev.smbus_write_byte_data(register, value)
+    }
+}
+
+impl RegisterValue for u16 {
+    fn read_register<D: I2CDevice + ?Sized>(dev: &mut D, register: u8) -> Result<u16, D::Error> {
+        dev.smbus_read_word_data(register)
+    }
+
+    fn write_register<D: I2CDevice + ?Sized>(
+        dev: &mut D,
+        register: u8,
+        value: u16,
+    ) -> Result<(), D::Error> {
+        dev.smbus_write_word_data(register, value)
+    }
+}
+
+/// A device register holding a value of type `T`, `u8` or `u16`
+///
+/// Besides reading and writing the whole register, bits are changed with a
+/// read-modify-write, leaving the other bits of the register as they were.
+///
+#[cfg_attr(feature = "std", doc = "```rust")]
+#[cfg_attr(not(feature = "std"), doc = "```ignore")]
+/// use i2cdev::mock::MockI2CDevice;
+/// use i2cdev::regmap::Register;
+///
+/// const CTRL_REG1: Register<u8> = Register::new(0x20);
+///
+/// let mut dev = MockI2CDevice::new();
+/// CTRL_REG1.write(&mut dev, 0b0000_0111).unwrap();
+/// CTRL_REG1.set_bits(&mut dev, 0b0000_1000).unwrap();
+/// CTRL_REG1.clear_bits(&mut dev, 0b0000_0001).unwrap();
+/// assert_eq!(CTRL_REG1.read(&mut dev).unwrap(), 0b0000_1110);
+/// ```
+///
+/// Note that the read and the write are separate transactions, so another
+/// process accessing the device in between can have its change lost.
+pub struct Register<T> {
+    address: u8,
+    value: PhantomData<T>,
+}
+
+impl<T> Register<T> {
+    /// A register at the specified address
+    pub const fn new(address: u8) -> Register<T> {
+        Register {
+            address,
+            value: PhantomData,
+        }
+    }
+
+    /// The address of the register
+    pub const fn address(&self) -> u8 {
+        self.address
+    }
+}
+
+impl<T: RegisterValue> Register<T> {
+    /// Read the register
+    pub fn read<D: I2CDevice + ?Sized>(&self, dev: &mut D) -> Result<T, D::Error> {
+        T::read_register(dev, self.address)
+    }
+
+    /// Write the register
+    pub fn write<D: I2CDevice + ?Sized>(&self, dev: &mut D, value: T) -> Result<(), D::Error> {
+        T::write_register(dev, self.address, value)
+    }
+
+    /// Read the register and write back the value returned by `f`
+    ///
+    /// Nothing is written if the read fails.
+    pub fn modify<D, F>(&self, dev: &mut D, f: F) -> Result<(), D::Error>
+    where
+        D: I2CDevice + ?Sized,
+        F: FnOnce(T) -> T,
+    {
+        let value = self.read(dev)?;
+        self.write(dev, f(value))
+    }
+}
+
+impl<T> Register<T>
+where
+    T: RegisterValue + BitOr<Output = T> + BitAnd<Output = T> + Not<Output = T>,
+{
+    /// Set the bits of `mask` in the register
+    pub fn set_bits<D: I2CDevice + ?Sized>(&self, dev: &mut D, mask: T) -> Result<(), D::Error> {
+        self.modify(dev, |value| value | mask)
+    }
+
+    /// Clear the bits of `mask` in the register
+    pub fn clear_bits<D: I2CDevice + ?Sized>(&self, dev: &mut D, mask: T) -> Result<(), D::Error> {
+        self.modify(dev, |value| value & !mask)
+    }
+}
+
+// not derived, which would require T to implement these traits as well
+impl<T> Clone for Register<T> {
+    fn clone(&self) -> Register<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Register<T> {}
+
+impl<T> fmt::Debug for Register<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Register(0x{:02x})", self.address)
+    }
+}
+
+/// Declare the registers of a device, with functions to read and write them
+///
+/// Each register gets a constant with its address, and the accessor
+/// functions named after `read:` and `write:`, generic over any
+/// [`I2CDevice`](core/trait.I2CDevice.html).  The width of the register,
+/// `u8` or `u16`, selects the SMBus method used.
+///
+#[cfg_attr(feature = "std", doc = "```rust")]
+#[cfg_attr(not(feature = "std"), doc = "```ignore")]
+/// #[macro_use]
+/// extern crate i2cdev;
+///
+/// i2cmap! {
+///     /// Registers of the ADXL345 accelerometer
+///     pub mod adxl345_regs {
+///         DEVID = 0x00: u8, read: read_devid;
+///         THRESH_TAP = 0x1D: u8, read: read_thresh_tap, write: write_thresh_tap;
+///     }
+/// }
+///
+/// # fn main() {
+/// use i2cdev::mock::MockI2CDevice;
+///
+/// let mut dev = MockI2CDevice::new();
+/// adxl345_regs::write_thresh_tap(&mut dev, 0x30).unwrap();
+/// assert_eq!(adxl345_regs::read_thresh_tap(&mut dev).unwrap(), 0x30);
+/// assert_eq!(adxl345_regs::THRESH_TAP, 0x1D);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! i2cmap {
+    (
+        $(#[$attr:meta])*
+        $vis:vis mod $name:ident {
+            $(
+                $(#[$reg_attr:meta])*
+                $reg:ident = $addr:literal : $ty:ty
+                $(, read: $read:ident)?
+                $(, write: $write:ident)?
+                ;
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[allow(dead_code)]
+        $vis mod $name {
+            $(
+                $(#[$reg_attr])*
+                pub const $reg: u8 = $addr;
+
+                $(
+                    /// Read the register
+                    pub fn $read<D: $crate::core::I2CDevice + ?Sized>(
+                        dev: &mut D,
+                    ) -> ::core::result::Result<$ty, D::Error> {
+                        <$ty as $crate::regmap::RegisterValue>::read_register(dev, $reg)
+                    }
+                )?
+
+                $(
+                    /// Write the register
+                    pub fn $write<D: $crate::core::I2CDevice + ?Sized>(
+                        dev: &mut D,
+                        value: $ty,
+                    ) -> ::core::result::Result<(), D::Error> {
+                        <$ty as $crate::regmap::RegisterValue>::write_register(dev, $reg, value)
+                    }
+                )?
+            )*
+        }
+    };
+}
+
+/// An integer field of a [`FromRegisters`] struct, decoded from
+/// `SIZE` consecutive registers in either byte order
+pub trait RegisterField: Sized {
+    /// The number of registers, i.e. bytes, the field takes
+    const SIZE: usize;
+
+    /// Decode the field from `SIZE` bytes, most significant byte first
+    fn be(bytes: &[u8]) -> Self;
+
+    /// Decode the field from `SIZE` bytes, least significant byte first
+    fn le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_register_field {
+    ($($ty:ty),*) => {
+        $(
+            impl RegisterField for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                fn be(bytes: &[u8]) -> $ty {
+                    let mut buf = [0; core::mem::size_of::<$ty>()];
+                    buf.copy_from_slice(bytes);
+                    <$ty>::from_be_bytes(buf)
+                }
+
+                fn le(bytes: &[u8]) -> $ty {
+                    let mut buf = [0; core::mem::size_of::<$ty>()];
+                    buf.copy_from_slice(bytes);
+                    <$ty>::from_le_bytes(buf)
+                }
+            }
+        )*
+    };
+}
+
+impl_register_field!(u8, i8, u16, i16, u32, i32);
+
+/// A struct decoded from a block of consecutive registers, such as the
+/// calibration coefficients of a sensor
+///
+/// Implement it with [`from_registers!`](../macro.from_registers.html)
+/// rather than by hand.
+pub trait FromRegisters: Sized {
+    /// The first register of the block
+    const START: u8;
+
+    /// The number of registers in the block
+    const LEN: usize;
+
+    /// Decode the struct from the contents of the block, `LEN` bytes
+    fn from_registers(bytes: &[u8]) -> Self;
+
+    /// Read the block from `dev` and decode it
+    ///
+    /// The block is read by writing `START` and then reading `LEN` bytes,
+    /// as separate transactions, which relies on the device incrementing
+    /// its register pointer as it sends each byte.
+    ///
+    /// Panics if `LEN` is above 256; `from_registers!` rejects such blocks
+    /// at compile time.
+    fn read_registers<D: I2CDevice + ?Sized>(dev: &mut D) -> Result<Self, D::Error> {
+        // a block cannot extend past the 256 registers of a one byte address
+        let mut buf = [0; 0x100];
+        let bytes = &mut buf[..Self::LEN];
+        dev.write(&[Self::START])?;
+        dev.read(bytes)?;
+        Ok(Self::from_registers(bytes))
+    }
+}
+
+/// Declare a struct decoded from a block of consecutive registers
+///
+/// The fields are laid out in the block in their order of declaration,
+/// starting at register `at`, each taking as many registers as its type
+/// has bytes.  `be` before the type decodes the field most significant
+/// byte first and `le` least significant byte first.  Fields may be `u8`,
+/// `i8`, `u16`, `i16`, `u32` or `i32`, or any type implementing
+/// [`RegisterField`](regmap/trait.RegisterField.html).
+///
+/// Only whole-byte fields are supported: there is no syntax for a bit
+/// width, so values packed into part of a register are declared as a `u8`
+/// field and masked by the caller.  The block can be at most 256 registers
+/// long, which is checked at compile time:
+///
+/// ```rust,compile_fail
+/// # #[macro_use]
+/// # extern crate i2cdev;
+/// # use i2cdev::regmap::RegisterField;
+/// # struct Block;
+/// # impl RegisterField for Block {
+/// #     const SIZE: usize = 0x101;
+/// #     fn be(_: &[u8]) -> Block { Block }
+/// #     fn le(_: &[u8]) -> Block { Block }
+/// # }
+/// from_registers! {
+///     struct TooLong at 0x00 {
+///         block: be Block,
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The struct implements [`FromRegisters`](regmap/trait.FromRegisters.html),
+/// so it is read with `read_registers`:
+///
+#[cfg_attr(feature = "std", doc = "```rust")]
+#[cfg_attr(not(feature = "std"), doc = "```ignore")]
+/// #[macro_use]
+/// extern crate i2cdev;
+///
+/// from_registers! {
+///     /// Trimming values of the MPL115A2 barometer
+///     #[derive(Debug)]
+///     pub struct Coefficients at 0x04 {
+///         pub a0: be i16,
+///         pub b1: be i16,
+///         pub b2: be i16,
+///         pub c12: be i16,
+///     }
+/// }
+///
+/// # fn main() {
+/// use i2cdev::mock::MockI2CDevice;
+/// use i2cdev::regmap::FromRegisters;
+///
+/// let mut dev = MockI2CDevice::new();
+/// dev.regmap.write_regs(0x04, &[0x3E, 0xCE, 0xB3, 0xF9, 0xC5, 0x17, 0x33, 0xC8]);
+/// let coefficients = Coefficients::read_registers(&mut dev).unwrap();
+/// assert_eq!(coefficients.a0, 0x3ECE);
+/// assert_eq!(coefficients.c12, 0x33C8);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! from_registers {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident at $start:literal {
+            $(
+                $(#[$field_attr:meta])*
+                $field_vis:vis $field:ident : $endian:ident $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::regmap::FromRegisters for $name {
+            const START: u8 = $start;
+            const LEN: usize = 0 $(+ <$ty as $crate::regmap::RegisterField>::SIZE)*;
+
+            #[allow(unused_assignments)]
+            fn from_registers(bytes: &[u8]) -> $name {
+                let mut offset = 0;
+                $(
+                    let size = <$ty as $crate::regmap::RegisterField>::SIZE;
+                    let $field = <$ty as $crate::regmap::RegisterField>::$endian(
+                        &bytes[offset..offset + size],
+                    );
+                    offset += size;
+                )*
+                $name { $($field),* }
+            }
+        }
+
+        const _: () = assert!(
+            <$name as $crate::regmap::FromRegisters>::LEN <= 0x100,
+            "a register block cannot be longer than 256 registers"
+        );
+    };
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{FromRegisters, Register};
+    use crate::core::I2CDevice;
+    use crate::mock::MockI2CDevice;
+
+    i2cmap! {
+        mod regs {
+            ID = 0x0F: u8, read: read_id;
+            CONFIG = 0x10: u8, read: read_config, write: write_config;
+            THRESHOLD = 0x12: u16, read: read_threshold, write: write_threshold;
+            COMMAND = 0x20: u8, write: write_command;
+        }
+    }
+
+    #[test]
+    fn test_register_accessors() {
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(0x0F, &[0x33]);
+        assert_eq!(regs::read_id(&mut dev).unwrap(), 0x33);
+
+        regs::write_config(&mut dev, 0xA5).unwrap();
+        assert_eq!(regs::read_config(&mut dev).unwrap(), 0xA5);
+
+        regs::write_threshold(&mut dev, 0x1234).unwrap();
+        assert_eq!(regs::read_threshold(&mut dev).unwrap(), 0x1234);
+        let mut buf = [0; 2];
+        dev.write(&[0x12]).unwrap();
+        dev.read(&mut buf).unwrap();
+        assert_eq!(buf, [0x34, 0x12]);
+
+        regs::write_command(&mut dev, 0x01).unwrap();
+        assert_eq!(regs::COMMAND, 0x20);
+    }
+
+    #[test]
+    fn test_register_modify_preserves_bits() {
+        let reg: Register<u8> = Register::new(0x20);
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(0x20, &[0b1010_0101]);
+
+        reg.set_bits(&mut dev, 0b0000_0010).unwrap();
+        assert_eq!(reg.read(&mut dev).unwrap(), 0b1010_0111);
+        reg.clear_bits(&mut dev, 0b1000_0001).unwrap();
+        assert_eq!(reg.read(&mut dev).unwrap(), 0b0010_0110);
+        reg.modify(&mut dev, |value| (value & 0xF0) | 0x09).unwrap();
+        assert_eq!(reg.read(&mut dev).unwrap(), 0b0010_1001);
+        // the neighbouring registers are untouched
+        assert_eq!(dev.smbus_read_byte_data(0x1F).unwrap(), 0);
+        assert_eq!(dev.smbus_read_byte_data(0x21).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_register_word_bits() {
+        let reg: Register<u16> = Register::new(0x10);
+        let mut dev = MockI2CDevice::new();
+        reg.write(&mut dev, 0x8001).unwrap();
+        reg.set_bits(&mut dev, 0x0100).unwrap();
+        reg.clear_bits(&mut dev, 0x8000).unwrap();
+        assert_eq!(reg.read(&mut dev).unwrap(), 0x0101);
+        assert_eq!(format!("{:?}", reg), "Register(0x10)");
+    }
+
+    from_registers! {
+        /// Calibration coefficients of the BMP180 barometer
+        #[derive(Debug, PartialEq)]
+        struct Bmp180Calibration at 0xAA {
+            ac1: be i16,
+            ac2: be i16,
+            ac3: be i16,
+            ac4: be u16,
+            ac5: be u16,
+            ac6: be u16,
+            b1: be i16,
+            b2: be i16,
+            mb: be i16,
+            mc: be i16,
+            md: be i16,
+        }
+    }
+
+    from_registers! {
+        struct Mixed at 0x10 {
+            id: le u8,
+            threshold: le u16,
+            offset: le i32,
+            status: be i8,
+        }
+    }
+
+    #[test]
+    fn test_from_registers_bmp180() {
+        // the example coefficients of the BMP180 datasheet
+        let expected = Bmp180Calibration {
+            ac1: 408,
+            ac2: -72,
+            ac3: -14383,
+            ac4: 32741,
+            ac5: 32757,
+            ac6: 23153,
+            b1: 6190,
+            b2: 4,
+            mb: -32768,
+            mc: -8711,
+            md: 2868,
+        };
+        let mut dev = MockI2CDevice::new();
+        let words = [
+            408i16 as u16,
+            -72i16 as u16,
+            -14383i16 as u16,
+            32741,
+            32757,
+            23153,
+            6190,
+            4,
+            -32768i16 as u16,
+            -8711i16 as u16,
+            2868,
+        ];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        dev.regmap.write_regs(0xAA, &bytes);
+
+        assert_eq!(Bmp180Calibration::LEN, 22);
+        assert_eq!(
+            Bmp180Calibration::read_registers(&mut dev).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_from_registers_byte_order() {
+        assert_eq!(Mixed::LEN, 8);
+        let mixed = Mixed::from_registers(&[0x42, 0x34, 0x12, 0xFE, 0xFF, 0xFF, 0xFF, 0x80]);
+        assert_eq!(mixed.id, 0x42);
+        assert_eq!(mixed.threshold, 0x1234);
+        assert_eq!(mixed.offset, -2);
+        assert_eq!(mixed.status, -128);
+    }
+}
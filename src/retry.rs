@@ -0,0 +1,351 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! On a bus with several masters, a transfer fails with `EAGAIN` when
+//! another master wins arbitration, and simply issuing it again usually
+//! succeeds.  [`RetryingI2CDevice`] does so for every operation of the
+//! device it wraps.
+//!
+//! The kernel can retry such transfers itself, see
+//! `LinuxI2CBus::set_retries`, but that setting applies to every user of
+//! the adapter and not all adapter drivers honour it.
+
+use crate::core::{I2CDevice, I2CDeviceExt, I2CTransfer, SMBUS_BLOCK_MAX};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::linux::{LinuxI2CError, LinuxI2CErrorKind};
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// Errors which may go away when the operation is issued again
+pub trait TransientError {
+    /// Whether the operation failing with this error may succeed if retried
+    fn is_transient(&self) -> bool;
+}
+
+/// Lost arbitration, classified as [`LinuxI2CErrorKind::Arbitration`], is
+/// transient
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl TransientError for LinuxI2CError {
+    fn is_transient(&self) -> bool {
+        self.kind() == LinuxI2CErrorKind::Arbitration
+    }
+}
+
+/// `WouldBlock`, the kind of `EAGAIN`, is transient
+impl TransientError for io::Error {
+    fn is_transient(&self) -> bool {
+        self.kind() == io::ErrorKind::WouldBlock
+    }
+}
+
+/// An I2C device issuing its operations again when they fail with a
+/// [transient](TransientError) error
+///
+/// Each operation is tried up to `max_attempts` times in all, sleeping for
+/// `backoff` between attempts; the error of the last attempt is returned
+/// if none succeeds.  Other errors are returned straight away.
+///
+/// Operations made of several bus transactions, such as the default
+/// `smbus_read_byte_data` writing the register and then reading it, retry
+/// each transaction on its own.  Use [`retry`](#method.retry) to retry a
+/// whole sequence.
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use i2cdev::core::I2CDevice;
+/// # use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+/// # use i2cdev::retry::RetryingI2CDevice;
+/// # fn main() -> Result<(), LinuxI2CError> {
+/// let dev = LinuxI2CDevice::new("/dev/i2c-1", 0x48)?;
+/// let mut dev = RetryingI2CDevice::new(dev, 3, Duration::from_millis(1));
+/// let temperature = dev.smbus_read_word_data_be(0x00)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RetryingI2CDevice<T> {
+    device: T,
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl<T> RetryingI2CDevice<T> {
+    /// Wrap `device`, trying each operation up to `max_attempts` times
+    ///
+    /// An operation is always tried at least once, even if `max_attempts`
+    /// is zero.
+    pub fn new(device: T, max_attempts: u32, backoff: Duration) -> RetryingI2CDevice<T> {
+        RetryingI2CDevice {
+            device,
+            max_attempts,
+            backoff,
+        }
+    }
+
+    /// Borrow the wrapped device
+    pub fn get_ref(&self) -> &T {
+        &self.device
+    }
+
+    /// Mutably borrow the wrapped device, to use it without retries
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.device
+    }
+
+    /// Unwrap the device
+    pub fn into_inner(self) -> T {
+        self.device
+    }
+
+    /// Run `f` on the wrapped device, running it again while it fails with
+    /// a transient error and attempts are left
+    pub fn retry<R, E, F>(&mut self, mut f: F) -> Result<R, E>
+    where
+        E: TransientError,
+        F: FnMut(&mut T) -> Result<R, E>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f(&mut self.device) {
+                Err(ref e) if e.is_transient() && attempt < self.max_attempts => {
+                    attempt += 1;
+                    thread::sleep(self.backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<T> I2CDevice for RetryingI2CDevice<T>
+where
+    T: I2CDevice,
+    T::Error: TransientError,
+{
+    type Error = T::Error;
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), T::Error> {
+        self.retry(|dev| dev.read(data))
+    }
+
+    fn read_len(&mut self, data: &mut [u8]) -> Result<usize, T::Error> {
+        self.retry(|dev| dev.read_len(data))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), T::Error> {
+        self.retry(|dev| dev.write(data))
+    }
+
+    fn flush(&mut self) -> Result<(), T::Error> {
+        self.retry(|dev| dev.flush())
+    }
+
+    fn smbus_write_quick(&mut self, bit: bool) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_quick(bit))
+    }
+
+    fn smbus_read_byte(&mut self) -> Result<u8, T::Error> {
+        self.retry(|dev| dev.smbus_read_byte())
+    }
+
+    fn smbus_write_byte(&mut self, value: u8) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_byte(value))
+    }
+
+    fn smbus_read_byte_data(&mut self, register: u8) -> Result<u8, T::Error> {
+        self.retry(|dev| dev.smbus_read_byte_data(register))
+    }
+
+    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_byte_data(register, value))
+    }
+
+    fn smbus_read_word_data(&mut self, register: u8) -> Result<u16, T::Error> {
+        self.retry(|dev| dev.smbus_read_word_data(register))
+    }
+
+    fn smbus_write_word_data(&mut self, register: u8, value: u16) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_word_data(register, value))
+    }
+
+    fn smbus_process_word(&mut self, register: u8, value: u16) -> Result<u16, T::Error> {
+        self.retry(|dev| dev.smbus_process_word(register, value))
+    }
+
+    fn smbus_read_block_data_into(
+        &mut self,
+        register: u8,
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize, T::Error> {
+        self.retry(|dev| dev.smbus_read_block_data_into(register, buf))
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_block_data(register, values))
+    }
+
+    fn smbus_write_i2c_block_data(&mut self, register: u8, values: &[u8]) -> Result<(), T::Error> {
+        self.retry(|dev| dev.smbus_write_i2c_block_data(register, values))
+    }
+}
+
+impl<T> I2CDeviceExt for RetryingI2CDevice<T>
+where
+    T: I2CDeviceExt,
+    T::Error: TransientError,
+{
+    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, T::Error> {
+        self.retry(|dev| dev.smbus_read_block_data(register))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> Result<Vec<u8>, T::Error> {
+        self.retry(|dev| dev.smbus_read_i2c_block_data(register, len))
+    }
+
+    fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> Result<Vec<u8>, T::Error> {
+        self.retry(|dev| dev.smbus_process_block(register, values))
+    }
+}
+
+/// The whole transfer is issued again, as the messages before the failing
+/// one may have been executed already
+impl<'a, T> I2CTransfer<'a> for RetryingI2CDevice<T>
+where
+    T: I2CTransfer<'a>,
+    T::Error: TransientError,
+{
+    type Error = T::Error;
+    type Message = T::Message;
+
+    fn transfer(&mut self, msgs: &mut [Self::Message]) -> Result<u32, T::Error> {
+        self.retry(|dev| dev.transfer(msgs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockI2CDevice;
+
+    /// Mock device failing its first writes with `error`
+    struct FlakyDevice {
+        dev: MockI2CDevice,
+        failures: u32,
+        error: io::ErrorKind,
+        writes: u32,
+    }
+
+    impl FlakyDevice {
+        fn new(failures: u32, error: io::ErrorKind) -> FlakyDevice {
+            FlakyDevice {
+                dev: MockI2CDevice::new(),
+                failures,
+                error,
+                writes: 0,
+            }
+        }
+    }
+
+    impl I2CDevice for FlakyDevice {
+        type Error = io::Error;
+
+        fn read(&mut self, data: &mut [u8]) -> io::Result<()> {
+            self.dev.read(data)
+        }
+
+        fn write(&mut self, data: &[u8]) -> io::Result<()> {
+            self.writes += 1;
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(self.error.into());
+            }
+            self.dev.write(data)
+        }
+
+        fn smbus_write_quick(&mut self, bit: bool) -> io::Result<()> {
+            self.dev.smbus_write_quick(bit)
+        }
+
+        fn smbus_read_block_data_into(
+            &mut self,
+            register: u8,
+            buf: &mut [u8; SMBUS_BLOCK_MAX],
+        ) -> io::Result<usize> {
+            self.dev.smbus_read_block_data_into(register, buf)
+        }
+
+        fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+            self.dev.smbus_write_block_data(register, values)
+        }
+
+        fn smbus_write_i2c_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+            self.dev.smbus_write_i2c_block_data(register, values)
+        }
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        let flaky = FlakyDevice::new(2, io::ErrorKind::WouldBlock);
+        let mut dev = RetryingI2CDevice::new(flaky, 3, Duration::from_millis(0));
+        dev.smbus_write_byte_data(0x10, 0xAB).unwrap();
+        assert_eq!(dev.get_ref().writes, 3);
+        assert_eq!(dev.smbus_read_byte_data(0x10).unwrap(), 0xAB);
+    }
+
+    #[test]
+    fn test_retry_block_into_slice() {
+        let mut flaky = FlakyDevice::new(0, io::ErrorKind::WouldBlock);
+        flaky
+            .dev
+            .smbus_write_block_data(0x20, &[0x01, 0x02])
+            .unwrap();
+        let mut dev = RetryingI2CDevice::new(flaky, 2, Duration::from_millis(0));
+        let mut buf = [0; 8];
+        assert_eq!(
+            dev.smbus_read_block_data_into_slice(0x20, &mut buf)
+                .unwrap(),
+            2
+        );
+        assert_eq!(buf[..2], [0x01, 0x02]);
+        assert!(dev
+            .smbus_read_block_data_into_slice(0x20, &mut buf[..1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let flaky = FlakyDevice::new(3, io::ErrorKind::WouldBlock);
+        let mut dev = RetryingI2CDevice::new(flaky, 3, Duration::from_millis(0));
+        let e = dev.smbus_write_byte_data(0x10, 0xAB).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(dev.into_inner().writes, 3);
+    }
+
+    #[test]
+    fn test_retry_not_transient() {
+        let flaky = FlakyDevice::new(1, io::ErrorKind::InvalidInput);
+        let mut dev = RetryingI2CDevice::new(flaky, 3, Duration::from_millis(0));
+        let e = dev.write(&[0x10]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(dev.get_ref().writes, 1);
+
+        let flaky = FlakyDevice::new(1, io::ErrorKind::WouldBlock);
+        let mut dev = RetryingI2CDevice::new(flaky, 0, Duration::from_millis(0));
+        assert!(dev.write(&[0x10]).is_err());
+        assert_eq!(dev.get_ref().writes, 1);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn test_linux_error_transient() {
+        assert!(LinuxI2CError::Errno(libc::EAGAIN).is_transient());
+        assert!(!LinuxI2CError::Errno(libc::ENXIO).is_transient());
+        assert!(!LinuxI2CError::Errno(libc::EIO).is_transient());
+    }
+}
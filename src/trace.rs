@@ -0,0 +1,67 @@
+// Copyright 2024, The Embedded Linux Team <embedded-linux@teams.rust-embedded.org>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/license/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option.  This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Tracing of bus traffic with the `log` feature
+
+use core::fmt;
+
+/// Log bus traffic at trace level, or do nothing without the `log` feature
+#[cfg(feature = "log")]
+macro_rules! trace_traffic {
+    ($($arg:tt)*) => {
+        log::trace!(target: "i2cdev", $($arg)*)
+    };
+}
+
+// the arguments are still type checked, but never formatted
+#[cfg(not(feature = "log"))]
+macro_rules! trace_traffic {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+/// Bytes at most shown by `HexDump`
+const HEX_DUMP_MAX: usize = 32;
+
+/// Formats a buffer as hex bytes, truncated for large buffers
+pub(crate) struct HexDump<'a>(pub &'a [u8]);
+
+impl<'a> fmt::Display for HexDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, byte) in self.0.iter().take(HEX_DUMP_MAX).enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        if self.0.len() > HEX_DUMP_MAX {
+            write!(f, " ... ({} bytes)", self.0.len())?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!(HexDump(&[]).to_string(), "[]");
+        assert_eq!(HexDump(&[0x01, 0xAB]).to_string(), "[01 ab]");
+        let long = [0xFF; 40];
+        let dump = HexDump(&long).to_string();
+        assert!(dump.starts_with("[ff ff"));
+        assert!(dump.ends_with("ff ... (40 bytes)]"));
+        assert_eq!(dump.matches("ff").count(), HEX_DUMP_MAX);
+    }
+}